@@ -29,8 +29,10 @@ pub struct ExtendedKey {
 
 impl ExtendedKey {
     /// Size of an extended key when deserialized into bytes from Base58.
-    // pub const BYTE_SIZE: usize = 78;
-    pub const BYTE_SIZE: usize = 77;
+    ///
+    /// The 32-byte ed25519 key is padded with a leading zero byte, as in
+    /// SLIP-10, to keep the standard BIP32 layout and its `xpub`/`xprv` text.
+    pub const BYTE_SIZE: usize = 78;
 
     /// Maximum size of a Base58Check-encoded extended key in bytes.
     ///
@@ -49,8 +51,7 @@ impl ExtendedKey {
         bytes[5..9].copy_from_slice(&self.attrs.parent_fingerprint);
         bytes[9..13].copy_from_slice(&self.attrs.child_number.to_bytes());
         bytes[13..45].copy_from_slice(&self.attrs.chain_code);
-        // bytes[45..78].copy_from_slice(&self.key_bytes);
-        bytes[45..77].copy_from_slice(&self.key_bytes);
+        bytes[46..78].copy_from_slice(&self.key_bytes);
 
         let base58_len = bs58::encode(&bytes).with_check().onto(buffer.as_mut())?;
         bytes.zeroize();
@@ -89,8 +90,10 @@ impl FromStr for ExtendedKey {
         let parent_fingerprint = bytes[5..9].try_into()?;
         let child_number = ChildNumber::from_bytes(bytes[9..13].try_into()?);
         let chain_code = bytes[13..45].try_into()?;
-        // let key_bytes = bytes[45..78].try_into()?;
-        let key_bytes = bytes[45..77].try_into()?;
+        if bytes[45] != 0 {
+            return Err(Error::Decode);
+        }
+        let key_bytes = bytes[46..78].try_into()?;
         bytes.zeroize();
 
         let attrs = ExtendedKeyAttrs {
@@ -0,0 +1,115 @@
+use libexception::*;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// How a `Messenger` turns the objects it carries into bytes on the wire.
+/// Every party of a session must use the same one.
+pub trait WireCodec: Clone + Send + Sync {
+    fn encode<T: Serialize>(&self, obj: &T) -> Outcome<Vec<u8>>;
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Outcome<T>;
+}
+
+/// Python pickle (protocol 3), the format of `MemoryTransport` and of the
+/// showcase sesman by default. Only Rust and Python parties read it.
+#[cfg(feature = "pickle")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PickleCodec;
+
+#[cfg(feature = "pickle")]
+impl WireCodec for PickleCodec {
+    fn encode<T: Serialize>(&self, obj: &T) -> Outcome<Vec<u8>> {
+        serde_pickle::to_vec(obj, Default::default()).catch("WireCodecException", "pickle")
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Outcome<T> {
+        serde_pickle::from_slice(bytes, Default::default()).catch("WireCodecException", "pickle")
+    }
+}
+
+/// Deterministic CBOR (RFC 8949, section 4.2), for parties in other languages.
+///
+/// The layout is serde's data model, so for the types of `mpc_algo`:
+/// - a struct is a map from its field names, as text, to its fields, and
+///   an `Option` field is either its value or null;
+/// - a unit enum variant, such as `AeadScheme::AesGcm`, is its name as text;
+/// - `MpcAddr` is its `u32`, and a `HashMap` a map, e.g. keyed by `MpcAddr`;
+/// - a point or a scalar is an array of its 32 canonical bytes, compressed
+///   Edwards y or little-endian, each an unsigned integer;
+/// - a `Vec<u8>`, such as `AEAD::ciphertext`, is an array of unsigned integers.
+///
+/// Every map is sorted by the bytes of its encoded keys, which puts shorter
+/// field names first, so equal values always encode to equal bytes
+/// whatever the iteration order of a `HashMap`.
+#[cfg(feature = "cbor")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CborCodec;
+
+#[cfg(feature = "cbor")]
+impl WireCodec for CborCodec {
+    fn encode<T: Serialize>(&self, obj: &T) -> Outcome<Vec<u8>> {
+        use ciborium::value::Value;
+        let mut value = Value::serialized(obj).catch("WireCodecException", "cbor")?;
+        canonicalize(&mut value)?;
+        let mut buf = Vec::new();
+        ciborium::ser::into_writer(&value, &mut buf).catch("WireCodecException", "cbor")?;
+        Ok(buf)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Outcome<T> {
+        ciborium::de::from_reader(bytes).catch("WireCodecException", "cbor")
+    }
+}
+
+/// Sort every map of `value`, recursively, by the encoding of its keys.
+#[cfg(feature = "cbor")]
+fn canonicalize(value: &mut ciborium::value::Value) -> Outcome<()> {
+    use ciborium::value::Value;
+    match value {
+        Value::Array(items) => {
+            for item in items.iter_mut() {
+                canonicalize(item)?;
+            }
+        }
+        Value::Map(entries) => {
+            let mut keyed = Vec::with_capacity(entries.len());
+            for (mut k, mut v) in entries.drain(..) {
+                canonicalize(&mut k)?;
+                canonicalize(&mut v)?;
+                let mut key_bytes = Vec::new();
+                ciborium::ser::into_writer(&k, &mut key_bytes)
+                    .catch("WireCodecException", "cbor")?;
+                keyed.push((key_bytes, k, v));
+            }
+            keyed.sort_by(|a, b| a.0.cmp(&b.0));
+            entries.extend(keyed.into_iter().map(|(_, k, v)| (k, v)));
+        }
+        Value::Tag(_, inner) => canonicalize(inner)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "cbor"))]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn cbor_maps_are_canonical() {
+        let small: HashMap<u32, &str> = (0..4).map(|i| (i, "x")).collect();
+        let mut large: HashMap<u32, &str> = (0..64).map(|i| (i, "x")).collect();
+        large.retain(|k, _| *k < 4);
+        let (a, b) = (
+            CborCodec.encode(&small).unwrap(),
+            CborCodec.encode(&large).unwrap(),
+        );
+        assert_eq!(a, b);
+        assert_eq!(hex(&a), "a4006178016178026178036178");
+        let back: HashMap<u32, String> = CborCodec.decode(&a).unwrap();
+        assert_eq!(back.len(), 4);
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
@@ -10,4 +10,12 @@ pub use tuple_id::*;
 mod multi_shard;
 pub use multi_shard::*;
 
+mod codec;
+pub use codec::*;
+
+#[cfg(feature = "memory-transport")]
+mod memory_transport;
+#[cfg(feature = "memory-transport")]
+pub use memory_transport::*;
+
 pub use async_trait::async_trait;
@@ -0,0 +1,271 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use libexception::*;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::Notify;
+
+use crate::*;
+
+type Mailbox = HashMap<(String, MpcAddr, MpcAddr), Vec<u8>>;
+
+/// In-process `Messenger` for running several parties in one process,
+/// e.g. in tests, without a session manager.
+///
+/// Like the showcase sesman, messages are kept in a persistent key-value
+/// mailbox, so a message may be sent before or after it is waited for.
+/// Clones share the same mailbox.
+///
+/// A sender gets one message per topic and receiver. Sending the same one
+/// again is harmless, e.g. on retry, but sending another throws
+/// `DuplicateMessage` naming the sender, so a peer cannot flood a round or
+/// swap its message after some parties read it.
+#[derive(Clone, Default)]
+pub struct MemoryTransport {
+    db: Arc<Mutex<Mailbox>>,
+    arrival: Arc<Notify>,
+    max_message_size: Option<usize>,
+    flood: Arc<FloodGuard>,
+}
+
+impl MemoryTransport {
+    /// Another view of the same mailbox, refusing to receive larger messages.
+    pub fn with_max_message_size(&self, limit: usize) -> Self {
+        Self {
+            max_message_size: Some(limit),
+            ..self.clone()
+        }
+    }
+
+    /// Another view of the same mailbox, for a single receiver, throwing
+    /// `FloodDetected` once it consumed more of a round than `limit`.
+    pub fn with_flood_limit(&self, limit: FloodLimit) -> Self {
+        Self {
+            flood: Arc::new(FloodGuard::new(limit)),
+            ..self.clone()
+        }
+    }
+}
+
+#[async_trait]
+impl Messenger for MemoryTransport {
+    type E = Box<Exception>;
+
+    async fn send<T>(&self, topic: &str, src: MpcAddr, dst: MpcAddr, obj: &T) -> Outcome<()>
+    where
+        T: Serialize + DeserializeOwned + Send + Sync,
+    {
+        let obj = serde_pickle::to_vec(obj, Default::default()).catch_()?;
+        let k = (topic.to_string(), src, dst);
+        {
+            let mut db = self.db.lock().unwrap();
+            if let Some(prev) = db.get(&k) {
+                assert_throw!(
+                    same_message(prev, &obj),
+                    "DuplicateMessage",
+                    format!(
+                        "{} already sent another message to {} at round \"{}\"",
+                        src, dst, topic
+                    )
+                );
+            }
+            db.insert(k, obj);
+        }
+        self.arrival.notify_waiters();
+        Ok(())
+    }
+
+    async fn receive<T>(&self, topic: &str, src: MpcAddr, dst: MpcAddr) -> Outcome<T>
+    where
+        T: Serialize + DeserializeOwned + Send + Sync,
+    {
+        let k = (topic.to_string(), src, dst);
+        loop {
+            // Register before looking, so an arrival in between is not missed.
+            let arrival = self.arrival.notified();
+            tokio::pin!(arrival);
+            arrival.as_mut().enable();
+
+            let obj = {
+                let db = self.db.lock().unwrap();
+                match db.get(&k) {
+                    Some(obj) => {
+                        let limit = self.max_message_size.unwrap_or(DEFAULT_MAX_MESSAGE_SIZE);
+                        check_message_size(obj.len(), limit, topic, src)?;
+                        Some(obj.clone())
+                    }
+                    None => None,
+                }
+            };
+            if let Some(obj) = obj {
+                self.flood.consume(topic, src, obj.len())?;
+                return serde_pickle::from_slice(&obj, Default::default()).catch_();
+            }
+            arrival.await;
+        }
+    }
+
+    async fn scatter<T>(
+        &self,
+        topic: &str,
+        src: MpcAddr,
+        dsts: &HashSet<MpcAddr>,
+        obj: &T,
+    ) -> Outcome<()>
+    where
+        T: Serialize + DeserializeOwned + Send + Sync,
+    {
+        for dst in dsts.iter() {
+            self.send(topic, src, *dst, obj).await?;
+        }
+        Ok(())
+    }
+
+    async fn gather<T>(
+        &self,
+        topic: &str,
+        srcs: &HashSet<MpcAddr>,
+        dst: MpcAddr,
+    ) -> Outcome<HashMap<MpcAddr, T>>
+    where
+        T: Serialize + DeserializeOwned + Send + Sync,
+    {
+        let mut ret = HashMap::new();
+        for src in srcs.iter() {
+            ret.insert(*src, self.receive(topic, *src, dst).await?);
+        }
+        Ok(ret)
+    }
+}
+
+/// Whether two pickles decode to the same value. Their bytes may differ,
+/// e.g. by the iteration order of a `HashMap`.
+fn same_message(a: &[u8], b: &[u8]) -> bool {
+    if a == b {
+        return true;
+    }
+    let decode = |x: &[u8]| serde_pickle::value_from_slice(x, Default::default()).ok();
+    match (decode(a), decode(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn round_beyond_the_flood_limit_is_refused() {
+        let transport = MemoryTransport::default();
+        let me = MpcAddr::new(1, 1);
+        let srcs: HashSet<MpcAddr> = (2..=4).map(|i| MpcAddr::new(1, i)).collect();
+        for src in srcs.iter() {
+            transport
+                .send("nonce_com", *src, me, &vec![0u8; 100])
+                .await
+                .unwrap();
+            transport.send("sign_resp", *src, me, &0u8).await.unwrap();
+        }
+
+        let limit = FloodLimit {
+            max_messages: 5,
+            max_bytes: 1 << 20,
+        };
+        let guarded = transport.with_flood_limit(limit);
+        let _: HashMap<MpcAddr, Vec<u8>> = guarded.gather("nonce_com", &srcs, me).await.unwrap();
+        let err = guarded
+            .gather::<Vec<u8>>("nonce_com", &srcs, me)
+            .await
+            .unwrap_err();
+        assert_eq!(err.get_name(), "FloodDetected");
+        assert!(err.get_context().unwrap().contains("6 messages"));
+        // Other rounds and other receivers have budgets of their own.
+        let _: HashMap<MpcAddr, u8> = guarded.gather("sign_resp", &srcs, me).await.unwrap();
+        let _: HashMap<MpcAddr, Vec<u8>> = transport.gather("nonce_com", &srcs, me).await.unwrap();
+
+        let tight = transport.with_flood_limit(FloodLimit {
+            max_bytes: 250,
+            ..limit
+        });
+        let err = tight
+            .gather::<Vec<u8>>("nonce_com", &srcs, me)
+            .await
+            .unwrap_err();
+        assert_eq!(err.get_name(), "FloodDetected");
+    }
+
+    #[tokio::test]
+    async fn message_sent_later_is_received() {
+        let transport = MemoryTransport::default();
+        let (src, dst) = (MpcAddr::new(1, 1), MpcAddr::new(1, 2));
+        let receiver = {
+            let transport = transport.clone();
+            tokio::spawn(async move { transport.receive::<u32>("topic", src, dst).await })
+        };
+        tokio::task::yield_now().await;
+        transport.send("topic", src, dst, &42u32).await.unwrap();
+        assert_eq!(receiver.await.unwrap().unwrap(), 42);
+    }
+
+    #[tokio::test]
+    async fn second_message_of_a_round_is_rejected() {
+        let transport = MemoryTransport::default();
+        let (src, dst) = (MpcAddr::new(1, 3), MpcAddr::new(1, 2));
+        let share: HashMap<u16, u32> = (0..64).map(|i| (i, i as u32)).collect();
+        transport
+            .send("aead_share", src, dst, &share)
+            .await
+            .unwrap();
+        // a retry, which may pickle the map in another order
+        let again: HashMap<u16, u32> = share.clone().into_iter().collect();
+        transport
+            .send("aead_share", src, dst, &again)
+            .await
+            .unwrap();
+
+        let mut flood = share.clone();
+        flood.insert(0, 7);
+        let err = transport
+            .send("aead_share", src, dst, &flood)
+            .await
+            .unwrap_err();
+        assert_eq!(err.get_name(), "DuplicateMessage");
+        assert!(err.get_context().unwrap().contains(&src.to_string()));
+        let got: HashMap<u16, u32> = transport.receive("aead_share", src, dst).await.unwrap();
+        assert_eq!(got, share);
+
+        // other rounds and receivers are untouched
+        transport
+            .send("aead_share", src, src, &flood)
+            .await
+            .unwrap();
+        transport.send("sign_resp", src, dst, &flood).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn oversized_message_is_rejected() {
+        let transport = MemoryTransport::default();
+        let (src, dst) = (MpcAddr::new(1, 1), MpcAddr::new(1, 2));
+        transport
+            .send("aead_share", src, dst, &vec![0u8; 4096])
+            .await
+            .unwrap();
+
+        let strict = transport.with_max_message_size(1024);
+        let err = strict
+            .receive::<Vec<u8>>("aead_share", src, dst)
+            .await
+            .unwrap_err();
+        assert_eq!(err.get_name(), "MessageTooLarge");
+        let ctx = err.get_context().unwrap();
+        assert!(
+            ctx.contains(&src.to_string()) && ctx.contains("aead_share"),
+            "{}",
+            ctx
+        );
+
+        let obj: Vec<u8> = transport.receive("aead_share", src, dst).await.unwrap();
+        assert_eq!(obj.len(), 4096);
+    }
+}
@@ -1,11 +1,79 @@
 use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
+use std::sync::Mutex;
 
 use super::MpcAddr;
 
 use async_trait::async_trait;
+use libexception::*;
 use serde::{de::DeserializeOwned, Serialize};
 
+/// Default cap on one serialized message. Protocol messages take a few KiB.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 1 << 20;
+
+/// To be called by a `Messenger` on the length of a message on `topic`
+/// from `src`, before buffering or deserializing it.
+/// Throws `MessageTooLarge` if it exceeds `limit` bytes.
+pub fn check_message_size(size: usize, limit: usize, topic: &str, src: MpcAddr) -> Outcome<()> {
+    assert_throw!(
+        size <= limit,
+        "MessageTooLarge",
+        format!(
+            "{} bytes from {} at round \"{}\" exceed the limit of {} bytes",
+            size, src, topic, limit
+        )
+    );
+    Ok(())
+}
+
+/// Cap on what a receiver consumes per round, against a relay feeding it
+/// bogus messages without end. Retries of a round count again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FloodLimit {
+    pub max_messages: usize,
+    pub max_bytes: usize,
+}
+
+/// Counts what a `Messenger` consumed per topic, to enforce a `FloodLimit`.
+/// `FloodGuard::default()` enforces none.
+#[derive(Debug, Default)]
+pub struct FloodGuard {
+    limit: Option<FloodLimit>,
+    used: Mutex<HashMap<String, (usize, usize)>>,
+}
+
+impl FloodGuard {
+    pub fn new(limit: FloodLimit) -> Self {
+        Self {
+            limit: Some(limit),
+            used: Mutex::default(),
+        }
+    }
+
+    /// To be called by a `Messenger` on each message of `size` bytes it
+    /// consumes on `topic` from `src`. Throws `FloodDetected` once the round
+    /// took more than `max_messages` messages or `max_bytes` bytes.
+    pub fn consume(&self, topic: &str, src: MpcAddr, size: usize) -> Outcome<()> {
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        let mut used = self.used.lock().unwrap();
+        let (n_messages, n_bytes) = used.entry(topic.to_string()).or_default();
+        *n_messages += 1;
+        *n_bytes += size;
+        assert_throw!(
+            *n_messages <= limit.max_messages && *n_bytes <= limit.max_bytes,
+            "FloodDetected",
+            format!(
+                "{} messages of {} bytes in all at round \"{}\", the last from {}, exceed the limit of {} messages or {} bytes",
+                n_messages, n_bytes, topic, src, limit.max_messages, limit.max_bytes
+            )
+        );
+        Ok(())
+    }
+}
+
 #[async_trait]
 pub trait Messenger {
     type E: Display + Send + Sync + 'static;
@@ -42,4 +110,11 @@ pub trait Messenger {
     ) -> Result<HashMap<MpcAddr, T>, Self::E>
     where
         T: Serialize + DeserializeOwned + Send + Sync;
+
+    /// Whether a call that failed with `err` may succeed if repeated,
+    /// e.g. after a network blip. By default every error is deemed transient;
+    /// override it to fail fast on definitive errors such as a rejection by the peer.
+    fn is_transient(&self, _err: &Self::E) -> bool {
+        true
+    }
 }
@@ -1,5 +1,6 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
     ops::{Add, Mul},
 };
 
@@ -8,7 +9,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::MpcAddr;
 
-#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct MultiShard<ScalarType, PointType>
 where
     ScalarType: Clone
@@ -24,9 +25,96 @@ where
     pub ui_pergroup: HashMap<u16, ScalarType>,
     pub xi_pergroup: HashMap<u16, ScalarType>,
     pub vss_com_grid: HashMap<u16, HashMap<MpcAddr, Vec<PointType>>>, // (group, member(poly), coef)
+    /// The keygen proof $(kG, \sigma)$ that each dealer of `vss_com_grid` knows
+    /// its secret, by group and dealer. A group has none if it was reshared
+    /// since, or dealt before layout version 2.
+    #[serde(default)]
+    pub vss_zkp_grid: HashMap<u16, HashMap<MpcAddr, (PointType, ScalarType)>>,
 
     pub ids: HashSet<MpcAddr>,
     pub aux: Option<Vec<u8>>,
+
+    /// Layout version. Blobs serialized before this field existed read as 0.
+    #[serde(default)]
+    pub version: u16,
+}
+
+impl<ScalarType, PointType> Default for MultiShard<ScalarType, PointType>
+where
+    ScalarType: Clone
+        + Default // Zero
+        + Add<Output = ScalarType>
+        + Mul<Output = ScalarType>
+        + Mul<PointType, Output = PointType>,
+    PointType: Clone
+        + Default // Identity
+        + std::ops::Add<Output = PointType>
+        + std::ops::Mul<ScalarType, Output = PointType>,
+{
+    fn default() -> Self {
+        Self {
+            ui_pergroup: HashMap::new(),
+            xi_pergroup: HashMap::new(),
+            vss_com_grid: HashMap::new(),
+            vss_zkp_grid: HashMap::new(),
+            ids: HashSet::new(),
+            aux: None,
+            version: Self::CURRENT_VERSION,
+        }
+    }
+}
+
+/// Prints the secret scalars as `"<redacted>"`, `aux` by its length and the
+/// proofs by their dealers, so a keystore can be logged or pasted in a report.
+impl<ScalarType, PointType> fmt::Debug for MultiShard<ScalarType, PointType>
+where
+    ScalarType: Clone
+        + Default // Zero
+        + Add<Output = ScalarType>
+        + Mul<Output = ScalarType>
+        + Mul<PointType, Output = PointType>,
+    PointType: Clone
+        + Default // Identity
+        + std::ops::Add<Output = PointType>
+        + std::ops::Mul<ScalarType, Output = PointType>
+        + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let redacted = |per_group: &HashMap<u16, ScalarType>| -> BTreeMap<u16, &str> {
+            per_group.keys().map(|gid| (*gid, "<redacted>")).collect()
+        };
+        let vss_com_grid: BTreeMap<&u16, BTreeMap<&MpcAddr, &Vec<PointType>>> = self
+            .vss_com_grid
+            .iter()
+            .map(|(gid, dict)| (gid, dict.iter().collect()))
+            .collect();
+        let vss_zkp_grid: BTreeMap<&u16, Vec<&MpcAddr>> = self
+            .vss_zkp_grid
+            .iter()
+            .map(|(gid, dict)| {
+                let mut dealers: Vec<&MpcAddr> = dict.keys().collect();
+                dealers.sort();
+                (gid, dealers)
+            })
+            .collect();
+        let mut ids: Vec<&MpcAddr> = self.ids.iter().collect();
+        ids.sort();
+        f.debug_struct("MultiShard")
+            .field("ui_pergroup", &redacted(&self.ui_pergroup))
+            .field("xi_pergroup", &redacted(&self.xi_pergroup))
+            .field("vss_com_grid", &vss_com_grid)
+            .field("vss_zkp_grid", &vss_zkp_grid)
+            .field("ids", &ids)
+            .field(
+                "aux",
+                &self
+                    .aux
+                    .as_ref()
+                    .map(|aux| format!("<{} bytes>", aux.len())),
+            )
+            .field("version", &self.version)
+            .finish()
+    }
 }
 
 impl<ScalarType, PointType> MultiShard<ScalarType, PointType>
@@ -41,6 +129,10 @@ where
         + std::ops::Add<PointType, Output = PointType>
         + std::ops::Mul<ScalarType, Output = PointType>,
 {
+    /// Layout version written by this build.
+    /// Version 2 added `vss_zkp_grid`.
+    pub const CURRENT_VERSION: u16 = 2;
+
     pub fn th(&self, gid: u16) -> Outcome<usize> {
         let vss_com_dict = self
             .vss_com_grid
@@ -0,0 +1,83 @@
+use std::collections::{HashMap, HashSet};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use curve25519_dalek::{edwards::EdwardsPoint, scalar::Scalar};
+use mpc_algo::{
+    deal_vss_share_from_coeffs, eval_chain_code, keygen_build_commitment, keygen_channel_keys,
+    keygen_process_commitments, non_hardened_derive, KeyGenDKGProposedCommitment,
+};
+use mpc_spec::MpcAddr;
+
+type KeyArch = HashMap<u16, (usize, HashSet<MpcAddr>)>;
+
+/// A single group of `n` members, of threshold a majority.
+fn key_arch_of(n: u16) -> KeyArch {
+    let members: HashSet<MpcAddr> = (1..=n).map(|i| MpcAddr::new(1, i)).collect();
+    HashMap::from([(1, (n as usize / 2 + 1, members))])
+}
+
+fn gen_coms(key_arch: &KeyArch, context: &str) -> HashMap<MpcAddr, KeyGenDKGProposedCommitment> {
+    let mut rng = rand::rngs::OsRng;
+    key_arch[&1]
+        .1
+        .iter()
+        .map(|id| {
+            let state = keygen_build_commitment(key_arch, *id, context, &mut rng).unwrap();
+            (*id, state.dkg_commitment.clone())
+        })
+        .collect()
+}
+
+fn bench_keygen(c: &mut Criterion) {
+    let context = "bench";
+    let mut rng = rand::rngs::OsRng;
+    let me = MpcAddr::new(1, 1);
+    for n in [4u16, 16] {
+        let key_arch = key_arch_of(n);
+        c.bench_with_input(
+            BenchmarkId::new("keygen_build_commitment", n),
+            &key_arch,
+            |b, key_arch| b.iter(|| keygen_build_commitment(key_arch, me, context, &mut rng)),
+        );
+        let coms = gen_coms(&key_arch, context);
+        c.bench_with_input(
+            BenchmarkId::new("keygen_process_commitments", n),
+            &coms,
+            |b, coms| b.iter(|| keygen_process_commitments(&key_arch, me, coms, context).unwrap()),
+        );
+        let (th, members) = &key_arch[&1];
+        let coeffs: Vec<Scalar> = (0..*th).map(|_| Scalar::random(&mut rng)).collect();
+        c.bench_with_input(
+            BenchmarkId::new("deal_vss_share_from_coeffs", n),
+            &coeffs,
+            |b, coeffs| b.iter(|| deal_vss_share_from_coeffs(coeffs, members).unwrap()),
+        );
+    }
+}
+
+/// The channel keys of one member of 32.
+fn bench_channel_keys(c: &mut Criterion) {
+    let context = "bench";
+    let me = MpcAddr::new(1, 1);
+    let key_arch = key_arch_of(32);
+    let members = &key_arch[&1].1;
+    let state = keygen_build_commitment(&key_arch, me, context, &mut rand::rngs::OsRng).unwrap();
+    let vss_com_dict: HashMap<MpcAddr, Vec<EdwardsPoint>> = gen_coms(&key_arch, context)
+        .into_iter()
+        .map(|(id, com)| (id, com.shares_commitment))
+        .collect();
+    c.bench_function("keygen_channel_keys 32", |b| {
+        b.iter(|| keygen_channel_keys(&state, &vss_com_dict, members, context).unwrap())
+    });
+}
+
+fn bench_derive(c: &mut Criterion) {
+    let parent_pk = &curve25519_dalek::constants::ED25519_BASEPOINT_TABLE * &Scalar::from(7u8);
+    let chain_code = eval_chain_code(&parent_pk);
+    c.bench_function("non_hardened_derive 5 segments", |b| {
+        b.iter(|| non_hardened_derive("m/44/501/0/0/7", &parent_pk, &chain_code).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_keygen, bench_channel_keys, bench_derive);
+criterion_main!(benches);
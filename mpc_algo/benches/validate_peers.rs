@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use curve25519_dalek::{constants, scalar::Scalar};
+use mpc_algo::{
+    find_invalid_zkps, generate_dkg_challenge, verify_dkg_zkp, KeyGenDKGProposedCommitment,
+    KeyGenZKP,
+};
+use mpc_spec::MpcAddr;
+
+fn gen_coms(n: u16, context: &str) -> HashMap<MpcAddr, KeyGenDKGProposedCommitment> {
+    let mut rng = rand::rngs::OsRng;
+    (1..=n)
+        .map(|i| {
+            let id = MpcAddr::new(1, i);
+            let u_i = Scalar::random(&mut rng);
+            let k_i = Scalar::random(&mut rng);
+            let g_u_i = &constants::ED25519_BASEPOINT_TABLE * &u_i;
+            let g_k_i = &constants::ED25519_BASEPOINT_TABLE * &k_i;
+            let challenge = generate_dkg_challenge(id, context, &g_u_i, &g_k_i).unwrap();
+            let com = KeyGenDKGProposedCommitment {
+                shares_commitment: vec![g_u_i],
+                zkp: KeyGenZKP {
+                    g_k_i,
+                    sigma: k_i + u_i * challenge,
+                },
+            };
+            (id, com)
+        })
+        .collect()
+}
+
+fn bench_validate(c: &mut Criterion) {
+    let context = "bench";
+    let coms = gen_coms(64, context);
+    c.bench_function("find_invalid_zkps 64", |b| {
+        b.iter(|| assert!(find_invalid_zkps(&coms, context).unwrap().is_empty()))
+    });
+    c.bench_function("verify_dkg_zkp x 64", |b| {
+        b.iter(|| {
+            for (id, com) in coms.iter() {
+                assert!(verify_dkg_zkp(*id, com, context).unwrap());
+            }
+        })
+    });
+}
+
+criterion_group!(benches, bench_validate);
+criterion_main!(benches);
@@ -0,0 +1,48 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use curve25519_dalek::{
+    edwards::{CompressedEdwardsY, EdwardsPoint},
+    scalar::Scalar,
+};
+use ed25519_dalek::{Keypair, Signer};
+use mpc_algo::{verify_batch, verify_signature, Signature};
+
+fn gen_items(n: usize) -> Vec<(Vec<u8>, Signature, EdwardsPoint)> {
+    let mut rng = rand::rngs::OsRng;
+    (0..n)
+        .map(|i| {
+            let keypair = Keypair::generate(&mut rng);
+            let msg = (i as u64).to_be_bytes().to_vec();
+            let sig_bytes = keypair.sign(&msg).to_bytes();
+            let mut r_bytes = [0u8; 32];
+            let mut s_bytes = [0u8; 32];
+            r_bytes.copy_from_slice(&sig_bytes[..32]);
+            s_bytes.copy_from_slice(&sig_bytes[32..]);
+            let sig = Signature {
+                r: CompressedEdwardsY(r_bytes).decompress().unwrap(),
+                s: Scalar::from_canonical_bytes(s_bytes).unwrap(),
+                hash: msg.clone(),
+            };
+            let pk = CompressedEdwardsY(keypair.public.to_bytes())
+                .decompress()
+                .unwrap();
+            (msg, sig, pk)
+        })
+        .collect()
+}
+
+fn bench_verify(c: &mut Criterion) {
+    let items = gen_items(256);
+    c.bench_function("verify_batch 256", |b| {
+        b.iter(|| verify_batch(&items).unwrap())
+    });
+    c.bench_function("verify_signature x 256", |b| {
+        b.iter(|| {
+            for (_, sig, pk) in items.iter() {
+                verify_signature(sig, pk).unwrap();
+            }
+        })
+    });
+}
+
+criterion_group!(benches, bench_verify);
+criterion_main!(benches);
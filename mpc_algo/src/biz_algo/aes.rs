@@ -1,66 +1,451 @@
 use libexception::*;
 
+/// Thrown when encryption fails or a pack does not authenticate,
+/// whichever `AeadScheme` it uses.
+pub const AeadException: &str = "AeadException";
+
+/// The cipher of an `AEAD` pack.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum AeadScheme {
+    #[default]
+    AesGcm,
+    /// Faster than AES-GCM on hosts without AES hardware acceleration.
+    ChaCha20Poly1305,
+}
+
+/// Lengths of the authentication tag an `AEAD` pack may have.
+/// Only AES-GCM takes the truncated 12-byte tag, which saves bandwidth;
+/// ChaCha20-Poly1305 always has 16.
+pub const AEAD_TAG_LENS: [usize; 2] = [12, 16];
+
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct AEAD {
+    /// The encrypted payload, followed by its `tag_len`-byte authentication tag.
     pub ciphertext: Vec<u8>,
+    /// The nonce, despite its historical name.
     pub tag: Vec<u8>,
+    /// Packs serialized before this field existed are AES-GCM.
+    #[serde(default)]
+    pub scheme: AeadScheme,
+    /// Packs serialized before this field existed have a 16-byte tag.
+    #[serde(default = "full_tag_len")]
+    pub tag_len: usize,
+}
+
+fn full_tag_len() -> usize {
+    16
 }
 
 pub fn aes_encrypt(key: &[u8], plaintext: &[u8]) -> Outcome<AEAD> {
-    let mut full_length_key: [u8; 32] = [0; 32];
-    full_length_key[(32 - key.len())..].copy_from_slice(key); // pad key with zeros
+    aes_encrypt_with_aad(key, plaintext, &LEGACY_AAD)
+}
+
+pub fn aes_decrypt(key: &[u8], aead_pack: &AEAD) -> Outcome<Vec<u8>> {
+    aes_decrypt_with_aad(key, aead_pack, &LEGACY_AAD)
+}
+
+/// The aad of `aes_encrypt` and `aes_decrypt`, kept for compatibility.
+const LEGACY_AAD: [u8; 16] = [0; 16];
+
+/// Like `aes_encrypt`, but also authenticates `aad`,
+/// which must be presented again to `aes_decrypt_with_aad`.
+pub fn aes_encrypt_with_aad(key: &[u8], plaintext: &[u8], aad: &[u8]) -> Outcome<AEAD> {
+    aead_encrypt(AeadScheme::AesGcm, key, plaintext, aad)
+}
 
-    let aes_key = aes_gcm::Key::from_slice(full_length_key.as_slice());
-    let cipher = Aes256Gcm::new(aes_key);
+/// Decrypt with the cipher and tag length recorded in `aead_pack`.
+/// Throws `AeadException` if `key` is not the 32 bytes both ciphers take.
+pub fn aes_decrypt_with_aad(key: &[u8], aead_pack: &AEAD, aad: &[u8]) -> Outcome<Vec<u8>> {
+    let key = check_key(key)?;
+    check_tag_len(aead_pack.scheme, aead_pack.tag_len)?;
 
-    let mut _buf = [0u8; 12];
-    let nonce = {
-        OsRng.fill_bytes(&mut _buf); // provided by Rng trait
-        Nonce::from_slice(&_buf.as_slice())
+    let payload = Payload {
+        msg: aead_pack.ciphertext.as_slice(),
+        aad,
     };
 
-    // reserve for later changes when a non-empty aad could be imported
-    let aad: Vec<u8> = std::iter::repeat(0).take(16).collect();
+    assert_throw!(
+        aead_pack.tag.len() == 12,
+        AeadException,
+        format!("A nonce has 12 bytes, not {}", aead_pack.tag.len())
+    );
+
+    // The cipher checks the tag in constant time and yields no plaintext
+    // unless it matches, so a wrong key, nonce, aad or any flipped byte
+    // all fail alike.
+    let nonce = &aead_pack.tag;
+    let out = match (aead_pack.scheme, aead_pack.tag_len) {
+        (AeadScheme::AesGcm, 12) => decrypt_with::<Aes256Gcm12>(key, nonce, payload),
+        (AeadScheme::AesGcm, _) => decrypt_with::<Aes256Gcm>(key, nonce, payload),
+        (AeadScheme::ChaCha20Poly1305, _) => decrypt_with::<ChaCha20Poly1305>(key, nonce, payload),
+    }
+    .catch(AeadException, "Wrong password or nonce.")?;
+    Ok(out)
+}
+
+/// Encrypt under `scheme` with a random nonce, authenticating `aad`.
+pub fn aead_encrypt(scheme: AeadScheme, key: &[u8], plaintext: &[u8], aad: &[u8]) -> Outcome<AEAD> {
+    aead_encrypt_with_tag_len(scheme, full_tag_len(), key, plaintext, aad)
+}
+
+/// Like `aead_encrypt`, but with a `tag_len`-byte tag, one of `AEAD_TAG_LENS`
+/// that `scheme` supports. Throws `AeadException` otherwise.
+pub fn aead_encrypt_with_tag_len(
+    scheme: AeadScheme,
+    tag_len: usize,
+    key: &[u8],
+    plaintext: &[u8],
+    aad: &[u8],
+) -> Outcome<AEAD> {
+    // Both ciphers take a 96-bit nonce.
+    let mut nonce = [0u8; 12];
+    OsRng.fill_bytes(&mut nonce); // provided by Rng trait
+    encrypt_with_nonce(scheme, tag_len, key, &nonce, plaintext, aad)
+}
+
+/// Like `aes_encrypt`, but the nonce is `src || dst || counter`, each
+/// 4 bytes big-endian, instead of random.
+///
+/// The nonce is only as unique as its inputs: the caller must never encrypt
+/// twice under `key` with the same `counter` in the same direction.
+/// Nothing here tracks which counters were used.
+pub fn aes_encrypt_seq(
+    key: &[u8],
+    plaintext: &[u8],
+    src: MpcAddr,
+    dst: MpcAddr,
+    counter: u32,
+) -> Outcome<AEAD> {
+    aead_encrypt_seq(
+        AeadScheme::AesGcm,
+        key,
+        plaintext,
+        &LEGACY_AAD,
+        src,
+        dst,
+        counter,
+    )
+}
+
+/// `aead_encrypt` with the nonce of `aes_encrypt_seq`.
+pub fn aead_encrypt_seq(
+    scheme: AeadScheme,
+    key: &[u8],
+    plaintext: &[u8],
+    aad: &[u8],
+    src: MpcAddr,
+    dst: MpcAddr,
+    counter: u32,
+) -> Outcome<AEAD> {
+    let nonce = seq_nonce(src, dst, counter);
+    encrypt_with_nonce(scheme, full_tag_len(), key, &nonce, plaintext, aad)
+}
+
+fn seq_nonce(src: MpcAddr, dst: MpcAddr, counter: u32) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..4].copy_from_slice(&src.to_be_bytes());
+    nonce[4..8].copy_from_slice(&dst.to_be_bytes());
+    nonce[8..].copy_from_slice(&counter.to_be_bytes());
+    nonce
+}
+
+fn encrypt_with_nonce(
+    scheme: AeadScheme,
+    tag_len: usize,
+    key: &[u8],
+    nonce: &[u8; 12],
+    plaintext: &[u8],
+    aad: &[u8],
+) -> Outcome<AEAD> {
+    let key = check_key(key)?;
+    check_tag_len(scheme, tag_len)?;
+
     let payload = Payload {
         msg: plaintext,
-        aad: &aad.as_slice(),
+        aad,
     };
 
-    let ciphertext = cipher
-        .encrypt(nonce, payload)
-        .catch("AesGcmException", "")?;
+    let ciphertext = match (scheme, tag_len) {
+        (AeadScheme::AesGcm, 12) => encrypt_with::<Aes256Gcm12>(key, nonce, payload),
+        (AeadScheme::AesGcm, _) => encrypt_with::<Aes256Gcm>(key, nonce, payload),
+        (AeadScheme::ChaCha20Poly1305, _) => encrypt_with::<ChaCha20Poly1305>(key, nonce, payload),
+    }
+    .catch(AeadException, "")?;
 
     Ok(AEAD {
-        ciphertext: ciphertext,
+        ciphertext,
         tag: nonce.to_vec(),
+        scheme,
+        tag_len,
     })
 }
 
-pub fn aes_decrypt(key: &[u8], aead_pack: &AEAD) -> Outcome<Vec<u8>> {
-    let mut full_length_key: [u8; 32] = [0; 32];
-    full_length_key[(32 - key.len())..].copy_from_slice(key); // Pad key with zeros
+/// AES-256-GCM with its tag truncated to 12 bytes.
+type Aes256Gcm12 = AesGcm<Aes256, U12, U12>;
 
-    let aes_key = aes_gcm::Key::from_slice(full_length_key.as_slice());
-    let nonce = Nonce::from_slice(&aead_pack.tag);
-    let gcm = Aes256Gcm::new(aes_key);
+/// Both ciphers take a 32-byte key.
+fn check_key(key: &[u8]) -> Outcome<&[u8; 32]> {
+    key.try_into().ok().ifnone(
+        AeadException,
+        format!("A key has 32 bytes, not {}", key.len()),
+    )
+}
 
-    // reserve for later changes when a non-empty aad could be imported
-    let aad: Vec<u8> = std::iter::repeat(0).take(16).collect();
-    let payload = Payload {
-        msg: aead_pack.ciphertext.as_slice(),
-        aad: aad.as_slice(),
+fn check_tag_len(scheme: AeadScheme, tag_len: usize) -> Outcome<()> {
+    let supported = match scheme {
+        AeadScheme::AesGcm => AEAD_TAG_LENS.contains(&tag_len),
+        AeadScheme::ChaCha20Poly1305 => tag_len == full_tag_len(),
     };
+    assert_throw!(
+        supported,
+        AeadException,
+        format!("{:?} has no {}-byte tag", scheme, tag_len)
+    );
+    Ok(())
+}
 
-    // NOTE: no error reported but return a value NONE when decrypt key is wrong
-    let out = gcm
-        .decrypt(nonce, payload)
-        .catch("AesGcmException", "Wrong password or nonce.")?;
-    Ok(out)
+fn encrypt_with<C: KeyInit + Aead>(
+    key: &[u8; 32],
+    nonce: &[u8],
+    payload: Payload,
+) -> Result<Vec<u8>, aead::Error> {
+    let cipher = C::new(GenericArray::from_slice(key));
+    cipher.encrypt(GenericArray::from_slice(nonce), payload)
+}
+
+fn decrypt_with<C: KeyInit + Aead>(
+    key: &[u8; 32],
+    nonce: &[u8],
+    payload: Payload,
+) -> Result<Vec<u8>, aead::Error> {
+    let cipher = C::new(GenericArray::from_slice(key));
+    cipher.decrypt(GenericArray::from_slice(nonce), payload)
+}
+
+/// AAD binding a P2P message to its sender, receiver and round,
+/// so that a share replayed to another party or round fails to decrypt.
+pub fn p2p_aad(src: MpcAddr, dst: MpcAddr, round: &str) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(8 + round.len());
+    aad.extend_from_slice(&src.to_be_bytes());
+    aad.extend_from_slice(&dst.to_be_bytes());
+    aad.extend_from_slice(round.as_bytes());
+    aad
+}
+
+/// Stretch the DH point `shared` between parties `a` and `b` into a fresh
+/// AES key with HKDF-SHA512, salted by the session `context` and both ids.
+/// The order of `a` and `b` does not matter.
+pub fn eval_channel_key(
+    shared: &EdwardsPoint,
+    context: &str,
+    a: MpcAddr,
+    b: MpcAddr,
+) -> Outcome<[u8; 32]> {
+    let (lo, hi) = if a < b { (a, b) } else { (b, a) };
+    let mut salt = context.as_bytes().to_vec();
+    salt.extend_from_slice(&lo.to_be_bytes());
+    salt.extend_from_slice(&hi.to_be_bytes());
+
+    let mut ikm = shared.compress().to_bytes();
+    let hk = Hkdf::<Sha512>::new(Some(&salt), &ikm);
+    ikm.zeroize();
+    let mut aes_key = [0u8; 32];
+    hk.expand(b"mpc_eddsa p2p aes key", &mut aes_key)
+        .catch("HkdfException", "")?;
+    Ok(aes_key)
 }
 
 use aes_gcm::{
-    aead::{Aead, NewAead, Payload},
-    Aes256Gcm, Nonce,
+    aead::{self, consts::U12, generic_array::GenericArray, Aead, KeyInit, Payload},
+    aes::Aes256,
+    Aes256Gcm, AesGcm,
 };
+use chacha20poly1305::ChaCha20Poly1305;
+use curve25519_dalek::edwards::EdwardsPoint;
+use hkdf::Hkdf;
+use mpc_spec::MpcAddr;
 use rand_core::{OsRng, RngCore};
 use serde::{Deserialize, Serialize};
+use sha2::Sha512;
+use zeroize::Zeroize;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use curve25519_dalek::{constants, scalar::Scalar};
+
+    #[test]
+    fn channel_key_is_symmetric_and_bound_to_session() {
+        let (u_i, u_j) = (Scalar::from(3u64), Scalar::from(5u64));
+        let (i, j) = (MpcAddr::new(1, 1), MpcAddr::new(1, 2));
+        let g = constants::ED25519_BASEPOINT_POINT;
+
+        let key_i = eval_channel_key(&((g * u_j) * u_i), "ses", i, j).unwrap();
+        let key_j = eval_channel_key(&((g * u_i) * u_j), "ses", j, i).unwrap();
+        assert_eq!(key_i, key_j);
+        assert_ne!(key_i, (g * u_i * u_j).compress().to_bytes());
+
+        let other_ses = eval_channel_key(&((g * u_j) * u_i), "ses2", i, j).unwrap();
+        assert_ne!(key_i, other_ses);
+    }
+
+    #[test]
+    fn misrouted_message_fails_authentication() {
+        let key = [9u8; 32];
+        let (i, j, k) = (MpcAddr::new(1, 1), MpcAddr::new(1, 2), MpcAddr::new(1, 3));
+        let pack = aes_encrypt_with_aad(&key, b"share", &p2p_aad(i, j, "aead_share")).unwrap();
+
+        let out = aes_decrypt_with_aad(&key, &pack, &p2p_aad(i, j, "aead_share")).unwrap();
+        assert_eq!(out, b"share");
+        assert!(aes_decrypt_with_aad(&key, &pack, &p2p_aad(k, j, "aead_share")).is_err());
+        assert!(aes_decrypt_with_aad(&key, &pack, &p2p_aad(j, i, "aead_share")).is_err());
+        assert!(aes_decrypt_with_aad(&key, &pack, &p2p_aad(i, j, "other_round")).is_err());
+        assert!(aes_decrypt(&key, &pack).is_err());
+    }
+
+    #[test]
+    fn seq_nonces_are_distinct() {
+        let key = [7u8; 32];
+        let (i, j) = (MpcAddr::new(1, 1), MpcAddr::new(1, 2));
+        let pack0 = aes_encrypt_seq(&key, b"share 0", i, j, 0).unwrap();
+        let pack1 = aes_encrypt_seq(&key, b"share 1", i, j, 1).unwrap();
+        let back = aes_encrypt_seq(&key, b"share 0", j, i, 0).unwrap();
+        assert_ne!(pack0.tag, pack1.tag);
+        assert_ne!(pack0.tag, back.tag);
+        assert_eq!(aes_decrypt(&key, &pack0).unwrap(), b"share 0");
+        assert_eq!(aes_decrypt(&key, &pack1).unwrap(), b"share 1");
+
+        let mut nonce = i.to_be_bytes().to_vec();
+        nonce.extend_from_slice(&j.to_be_bytes());
+        nonce.extend_from_slice(&1u32.to_be_bytes());
+        assert_eq!(pack1.tag, nonce);
+    }
+
+    #[test]
+    fn both_schemes_round_trip() {
+        let key = [7u8; 32];
+        for scheme in [AeadScheme::AesGcm, AeadScheme::ChaCha20Poly1305] {
+            let pack = aead_encrypt(scheme, &key, b"share", b"aad").unwrap();
+            assert_eq!(pack.scheme, scheme);
+            let out = aes_decrypt_with_aad(&key, &pack, b"aad").unwrap();
+            assert_eq!(out, b"share");
+            assert!(aes_decrypt_with_aad(&[8u8; 32], &pack, b"aad").is_err());
+        }
+    }
+
+    #[test]
+    fn shares_survive_the_channel_encoding() {
+        assert_eq!(Scalar::one().to_bytes()[0], 1); // little-endian
+        let key = [7u8; 32];
+        let (i, j) = (MpcAddr::new(1, 1), MpcAddr::new(1, 2));
+        let aad = p2p_aad(i, j, "aead_share");
+        let largest = -Scalar::one();
+        let random = Scalar::random(&mut rand::rngs::OsRng);
+        for share in [Scalar::zero(), Scalar::one(), largest, random] {
+            let pack = aead_encrypt_seq(AeadScheme::AesGcm, &key, &share.to_bytes(), &aad, i, j, 0)
+                .unwrap();
+            let out: [u8; 32] = aes_decrypt_with_aad(&key, &pack, &aad)
+                .unwrap()
+                .try_into()
+                .unwrap();
+            assert_eq!(Scalar::from_canonical_bytes(out), Some(share));
+            assert_eq!(Scalar::from_bytes_mod_order(out), share);
+        }
+    }
+
+    #[test]
+    fn every_tag_length_round_trips() {
+        let key = [7u8; 32];
+        for tag_len in AEAD_TAG_LENS {
+            let pack =
+                aead_encrypt_with_tag_len(AeadScheme::AesGcm, tag_len, &key, b"share", b"aad")
+                    .unwrap();
+            assert_eq!(pack.ciphertext.len(), b"share".len() + tag_len);
+            let buf = serde_pickle::to_vec(&pack, Default::default()).unwrap();
+            let pack: AEAD = serde_pickle::from_slice(&buf, Default::default()).unwrap();
+            assert_eq!(pack.tag_len, tag_len);
+            assert_eq!(aes_decrypt_with_aad(&key, &pack, b"aad").unwrap(), b"share");
+
+            let mut relabeled = pack.clone();
+            relabeled.tag_len = 28 - tag_len; // the other one
+            assert!(aes_decrypt_with_aad(&key, &relabeled, b"aad").is_err());
+        }
+
+        let chacha = AeadScheme::ChaCha20Poly1305;
+        let err = aead_encrypt_with_tag_len(chacha, 12, &key, b"share", b"aad").unwrap_err();
+        assert_eq!(err.get_name(), AeadException);
+        let mut pack = aead_encrypt(chacha, &key, b"share", b"aad").unwrap();
+        pack.tag_len = 12;
+        let err = aes_decrypt_with_aad(&key, &pack, b"aad").unwrap_err();
+        assert!(err.get_context().unwrap().contains("no 12-byte tag"));
+    }
+
+    #[test]
+    fn key_of_another_length_is_rejected() {
+        let pack = aes_encrypt(&[7u8; 32], b"share").unwrap();
+        for len in [16, 31, 33] {
+            let key = vec![7u8; len];
+            let err = aes_decrypt(&key, &pack).unwrap_err();
+            assert_eq!(err.get_name(), AeadException);
+            assert_eq!(
+                err.get_context().unwrap(),
+                format!("A key has 32 bytes, not {}", len)
+            );
+            assert!(aes_encrypt(&key, b"share").is_err());
+        }
+    }
+
+    #[test]
+    fn failures_are_named_alike_for_both_schemes() {
+        let key = [7u8; 32];
+        for scheme in [AeadScheme::AesGcm, AeadScheme::ChaCha20Poly1305] {
+            let mut pack = aead_encrypt(scheme, &key, b"share", b"aad").unwrap();
+            let err = aes_decrypt_with_aad(&[8u8; 32], &pack, b"aad").unwrap_err();
+            assert_eq!(err.get_name(), AeadException);
+
+            pack.tag.truncate(8);
+            let err = aes_decrypt_with_aad(&key, &pack, b"aad").unwrap_err();
+            assert_eq!(err.get_name(), AeadException);
+        }
+    }
+
+    #[test]
+    fn flipped_ciphertext_byte_fails_closed() {
+        let key = [7u8; 32];
+        for scheme in [AeadScheme::AesGcm, AeadScheme::ChaCha20Poly1305] {
+            let pack = aead_encrypt(scheme, &key, b"secret share", b"aad").unwrap();
+            let wrong_key = aes_decrypt_with_aad(&[8u8; 32], &pack, b"aad").unwrap_err();
+            for i in [0, pack.ciphertext.len() - 1] {
+                let mut tampered = pack.clone();
+                tampered.ciphertext[i] ^= 1;
+                let err = aes_decrypt_with_aad(&key, &tampered, b"aad").unwrap_err();
+                assert_eq!(err.get_name(), AeadException);
+                assert_eq!(err.get_context(), wrong_key.get_context());
+                assert!(!err.to_string().contains("secret"));
+            }
+        }
+
+        let err = aes_decrypt(&[7u8; 33], &aes_encrypt(&key, b"share").unwrap()).unwrap_err();
+        assert_eq!(err.get_name(), AeadException);
+    }
+
+    #[test]
+    fn pack_without_scheme_is_aes_gcm() {
+        #[derive(Serialize)]
+        struct LegacyAead {
+            ciphertext: Vec<u8>,
+            tag: Vec<u8>,
+        }
+        let key = [7u8; 32];
+        let pack = aes_encrypt(&key, b"share").unwrap();
+        let legacy = LegacyAead {
+            ciphertext: pack.ciphertext,
+            tag: pack.tag,
+        };
+        let buf = serde_pickle::to_vec(&legacy, Default::default()).unwrap();
+        let pack: AEAD = serde_pickle::from_slice(&buf, Default::default()).unwrap();
+        assert_eq!(pack.scheme, AeadScheme::AesGcm);
+        assert_eq!(pack.tag_len, 16);
+        assert_eq!(aes_decrypt(&key, &pack).unwrap(), b"share");
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::{HashMap, HashSet};
+
+use curve25519_dalek::constants;
+use curve25519_dalek::edwards::EdwardsPoint;
+use curve25519_dalek::scalar::Scalar;
+use libexception::*;
+use mpc_spec::*;
+use rand::rngs::OsRng;
+use tracing::info;
+use zeroize::Zeroize;
+
+use super::aes::*;
+use super::{decode_scalar_strict, KeyStore};
+use crate::frost::{lagrange_lambda_at, verify_signing_share};
+
+/// Give the new member `new_id` a share of group `new_id.group_id()`,
+/// leaving the commitments, the other shares and the group public key untouched.
+///
+/// Each of the (at least `th`) `helpers` holds $x_i = f(i)$ and computes its
+/// part $\lambda_i(j) x_i$ of $f(j)$, where $j$ is `new_id`. The part is
+/// split into random masks exchanged among the helpers, so the newcomer only
+/// sees the masked sums, and no helper learns another one's share or $f(j)$.
+/// The newcomer checks the result against the existing `vss_com_grid`.
+///
+/// `my_id` is who I am: one of `helpers`, or `new_id` itself, whose keystore
+/// must hold at least the public `vss_com_grid` of the group. Helpers get
+/// their keystore back unchanged. Members enrolled this way deal nothing,
+/// so they cannot appear as dealers in the commitments.
+#[tracing::instrument(name = "enroll", skip_all, fields(context = %context, %my_id, %new_id))]
+pub async fn algo_enroll(
+    messenger: &impl Messenger,
+    keystore: &KeyStore,
+    my_id: MpcAddr,
+    helpers: &HashSet<MpcAddr>,
+    new_id: MpcAddr,
+    context: &str,
+) -> Outcome<KeyStore> {
+    const IEE: &str = "InvalidEnrollment";
+    let gid = new_id.group_id();
+    let gcast_id = MpcAddr::gcast_id(gid);
+    let vss_com_dict = keystore
+        .vss_com_grid
+        .get(&gid)
+        .ifnone("NoSuchGroup", gid.to_string())?;
+    let th = keystore.th(gid).catch_()?;
+    assert_throw!(
+        helpers.len() >= th,
+        IEE,
+        format!(
+            "{} helpers cannot enroll into group {} of threshold th={}",
+            helpers.len(),
+            gid,
+            th
+        )
+    );
+    assert_throw!(
+        helpers.iter().all(|id| id.group_id() == gid),
+        IEE,
+        format!("helpers are not all in group {}", gid)
+    );
+    assert_throw!(
+        new_id.member_id() != 0 && !helpers.contains(&new_id),
+        IEE,
+        format!("{} cannot be enrolled as a new member", new_id)
+    );
+    let is_helper = helpers.contains(&my_id);
+    assert_throw!(
+        is_helper || my_id == new_id,
+        IEE,
+        format!("my_id={} is neither a helper nor the new member", my_id)
+    );
+
+    // Round 1: everyone announces an ephemeral DH key.
+    let mut ephemeral_sk = Scalar::random(&mut OsRng);
+    let ephemeral_pk = &constants::ED25519_BASEPOINT_TABLE * &ephemeral_sk;
+    messenger
+        .send("enroll_ek", my_id, gcast_id, &ephemeral_pk)
+        .await
+        .catch_()?;
+    let mut parties = helpers.clone();
+    parties.insert(new_id);
+    let ek_dict: HashMap<MpcAddr, EdwardsPoint> = messenger
+        .gather("enroll_ek", &parties, gcast_id)
+        .await
+        .catch_()?;
+    let channel_key = |peer: &MpcAddr| -> Outcome<[u8; 32]> {
+        let ek = ek_dict.get(peer).ifnone_()?;
+        eval_channel_key(&(ek * ephemeral_sk), context, my_id, *peer)
+    };
+
+    if is_helper {
+        let x_i = keystore.xi_pergroup.get(&gid).ifnone(
+            IEE,
+            format!("helper {} holds no share of group {}", my_id, gid),
+        )?;
+        let x_new = Scalar::from(new_id.member_id());
+        let mut part = lagrange_lambda_at(my_id, helpers, &x_new).catch_()? * x_i;
+
+        // Round 2: split my part into masks, one for each other helper.
+        for id in helpers.iter().filter(|id| **id != my_id) {
+            let mut mask = Scalar::random(&mut OsRng);
+            part -= mask;
+            let mut aes_key = channel_key(id)?;
+            let aad = p2p_aad(my_id, *id, "enroll_mask");
+            let aead_pack = aes_encrypt_with_aad(&aes_key, &mask.to_bytes(), &aad).catch_()?;
+            aes_key.zeroize();
+            mask.zeroize();
+            messenger
+                .send("enroll_mask", my_id, *id, &aead_pack)
+                .await
+                .catch_()?;
+        }
+
+        // Round 3: pass the masked sum to the newcomer.
+        let others: HashSet<MpcAddr> = helpers.iter().filter(|id| **id != my_id).cloned().collect();
+        let aead_dict: HashMap<MpcAddr, AEAD> = messenger
+            .gather("enroll_mask", &others, my_id)
+            .await
+            .catch_()?;
+        for (id, aead_pack) in aead_dict.iter() {
+            let mut aes_key = channel_key(id)?;
+            let mask = decrypt_scalar(&aes_key, aead_pack, *id, my_id, "enroll_mask")?;
+            aes_key.zeroize();
+            part += mask;
+        }
+        let mut aes_key = channel_key(&new_id)?;
+        let aad = p2p_aad(my_id, new_id, "enroll_share");
+        let aead_pack = aes_encrypt_with_aad(&aes_key, &part.to_bytes(), &aad).catch_()?;
+        aes_key.zeroize();
+        part.zeroize();
+        messenger
+            .send("enroll_share", my_id, new_id, &aead_pack)
+            .await
+            .catch_()?;
+        ephemeral_sk.zeroize();
+        return Ok(keystore.clone());
+    }
+
+    // I am the newcomer: sum the masked parts into $f(j)$.
+    let aead_dict: HashMap<MpcAddr, AEAD> = messenger
+        .gather("enroll_share", helpers, my_id)
+        .await
+        .catch_()?;
+    let mut x_i = Scalar::zero();
+    for (id, aead_pack) in aead_dict.iter() {
+        let mut aes_key = channel_key(id)?;
+        x_i += decrypt_scalar(&aes_key, aead_pack, *id, my_id, "enroll_share")?;
+        aes_key.zeroize();
+    }
+    ephemeral_sk.zeroize();
+    verify_signing_share(new_id, &x_i, vss_com_dict).catch(
+        "InvalidShare",
+        format!(
+            "Share enrolled by {} helpers does not match the group",
+            helpers.len()
+        ),
+    )?;
+
+    let mut new_keystore = keystore.clone();
+    new_keystore.xi_pergroup.insert(gid, x_i);
+    new_keystore.ids.insert(new_id);
+    info!(n_helpers = helpers.len(), th, "Enrolled into group {}", gid);
+    Ok(new_keystore)
+}
+
+fn decrypt_scalar(
+    aes_key: &[u8],
+    aead_pack: &AEAD,
+    src: MpcAddr,
+    dst: MpcAddr,
+    round: &str,
+) -> Outcome<Scalar> {
+    let out = aes_decrypt_with_aad(aes_key, aead_pack, &p2p_aad(src, dst, round)).catch(
+        "InvalidShare",
+        format!("Cannot decrypt share from party {}", src),
+    )?;
+    decode_scalar_strict(&out, src)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_util::{group_of, keygen_for_test};
+    use super::*;
+    use crate::{algo_sign, KeyStoreExt};
+
+    #[tokio::test]
+    async fn enrolled_member_can_sign() {
+        let messenger = MemoryTransport::default();
+        let mut keystores = keygen_for_test(2, 3).await;
+        let (ks3, ks2) = (keystores.pop().unwrap(), keystores.pop().unwrap());
+        let pk = ks2.group_public_key();
+
+        let newcomer = KeyStore {
+            vss_com_grid: ks2.vss_com_grid.clone(),
+            ..Default::default()
+        };
+        let (helpers, new_id) = (group_of(&[2, 3]), MpcAddr::new(1, 4));
+        let enroll = |my_id: MpcAddr, ks: KeyStore| {
+            let (messenger, helpers) = (messenger.clone(), helpers.clone());
+            async move {
+                algo_enroll(&messenger, &ks, my_id, &helpers, new_id, "test")
+                    .await
+                    .unwrap()
+            }
+        };
+        let (ks2, _ks3, ks4) = tokio::join!(
+            enroll(MpcAddr::new(1, 2), ks2),
+            enroll(MpcAddr::new(1, 3), ks3),
+            enroll(new_id, newcomer),
+        );
+        assert_eq!(ks4.group_public_key(), pk);
+        assert!(ks4.ids.contains(&new_id));
+
+        // The old member 2 and the new member 4 sign together.
+        let ses_arch = HashMap::from([(1u16, group_of(&[2, 4]))]);
+        let msg_hash = [7u8; 32];
+        let sign = |ks: KeyStore| {
+            let (messenger, ses_arch) = (messenger.clone(), ses_arch.clone());
+            async move { algo_sign(&messenger, &ses_arch, "", &msg_hash, &ks).await }
+        };
+        let (sig2, sig4) = tokio::join!(sign(ks2), sign(ks4));
+        let (sig2, sig4) = (sig2.unwrap(), sig4.unwrap());
+        assert_eq!(sig2.r, sig4.r);
+        assert_eq!(sig2.s, sig4.s);
+    }
+}
@@ -1,17 +1,47 @@
-use std::{convert::TryInto, str::FromStr};
+use std::{collections::BTreeMap, convert::TryInto, str::FromStr};
 
 use bip32::{
     ChainCode, ChildNumber, DerivationPath, ExtendedKey, ExtendedKeyAttrs, ExtendedPublicKey,
     Prefix, PrivateKey, PublicKey, XPrv, XPub, KEY_SIZE,
 };
 use curve25519_dalek::{
+    constants,
     edwards::{CompressedEdwardsY, EdwardsPoint},
     scalar::Scalar,
 };
 use hmac::{Hmac, Mac, NewMac};
 use libexception::*;
 use sha2::{Digest, Sha512};
+use zeroize::{Zeroize, Zeroizing};
 
+/// Exception name thrown when a derivation path has more segments than the
+/// max depth (255) of an extended key, as opposed to a malformed path string.
+pub const HdPathTooDeepException: &str = "HdPathTooDeepException";
+
+/// Exception name thrown when the parent or a derived public key is of small
+/// order (e.g. the identity), against which signatures are trivially forged.
+pub const HdKeyException: &str = "HdKeyException";
+
+/// Exception name thrown when a chain code string is not 32 bytes of hex or base58.
+pub const ChainCodeFormatException: &str = "ChainCodeFormatException";
+
+/// Everything known about the child node at the end of a derivation.
+#[derive(Clone, Debug)]
+pub struct HdDeriveResult {
+    pub tweak_sk: Scalar,
+    pub child_pk: EdwardsPoint,
+    /// Chain code of the child, to further derive from it without re-walking.
+    pub chain_code: ChainCode,
+    pub depth: u8,
+    pub parent_fingerprint: [u8; 4],
+}
+
+/// The tweak to add to the signing key, and the child key
+/// $pk_{par} + tweak \ast G$, at the non-hardened `drv_path` under `parent_pk`.
+///
+/// The empty path `"m"` is a valid no-op derivation: the tweak is zero and
+/// the child is `parent_pk` itself, so signers applying that zero tweak still
+/// sign for the parent key.
 pub fn non_hardened_derive(
     drv_path: &str,
     parent_pk: &EdwardsPoint,
@@ -21,88 +51,459 @@ pub fn non_hardened_derive(
     /* child_pk: */ EdwardsPoint,
 )> {
     let HDE = "NonHardenedDerivationException";
-    let path = DerivationPath::from_str(drv_path).catch(
-        HDE,
-        &format!("String \"{}\" is not a valid derivation path", drv_path),
-    )?;
-    let encoded_par_pk = parent_pk.compress().to_bytes();
-    let par_pk_bytes: &[u8] = encoded_par_pk.as_ref();
-    assert_throw!(par_pk_bytes.len() == 32 /* formerly 33 */);
-    let mut ex_pk = ExtendedKey {
-        prefix: Prefix::XPUB,
-        attrs: ExtendedKeyAttrs {
-            parent_fingerprint: [0u8; 4],
-            child_number: ChildNumber(0u32),
-            chain_code: *chain_code,
-            depth: 0u8,
-        },
-        key_bytes: par_pk_bytes.try_into().unwrap(),
-    };
-    let mut pk: ExtendedPublicKey<EdwardsPoint> = XPub::try_from(ex_pk.clone()).catch(
-        HDE,
-        &format!("Cannot create XPub from ex_pk_b58={}", &ex_pk.to_string()),
-    )?;
-    let ex_sk = ExtendedKey {
-        prefix: Prefix::XPRV,
-        attrs: ExtendedKeyAttrs {
-            parent_fingerprint: [0u8; 4],
-            child_number: ChildNumber(0u32),
-            chain_code: *chain_code,
-            depth: 0u8,
-        },
-        // key_bytes: [
-        //     1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-        //     0, 0,
-        // ],
-        key_bytes: Scalar::one().to_bytes(), // equivalent to the above byte array
-    };
-    let scalar_one = XPrv::try_from(ex_sk.clone()).catch(
+    let path = parse_drv_path(drv_path, HDE)?;
+    let res = derive_along_path(&path, parent_pk, chain_code, false, HDE)?;
+    Ok((res.tweak_sk, res.child_pk))
+}
+
+/// Same as `non_hardened_derive`, but also accepts hardened child numbers.
+///
+/// For a hardened child, the HMAC is fed with `0x00 || total_tweak || index`
+/// instead of the parent public key, i.e. private CKD where the running tweak
+/// stands in for the parent private key. Since the tweak is computed from
+/// public data only, every signer derives the same value -- this keeps
+/// BIP44-style paths like `m/44'/501'/0'/0` usable for threshold keys, but it
+/// does NOT hide siblings from someone who knows `(parent_pk, chain_code)`.
+pub fn hd_derive(
+    drv_path: &str,
+    parent_pk: &EdwardsPoint,
+    chain_code: &ChainCode,
+) -> Outcome<(
+    /* tweak_sk: */ Scalar,
+    /* child_pk: */ EdwardsPoint,
+)> {
+    let res = hd_derive_full(drv_path, parent_pk, chain_code)?;
+    Ok((res.tweak_sk, res.child_pk))
+}
+
+/// Same as `hd_derive`, but also returns chain code, depth and parent
+/// fingerprint of the child, e.g. to build an xpub for it.
+pub fn hd_derive_full(
+    drv_path: &str,
+    parent_pk: &EdwardsPoint,
+    chain_code: &ChainCode,
+) -> Outcome<HdDeriveResult> {
+    let HDE = "HdDerivationException";
+    let path = parse_drv_path(drv_path, HDE)?;
+    hd_derive_full_path(&path, parent_pk, chain_code)
+}
+
+/// Same as `hd_derive`, but takes an already parsed `path`,
+/// e.g. one bumped by `with_last_index` in a loop.
+pub fn hd_derive_path(
+    path: &DerivationPath,
+    parent_pk: &EdwardsPoint,
+    chain_code: &ChainCode,
+) -> Outcome<(
+    /* tweak_sk: */ Scalar,
+    /* child_pk: */ EdwardsPoint,
+)> {
+    let res = hd_derive_full_path(path, parent_pk, chain_code)?;
+    Ok((res.tweak_sk, res.child_pk))
+}
+
+/// Same as `hd_derive_full`, but takes an already parsed `path`.
+pub fn hd_derive_full_path(
+    path: &DerivationPath,
+    parent_pk: &EdwardsPoint,
+    chain_code: &ChainCode,
+) -> Outcome<HdDeriveResult> {
+    derive_along_path(path, parent_pk, chain_code, true, "HdDerivationException")
+}
+
+/// Base58 `xpub` of the child at `drv_path`, for import into wallet tooling.
+/// `"m"` gives the xpub of `parent_pk` itself.
+///
+/// There is no xprv counterpart: the private key is split across parties.
+pub fn export_xpub(
+    drv_path: &str,
+    parent_pk: &EdwardsPoint,
+    chain_code: &ChainCode,
+) -> Outcome<String> {
+    let HDE = "HdDerivationException";
+    let path = parse_drv_path(drv_path, HDE)?;
+    check_path_depth(&path)?;
+    let mut node = HdNode::root(parent_pk, chain_code, HDE)?;
+    for ccnum in path.iter() {
+        node = node.derive_child(ccnum, true, HDE)?;
+    }
+    Ok(node.pk.to_string(Prefix::XPUB))
+}
+
+/// `path` with its last child number replaced by `index`, hardened or not
+/// like the one it replaces. Turns `m/0/0/0` into `m/0/0/1` without reparsing.
+pub fn with_last_index(path: &DerivationPath, index: u32) -> Outcome<DerivationPath> {
+    let HDE = "HdDerivationException";
+    let last = path
+        .iter()
+        .last()
+        .ifnone(HDE, "Path \"m\" has no child number")?;
+    let mut res = path.parent().ifnone_()?;
+    let ccnum = ChildNumber::new(index, last.is_hardened())
+        .catch(HDE, format!("Index {} is out of range", index))?;
+    res.push(ccnum);
+    Ok(res)
+}
+
+/// Which HD scheme `hd_derive_with` should run.
+pub enum DeriveScheme<'a> {
+    /// Additive tweak on a (threshold) public key, see `hd_derive`.
+    /// Yields `(tweak_sk, child_pk)`.
+    Custom {
+        parent_pk: &'a EdwardsPoint,
+        chain_code: &'a ChainCode,
+    },
+    /// Standard SLIP-0010 ed25519 from a seed, see `hd_derive_slip10`.
+    /// Yields `(child_sk, child_pk)`.
+    Slip10Ed25519 { seed: &'a [u8] },
+}
+
+pub fn hd_derive_with(
+    scheme: DeriveScheme,
+    drv_path: &str,
+) -> Outcome<(
+    /* tweak_sk or child_sk: */ Scalar,
+    /* child_pk: */ EdwardsPoint,
+)> {
+    match scheme {
+        DeriveScheme::Custom {
+            parent_pk,
+            chain_code,
+        } => hd_derive(drv_path, parent_pk, chain_code),
+        DeriveScheme::Slip10Ed25519 { seed } => hd_derive_slip10(seed, drv_path),
+    }
+}
+
+/// SLIP-0010 ed25519 derivation, compatible with Ledger/Trezor style wallets.
+///
+/// Unlike `hd_derive`, this works on a single-party seed, and the child secret
+/// is NOT the parent secret plus a tweak -- so it cannot be applied to a
+/// threshold key. Only hardened child numbers are defined for ed25519.
+///
+/// Returns the ed25519 signing scalar (the clamped lower half of
+/// `SHA512(k)`, reduced mod l), together with its public key.
+pub fn hd_derive_slip10(
+    seed: &[u8],
+    drv_path: &str,
+) -> Outcome<(
+    /* child_sk: */ Scalar,
+    /* child_pk: */ EdwardsPoint,
+)> {
+    let HDE = "Slip10DerivationException";
+    assert_throw!(
+        (16..=64).contains(&seed.len()),
         HDE,
-        &format!("Cannot create XPrv from ex_sk_b58={}", &ex_sk.to_string()),
-    )?;
-    let mut total_tweak = scalar_one.private_key().clone();
+        format!("Seed has {} bytes, expected 16 to 64", seed.len())
+    );
+    let path = parse_drv_path(drv_path, HDE)?;
+    check_path_depth(&path)?;
+
+    let mut result = hmac_sha512(b"ed25519 seed", &[seed], HDE)?;
+    for ccnum in path.iter() {
+        assert_throw!(
+            ccnum.is_hardened(),
+            HDE,
+            format!("child number {} is not hardened", &ccnum)
+        );
+        let (key, chain_code) = result.split_at(KEY_SIZE);
+        result = hmac_sha512(chain_code, &[&[0u8], key, &ccnum.to_bytes()], HDE)?;
+    }
+
+    // RFC 8032 key expansion of the 32-byte private key `k`.
+    let mut h = Sha512::digest(&result[..KEY_SIZE]);
+    let mut bits = Zeroizing::new([0u8; 32]);
+    bits.copy_from_slice(&h[..32]);
+    h.as_mut_slice().zeroize();
+    bits[0] &= 248;
+    bits[31] &= 127;
+    bits[31] |= 64;
+    let child_sk = Scalar::from_bytes_mod_order(*bits);
+    let child_pk = &constants::ED25519_BASEPOINT_TABLE * &child_sk;
+
+    Ok((child_sk, child_pk))
+}
+
+fn hmac_sha512(key: &[u8], data: &[&[u8]], HDE: &str) -> Outcome<Zeroizing<[u8; 64]>> {
+    let mut hmac: Hmac<Sha512> =
+        Hmac::new_from_slice(key).catch(HDE, "Invalid HMAC SHA512 bytes")?;
+    for d in data {
+        hmac.update(d);
+    }
+    let mut result = Zeroizing::new([0u8; 64]);
+    let mut out = hmac.finalize().into_bytes();
+    result.copy_from_slice(&out);
+    out.as_mut_slice().zeroize();
+    Ok(result)
+}
+
+/// Derive many paths from the same parent at once, returning results in the
+/// same order as `drv_paths`. Nodes on a shared prefix (e.g. `m/0/1` for both
+/// `m/0/1/2` and `m/0/1/3`) are only derived once.
+pub fn hd_derive_batch(
+    drv_paths: &[&str],
+    parent_pk: &EdwardsPoint,
+    chain_code: &ChainCode,
+) -> Outcome<Vec<HdDeriveResult>> {
+    let HDE = "HdDerivationException";
+    let root = HdNode::root(parent_pk, chain_code, HDE)?;
+    let mut cache: BTreeMap<Vec<ChildNumber>, HdNode> = BTreeMap::new();
+    let mut results = Vec::with_capacity(drv_paths.len());
+    for (idx, drv_path) in drv_paths.iter().enumerate() {
+        let errmsg = format!("Failed to derive path #{} \"{}\"", idx, drv_path);
+        let path = DerivationPath::from_str(drv_path).catch(HDE, &errmsg)?;
+        check_path_depth(&path).catch(HdPathTooDeepException, &errmsg)?;
+        let ccnums = path.as_ref();
+
+        // resume from the deepest cached ancestor
+        let mut start = 0;
+        let mut node = root.clone();
+        for depth in (1..=ccnums.len()).rev() {
+            if let Some(cached) = cache.get(&ccnums[..depth]) {
+                start = depth;
+                node = cached.clone();
+                break;
+            }
+        }
+        for depth in start..ccnums.len() {
+            node = node
+                .derive_child(ccnums[depth], true, HDE)
+                .catch(HDE, &errmsg)?;
+            cache.insert(ccnums[..=depth].to_vec(), node.clone());
+        }
+        results.push(node.finish(HDE).catch(HDE, &errmsg)?);
+    }
+    Ok(results)
+}
+
+fn derive_along_path(
+    path: &DerivationPath,
+    parent_pk: &EdwardsPoint,
+    chain_code: &ChainCode,
+    allow_hardened: bool,
+    HDE: &str,
+) -> Outcome<HdDeriveResult> {
+    check_path_depth(path)?;
+    let mut node = HdNode::root(parent_pk, chain_code, HDE)?;
     for ccnum in path.as_ref() {
+        node = node.derive_child(*ccnum, allow_hardened, HDE)?;
+    }
+    node.finish(HDE)
+}
+
+/// Split a derivation path into `(index, is_hardened)` per segment, e.g. for a
+/// policy layer to reject paths below a hardened account boundary.
+/// `"m/44'/501'/0"` yields `[(44, true), (501, true), (0, false)]`.
+pub fn parse_and_classify_path(drv_path: &str) -> Outcome<Vec<(u32, bool)>> {
+    let path = parse_drv_path(drv_path, "HdDerivationException")?;
+    let res = path
+        .iter()
+        .map(|ccnum| (ccnum.index(), ccnum.is_hardened()))
+        .collect();
+    Ok(res)
+}
+
+fn parse_drv_path(drv_path: &str, HDE: &str) -> Outcome<DerivationPath> {
+    DerivationPath::from_str(drv_path).catch(
+        HDE,
+        format!("String \"{}\" is not a valid derivation path", drv_path),
+    )
+}
+
+fn check_path_depth(path: &DerivationPath) -> Outcome<()> {
+    let max_depth = XPrv::MAX_DEPTH as usize;
+    assert_throw!(
+        path.len() <= max_depth,
+        HdPathTooDeepException,
+        format!(
+            "Derivation path has {} segments, at most {} are allowed",
+            path.len(),
+            max_depth
+        )
+    );
+    Ok(())
+}
+
+/// A node of the derivation tree: its xpub, plus the tweak accumulated from
+/// the root down to it. The tweak is zeroized when the node is dropped,
+/// including when a derivation throws halfway.
+#[derive(Clone)]
+struct HdNode {
+    pk: ExtendedPublicKey<EdwardsPoint>,
+    total_tweak: Scalar,
+}
+
+impl Drop for HdNode {
+    fn drop(&mut self) {
+        self.total_tweak.zeroize();
+    }
+}
+
+impl HdNode {
+    fn root(parent_pk: &EdwardsPoint, chain_code: &ChainCode, HDE: &str) -> Outcome<Self> {
+        assert_throw!(
+            !parent_pk.is_small_order(),
+            HdKeyException,
+            "Parent public key is the identity or another small-order point"
+        );
+        let encoded_par_pk = parent_pk.compress().to_bytes();
+        let par_pk_bytes: &[u8] = encoded_par_pk.as_ref();
+        assert_throw!(par_pk_bytes.len() == 32 /* formerly 33 */);
+        let ex_pk = ExtendedKey {
+            prefix: Prefix::XPUB,
+            attrs: ExtendedKeyAttrs {
+                parent_fingerprint: [0u8; 4],
+                child_number: ChildNumber(0u32),
+                chain_code: *chain_code,
+                depth: 0u8,
+            },
+            key_bytes: par_pk_bytes.try_into().unwrap(),
+        };
+        let pk: ExtendedPublicKey<EdwardsPoint> = XPub::try_from(ex_pk.clone()).catch(
+            HDE,
+            &format!("Cannot create XPub from ex_pk_b58={}", &ex_pk.to_string()),
+        )?;
+        let ex_sk = ExtendedKey {
+            prefix: Prefix::XPRV,
+            attrs: ExtendedKeyAttrs {
+                parent_fingerprint: [0u8; 4],
+                child_number: ChildNumber(0u32),
+                chain_code: *chain_code,
+                depth: 0u8,
+            },
+            // key_bytes: [
+            //     1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            //     0, 0,
+            // ],
+            key_bytes: Scalar::one().to_bytes(), // equivalent to the above byte array
+        }; // `ExtendedKey` zeroizes `key_bytes` on drop
+        let scalar_one = XPrv::try_from(ex_sk.clone()).catch(
+            HDE,
+            &format!("Cannot create XPrv from ex_sk_b58={}", &ex_sk.to_string()),
+        )?;
+        let total_tweak = scalar_one.private_key().clone();
+        Ok(Self { pk, total_tweak })
+    }
+
+    fn derive_child(&self, ccnum: ChildNumber, allow_hardened: bool, HDE: &str) -> Outcome<Self> {
+        let pk = &self.pk;
         let depth: u8 = pk
             .attrs()
             .depth
             .checked_add(1)
             .ifnone(HDE, "Invalid depth")?;
-        let mut hmac: Hmac<Sha512> =
-            Hmac::new_from_slice(&pk.attrs().chain_code).catch(HDE, "Invalid HMAC SHA512 bytes")?;
-        assert_throw!(
-            false == ccnum.is_hardened(),
+        let par_key_bytes = match ccnum.is_hardened() {
+            true => {
+                assert_throw!(
+                    allow_hardened,
+                    HDE,
+                    &format!("child number {} is hardened", &ccnum)
+                );
+                Zeroizing::new(self.total_tweak.to_bytes())
+            }
+            false => Zeroizing::new(pk.public_key().to_bytes()),
+        };
+        // hardened children are prefixed with 0x00, as in BIP32 private CKD
+        let prefix: &[u8] = match ccnum.is_hardened() {
+            true => &[0u8],
+            false => &[],
+        };
+        let result = hmac_sha512(
+            &pk.attrs().chain_code,
+            &[prefix, par_key_bytes.as_ref(), &ccnum.to_bytes()],
             HDE,
-            &format!("child number {} is hardened", &ccnum)
-        );
-        hmac.update(&pk.public_key().to_bytes());
-        hmac.update(&ccnum.to_bytes());
-        let result = hmac.finalize().into_bytes();
+        )?;
         let (tweak, chain_code) = result.split_at(KEY_SIZE);
         assert_throw!(tweak.len() == 32);
         assert_throw!(chain_code.len() == 32);
-        let public_key = pk.public_key().derive_child(tweak.try_into().unwrap());
-        total_tweak = total_tweak.derive_child(tweak.try_into().unwrap());
+        let tweak: Zeroizing<[u8; 32]> = Zeroizing::new(tweak.try_into().unwrap());
+        // $pk_{child} = pk_{par} + tweak * G$, in sync with `total_tweak` below.
+        let public_key = pk.public_key().derive_child(*tweak);
+        assert_throw!(
+            !public_key.is_small_order(),
+            HdKeyException,
+            &format!("Child public key at {} is of small order", &ccnum)
+        );
+        let total_tweak = self.total_tweak.derive_child(*tweak);
 
-        ex_pk = ExtendedKey {
+        let ex_pk = ExtendedKey {
             prefix: Prefix::XPUB,
             attrs: ExtendedKeyAttrs {
                 parent_fingerprint: pk.public_key().fingerprint(),
-                child_number: *ccnum,
+                child_number: ccnum,
                 chain_code: chain_code.try_into().unwrap(),
                 depth,
             },
             key_bytes: public_key.to_bytes(),
         };
 
-        pk = XPub::try_from(ex_pk).catch(HDE, "")?;
+        let pk = XPub::try_from(ex_pk).catch(HDE, "")?;
+        Ok(Self { pk, total_tweak })
+    }
+
+    fn finish(&self, HDE: &str) -> Outcome<HdDeriveResult> {
+        let pk = &self.pk;
+        let tweak_sk: Scalar =
+            Scalar::from_bytes_mod_order(self.total_tweak.to_bytes()) - Scalar::one();
+        let child_pk: EdwardsPoint = CompressedEdwardsY::from_slice(&pk.public_key().to_bytes())
+            .decompress()
+            .ifnone(HDE, "Public key is not compressed Edwards point")?;
+
+        let attrs = pk.attrs();
+        Ok(HdDeriveResult {
+            tweak_sk,
+            child_pk,
+            chain_code: attrs.chain_code,
+            depth: attrs.depth,
+            parent_fingerprint: attrs.parent_fingerprint,
+        })
     }
+}
+
+/// The master chain code of `seed` as SLIP-0010 defines it for ed25519:
+/// the right half of `HMAC-SHA512(key = "ed25519 seed", seed)`, i.e. the
+/// chain code `hd_derive_slip10` starts from. Pass it to `hd_derive` and
+/// `algo_sign_hd` instead of inventing one, or `eval_chain_code` to derive
+/// it from the group key alone. SLIP-0010 calls for a seed of 16 to 64 bytes,
+/// but any length hashes.
+pub fn master_chain_code_from_seed(seed: &[u8]) -> ChainCode {
+    // A fixed key of any length makes a valid HMAC.
+    let result = hmac_sha512(b"ed25519 seed", &[seed], "").unwrap();
+    result[KEY_SIZE..].try_into().unwrap()
+}
+
+/// Parse a chain code from 64 hex digits, e.g. from a config file,
+/// as `chain_code_to_hex` formats it.
+pub fn chain_code_from_hex(s: &str) -> Outcome<ChainCode> {
+    let bytes = hex::decode(s).catch(
+        ChainCodeFormatException,
+        format!("Chain code \"{}\" is not hex", s),
+    )?;
+    chain_code_from_bytes(&bytes)
+}
 
-    let tweak_sk: Scalar = Scalar::from_bytes_mod_order(total_tweak.to_bytes()) - Scalar::one();
-    let child_pk: EdwardsPoint = CompressedEdwardsY::from_slice(&pk.public_key().to_bytes())
-        .decompress()
-        .ifnone(HDE, "Public key is not compressed Edwards point")?;
+pub fn chain_code_to_hex(chain_code: &ChainCode) -> String {
+    hex::encode(chain_code)
+}
+
+/// Parse a chain code from base58 (bitcoin alphabet, no checksum),
+/// as `chain_code_to_base58` formats it.
+pub fn chain_code_from_base58(s: &str) -> Outcome<ChainCode> {
+    let bytes = bs58::decode(s).into_vec().catch(
+        ChainCodeFormatException,
+        format!("Chain code \"{}\" is not base58", s),
+    )?;
+    chain_code_from_bytes(&bytes)
+}
+
+pub fn chain_code_to_base58(chain_code: &ChainCode) -> String {
+    bs58::encode(chain_code).into_string()
+}
 
-    Ok((tweak_sk, child_pk))
+fn chain_code_from_bytes(bytes: &[u8]) -> Outcome<ChainCode> {
+    bytes.try_into().ok().ifnone(
+        ChainCodeFormatException,
+        format!("Chain code has {} bytes, not {}", bytes.len(), KEY_SIZE),
+    )
 }
 
 pub fn eval_chain_code(pk: &EdwardsPoint) -> ChainCode {
@@ -114,3 +515,341 @@ pub fn eval_chain_code(pk: &EdwardsPoint) -> ChainCode {
         .unwrap();
     chain_code
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use curve25519_dalek::traits::Identity;
+
+    #[test]
+    fn chain_code_parses_from_hex_and_base58() {
+        let chain_code = eval_chain_code(&constants::ED25519_BASEPOINT_POINT);
+        let hex_str = chain_code_to_hex(&chain_code);
+        assert_eq!(hex_str.len(), 64);
+        assert_eq!(chain_code_from_hex(&hex_str).unwrap(), chain_code);
+        assert_eq!(
+            chain_code_from_hex(&hex_str.to_uppercase()).unwrap(),
+            chain_code
+        );
+        let b58 = chain_code_to_base58(&chain_code);
+        assert_eq!(chain_code_from_base58(&b58).unwrap(), chain_code);
+
+        let rejections = [
+            chain_code_from_hex(&hex_str[..62]),
+            chain_code_from_hex(&format!("{}zz", &hex_str[..62])),
+            chain_code_from_hex(&hex_str[..63]),
+            chain_code_from_base58("0OIl"),
+            chain_code_from_base58(&bs58::encode([1u8; 31]).into_string()),
+            chain_code_from_base58(&bs58::encode([1u8; 33]).into_string()),
+            chain_code_from_base58(""),
+        ];
+        for res in rejections {
+            assert_eq!(res.unwrap_err().get_name(), ChainCodeFormatException);
+        }
+        let err = chain_code_from_hex(&hex_str[..62]).unwrap_err();
+        assert!(err.get_context().unwrap().contains("has 31 bytes, not 32"));
+    }
+
+    #[test]
+    fn hardened_step_follows_documented_formula() {
+        // Computed by hand: the tweak of `m/7'` is $I_L$ of
+        // HMAC-SHA512(chain_code, 0x00 || total_tweak || index), where the
+        // running tweak of the root is 1.
+        let par_pk = constants::ED25519_BASEPOINT_POINT;
+        let chain_code = [3u8; 32];
+        let mut hmac: Hmac<Sha512> = Hmac::new_from_slice(&chain_code).unwrap();
+        hmac.update(&[0u8]);
+        hmac.update(&Scalar::one().to_bytes());
+        hmac.update(&(7u32 | 0x8000_0000).to_be_bytes());
+        let mut i_l = [0u8; 32];
+        i_l.copy_from_slice(&hmac.finalize().into_bytes()[..32]);
+        let expected = Scalar::from_bytes_mod_order(i_l);
+
+        let (tweak_sk, child_pk) = hd_derive("m/7'", &par_pk, &chain_code).unwrap();
+        assert_eq!(tweak_sk, expected);
+        assert_eq!(
+            child_pk,
+            par_pk + &constants::ED25519_BASEPOINT_TABLE * &expected
+        );
+    }
+
+    #[test]
+    fn hardened_matches_private_ckd() {
+        // With parent secret key 1, the running tweak IS the child secret key,
+        // so `hd_derive` must agree with the additive private CKD of `XPrv`
+        // along a whole path.
+        //
+        // There is no SLIP-0010 vector to check against: SLIP-0010 ed25519
+        // takes $I_L$ itself as the child secret, while here the child secret
+        // is the parent secret plus $I_L$, which is what lets each signer
+        // shift its share. `slip10_ed25519_vectors` covers SLIP-0010 itself.
+        let par_pk = constants::ED25519_BASEPOINT_POINT;
+        let chain_code = eval_chain_code(&par_pk);
+        let drv_path = "m/44'/501'/0'/0";
+        let (tweak_sk, child_pk) = hd_derive(drv_path, &par_pk, &chain_code).unwrap();
+
+        let root = XPrv::try_from(ExtendedKey {
+            prefix: Prefix::XPRV,
+            attrs: ExtendedKeyAttrs {
+                parent_fingerprint: [0u8; 4],
+                child_number: ChildNumber(0u32),
+                chain_code,
+                depth: 0u8,
+            },
+            key_bytes: Scalar::one().to_bytes(),
+        })
+        .unwrap();
+        let child = DerivationPath::from_str(drv_path)
+            .unwrap()
+            .iter()
+            .fold(root, |xprv, ccnum| xprv.derive_child(ccnum).unwrap());
+
+        assert_eq!(Scalar::one() + tweak_sk, *child.private_key());
+        assert_eq!(
+            child_pk,
+            &constants::ED25519_BASEPOINT_TABLE * child.private_key()
+        );
+    }
+
+    #[test]
+    fn full_result_continues_derivation() {
+        let par_pk = constants::ED25519_BASEPOINT_POINT;
+        let chain_code = eval_chain_code(&par_pk);
+        let whole = hd_derive_full("m/1/14/514", &par_pk, &chain_code).unwrap();
+        assert_eq!(whole.depth, 3);
+
+        let mid = hd_derive_full("m/1/14", &par_pk, &chain_code).unwrap();
+        let rest = hd_derive_full("m/514", &mid.child_pk, &mid.chain_code).unwrap();
+        assert_eq!(rest.child_pk, whole.child_pk);
+        assert_eq!(rest.chain_code, whole.chain_code);
+        assert_eq!(mid.tweak_sk + rest.tweak_sk, whole.tweak_sk);
+        assert_eq!(
+            (whole.tweak_sk, whole.child_pk),
+            hd_derive("m/1/14/514", &par_pk, &chain_code).unwrap()
+        );
+    }
+
+    #[test]
+    fn parsed_path_matches_string() {
+        let par_pk = constants::ED25519_BASEPOINT_POINT;
+        let chain_code = eval_chain_code(&par_pk);
+        let mut path = DerivationPath::from_str("m/0/0/0").unwrap();
+        for i in 0..4u32 {
+            path = with_last_index(&path, i).unwrap();
+            let drv_path = format!("m/0/0/{}", i);
+            assert_eq!(path.to_string(), drv_path);
+            assert_eq!(
+                hd_derive_path(&path, &par_pk, &chain_code).unwrap(),
+                hd_derive(&drv_path, &par_pk, &chain_code).unwrap()
+            );
+        }
+
+        let hardened = DerivationPath::from_str("m/44'/501'").unwrap();
+        assert_eq!(
+            with_last_index(&hardened, 7).unwrap().to_string(),
+            "m/44'/7'"
+        );
+        let root = DerivationPath::from_str("m").unwrap();
+        assert!(with_last_index(&root, 1).is_err());
+        assert!(with_last_index(&hardened, 1 << 31).is_err());
+    }
+
+    #[test]
+    fn exported_xpub_parses_back() {
+        let par_pk = constants::ED25519_BASEPOINT_POINT * Scalar::from(7u64);
+        let chain_code = eval_chain_code(&par_pk);
+        for drv_path in ["m", "m/0/1", "m/44'/501'/0'"] {
+            let xpub = export_xpub(drv_path, &par_pk, &chain_code).unwrap();
+            assert!(xpub.starts_with("xpub"), "{}", xpub);
+            let parsed = XPub::from_str(&xpub).unwrap();
+            let res = hd_derive_full(drv_path, &par_pk, &chain_code).unwrap();
+            assert_eq!(
+                parsed.public_key().compress().to_bytes(),
+                res.child_pk.compress().to_bytes()
+            );
+            assert_eq!(parsed.attrs().chain_code, res.chain_code);
+            assert_eq!(parsed.attrs().depth, res.depth);
+        }
+    }
+
+    #[test]
+    fn identity_parent_key_is_rejected() {
+        let par_pk = EdwardsPoint::identity();
+        let chain_code = [7u8; 32];
+        let err = hd_derive("m/0", &par_pk, &chain_code).unwrap_err();
+        assert_eq!(err.get_name(), HdKeyException);
+        let err = hd_derive_batch(&["m/1"], &par_pk, &chain_code).unwrap_err();
+        assert_eq!(err.get_name(), HdKeyException);
+        assert!(export_xpub("m", &par_pk, &chain_code).is_err());
+
+        // any other torsion point is just as degenerate
+        let torsion = constants::EIGHT_TORSION[1];
+        let err = hd_derive("m/0", &torsion, &chain_code).unwrap_err();
+        assert_eq!(err.get_name(), HdKeyException);
+    }
+
+    #[test]
+    fn batch_matches_one_by_one() {
+        let par_pk = constants::ED25519_BASEPOINT_POINT;
+        let chain_code = eval_chain_code(&par_pk);
+        let drv_paths = ["m/0/1/2", "m/0/1/3", "m/44'/501'", "m", "m/0/1"];
+        let batch = hd_derive_batch(&drv_paths, &par_pk, &chain_code).unwrap();
+        assert_eq!(batch.len(), drv_paths.len());
+        for (drv_path, res) in drv_paths.iter().zip(batch.iter()) {
+            let single = hd_derive_full(drv_path, &par_pk, &chain_code).unwrap();
+            assert_eq!(res.tweak_sk, single.tweak_sk);
+            assert_eq!(res.child_pk, single.child_pk);
+            assert_eq!(res.chain_code, single.chain_code);
+        }
+
+        let ex = hd_derive_batch(&["m/0", "m/x"], &par_pk, &chain_code).unwrap_err();
+        assert!(ex.get_context().unwrap().contains("#1 \"m/x\""));
+
+        let too_deep = format!("m{}", "/0".repeat(256));
+        let ex = hd_derive_batch(&["m/0", &too_deep], &par_pk, &chain_code).unwrap_err();
+        assert_eq!(ex.get_name(), HdPathTooDeepException);
+        assert!(ex.get_context().unwrap().contains("#1 \"m/0/0"));
+    }
+
+    #[test]
+    fn too_deep_path_is_rejected() {
+        let par_pk = constants::ED25519_BASEPOINT_POINT;
+        let chain_code = eval_chain_code(&par_pk);
+        let path_of_len = |n: usize| format!("m{}", "/0".repeat(n));
+
+        assert!(hd_derive(&path_of_len(255), &par_pk, &chain_code).is_ok());
+        let ex = hd_derive(&path_of_len(256), &par_pk, &chain_code).unwrap_err();
+        assert_eq!(ex.get_name(), HdPathTooDeepException);
+        assert!(ex.get_context().unwrap().contains("256 segments"));
+
+        let ex = hd_derive("m/0/zero", &par_pk, &chain_code).unwrap_err();
+        assert_ne!(ex.get_name(), HdPathTooDeepException);
+    }
+
+    #[test]
+    fn known_tweak_survives_zeroization() {
+        let par_pk = constants::ED25519_BASEPOINT_POINT;
+        let chain_code = eval_chain_code(&par_pk);
+        let (tweak_sk, _) = hd_derive("m/1/14/514", &par_pk, &chain_code).unwrap();
+        assert_eq!(
+            hex::encode(tweak_sk.to_bytes()),
+            "9fe9b404d42127a9c58490682d8889fb138d93a0a6ee7b6934fedb51937cdb0e"
+        );
+    }
+
+    #[test]
+    fn slip10_ed25519_vectors() {
+        // SLIP-0010 test vector 1 for ed25519, public keys without the 0x00 prefix
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let vectors = [
+            (
+                "m",
+                "a4b2856bfec510abab89753fac1ac0e1112364e7d250545963f135f2a33188ed",
+            ),
+            (
+                "m/0'",
+                "8c8a13df77a28f3445213a0f432fde644acaa215fc72dcdf300d5efaa85d350c",
+            ),
+            (
+                "m/0'/1'",
+                "1932a5270f335bed617d5b935c80aedb1a35bd9fc1e31acafd5372c30f5c1187",
+            ),
+            (
+                "m/0'/1'/2'",
+                "ae98736566d30ed0e9d2f4486a64bc95740d89c7db33f52121f8ea8f76ff0fc1",
+            ),
+            (
+                "m/0'/1'/2'/2'",
+                "8abae2d66361c879b900d204ad2cc4984fa2aa344dd7ddc46007329ac76c429c",
+            ),
+            (
+                "m/0'/1'/2'/2'/1000000000'",
+                "3c24da049451555d51a7014a37337aa4e12d41e485abccfa46b47dfb2af54b7a",
+            ),
+        ];
+        for (drv_path, pk_hex) in vectors {
+            let (child_sk, child_pk) =
+                hd_derive_with(DeriveScheme::Slip10Ed25519 { seed: &seed }, drv_path).unwrap();
+            assert_eq!(hex::encode(child_pk.compress().to_bytes()), pk_hex);
+            assert_eq!(child_pk, &constants::ED25519_BASEPOINT_TABLE * &child_sk);
+        }
+
+        // ... and its master chain code
+        assert_eq!(
+            hex::encode(master_chain_code_from_seed(&seed)),
+            "90046a93de5380a72b5e45010748567d5ea02bbf6522f979e05c0d8d8ca9fffb"
+        );
+
+        assert!(hd_derive_slip10(&seed, "m/0'/1").is_err());
+        assert!(hd_derive_slip10(&seed[..8], "m/0'").is_err());
+    }
+
+    #[test]
+    fn non_hardened_reference_vectors() {
+        // Pinned from a reference run, with the parent key 7G.
+        let par_pk = &constants::ED25519_BASEPOINT_TABLE * &Scalar::from(7u64);
+        let chain_code = [42u8; 32];
+        let vectors = [
+            (
+                "m/0",
+                "5a3ed33a62e2969cb7ede6c442ff60eb4dd149e6afeb54436fbfb004ecaffe02",
+                "c501ecfa2f4847ba32453743ee30671b586274b5d7177f43bfbddf5721350945",
+            ),
+            (
+                "m/1/2/3",
+                "d78836d4d9903413f47d721b83d31f8467ddc2ddef4f625c0af90e01bc45bd0f",
+                "153220d2f02a68c1439bedcec4a7e71c37493418c7340da8935338cd0a9f0b31",
+            ),
+            (
+                "m",
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                "b862409fb5c4c4123df2abf7462b88f041ad36dd6864ce872fd5472be363c5b1",
+            ),
+        ];
+        for (drv_path, tweak_hex, child_hex) in vectors {
+            let (tweak_sk, child_pk) = non_hardened_derive(drv_path, &par_pk, &chain_code).unwrap();
+            assert_eq!(hex::encode(tweak_sk.to_bytes()), tweak_hex, "{}", drv_path);
+            assert_eq!(hex::encode(child_pk.compress().to_bytes()), child_hex);
+            assert_eq!(
+                child_pk,
+                par_pk + &constants::ED25519_BASEPOINT_TABLE * &tweak_sk
+            );
+            let (tweak_hd, child_hd) = hd_derive(drv_path, &par_pk, &chain_code).unwrap();
+            assert_eq!((tweak_hd, child_hd), (tweak_sk, child_pk));
+        }
+    }
+
+    #[test]
+    fn empty_path_returns_the_parent() {
+        let par_pk = &constants::ED25519_BASEPOINT_TABLE * &Scalar::from(7u64);
+        let chain_code = [42u8; 32];
+        let none = (Scalar::zero(), par_pk);
+        assert_eq!(
+            non_hardened_derive("m", &par_pk, &chain_code).unwrap(),
+            none
+        );
+        assert_eq!(hd_derive("m", &par_pk, &chain_code).unwrap(), none);
+        let root = hd_derive_full("m", &par_pk, &chain_code).unwrap();
+        assert_eq!((root.tweak_sk, root.child_pk), none);
+        assert_eq!(root.chain_code, chain_code);
+        assert_eq!(root.depth, 0);
+    }
+
+    #[test]
+    fn classify_path() {
+        assert_eq!(
+            parse_and_classify_path("m/44'/501'/0").unwrap(),
+            vec![(44, true), (501, true), (0, false)]
+        );
+        assert!(parse_and_classify_path("m").unwrap().is_empty());
+        assert!(parse_and_classify_path("44/0").is_err());
+    }
+
+    #[test]
+    fn non_hardened_rejects_hardened() {
+        let par_pk = constants::ED25519_BASEPOINT_POINT;
+        let chain_code = eval_chain_code(&par_pk);
+        assert!(non_hardened_derive("m/44'/0", &par_pk, &chain_code).is_err());
+        assert!(non_hardened_derive("m/44/0", &par_pk, &chain_code).is_ok());
+    }
+}
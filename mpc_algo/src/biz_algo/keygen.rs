@@ -1,172 +1,2299 @@
 use std::collections::{HashMap, HashSet}; // keys are in ascending order to avoid deadlock.
+use std::future::{pending, Future};
+use std::pin::pin;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
+use async_trait::async_trait;
 use curve25519_dalek::edwards::EdwardsPoint;
 use curve25519_dalek::scalar::Scalar;
+use futures::future::{select, select_all, Either};
 use libexception::*;
 use mpc_spec::*;
 use rand::rngs::OsRng;
-use zeroize::Zeroize;
+use rand::{CryptoRng, RngCore};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::{debug, info};
+use zeroize::{Zeroize, Zeroizing};
 
 use super::aes::*;
+use super::retry::*;
+use super::KeyStore;
+pub use crate::frost::{
+    deal_vss_share_from_coeffs, decode_point_strict, decode_scalar_strict,
+    find_equivocating_dealers, find_invalid_zkps, generate_dkg_challenge, verify_dkg_zkp,
+    KeyGenDKGProposedCommitment, KeyGenZKP, NonCanonicalEncoding,
+};
 use crate::frost::{
-    generate_dkg_challenge, generate_vss_share, keygen_validate_peers, merge_vss_share,
-    KeyGenDKGProposedCommitment, KeyGenZKP, PartyKey,
+    decode_commitments_strict, find_invalid_shares, generate_vss_share, keygen_validate_peers,
+    merge_vss_share, PartyKey, WireDKGCommitment,
 };
 
-pub type KeyStore = MultiShard<Scalar, EdwardsPoint>;
+// Names of the exceptions thrown by `algo_keygen` and its rounds,
+// so that callers can tell whether to retry, abort or ban a peer.
+
+/// `key_arch`, `whoami` or `context` cannot make a keygen. Fix them, don't retry.
+pub const InvalidParameters: &str = "InvalidParameters";
+/// A messenger call failed. Worth retrying, e.g. with `keygen_round2_shares`.
+pub const TransportError: &str = "TransportError";
+/// A peer's commitment carries a bad proof or the wrong threshold.
+/// The context names the peers.
+pub const CommitmentVerifyFailed: &str = "CommitmentVerifyFailed";
+/// A dealer's round-1 commitment differs between members of its group.
+/// The context names the dealers.
+pub const Equivocation: &str = "Equivocation";
+/// Peers dealt me shares that do not decrypt, do not decode into a scalar,
+/// or do not match their commitments. The context lists them all, sorted,
+/// then why any did not decode.
+pub const ShareVerifyFailed: &str = "ShareVerifyFailed";
+/// A member of a group I was awaiting sent an `Abort`.
+/// The context names the member and its reason.
+pub const Aborted: &str = "Aborted";
+
+/// Largest `n_members` of a group that `algo_keygen` takes, beyond which it
+/// throws `InvalidParameters`. Member ids enter the vss polynomials and the
+/// Lagrange coefficients as scalars mod $\ell$, so even `u16::MAX` evaluates
+/// exactly; the cap is on keygen itself, whose messages grow as
+/// $n^2$ and whose work per member as $n \cdot th$.
+pub const MAX_MEMBERS: usize = 4096;
+
+/// Longest `context` of a keygen, in bytes. Every proof of knowledge hashes
+/// it, so an orchestrator cannot make the members hash without bound.
+pub const MAX_CONTEXT_LEN: usize = 1024;
+
+/// Broadcast within its group by a member whose keygen failed with
+/// `CommitmentVerifyFailed`, `Equivocation` or `ShareVerifyFailed`,
+/// so that its peers fail with `Aborted` rather than await it forever.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Abort {
+    pub reason: String,
+}
+
+const ABORT_ROUND: &str = "keygen_abort";
+
+/// Optional knobs of `algo_keygen_with_options`.
+/// `KeygenOptions::default()` gives the behavior of `algo_keygen`.
+#[derive(Clone, Debug, Default)]
+pub struct KeygenOptions {
+    /// Give up with a `KeygenTimeout` exception, naming the stalled round,
+    /// if keygen hasn't finished by then. `None` waits forever.
+    /// `Some` needs a tokio runtime with the time driver enabled.
+    pub deadline: Option<Instant>,
+    /// Cipher of the vss shares dealt in round 2.
+    /// Receivers follow whatever the dealer picked.
+    pub aead_scheme: AeadScheme,
+    /// Retries of each messenger call, all within `deadline`.
+    /// Their backoff sleeps with tokio.
+    pub sesman: SesmanConfig,
+}
+
+/// Where `algo_keygen_timed` spent its time, summed over my shards.
+/// The rounds include the time awaiting peers, which `network` sums up,
+/// so whatever is left of them is crypto.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeygenTimings {
+    /// `keygen_round1_commit`: dealing the secrets and broadcasting the commitment.
+    pub commit: Duration,
+    /// `keygen_round2_shares`: validating the commitments and dealing the shares.
+    pub shares: Duration,
+    /// `keygen_finalize`: verifying the shares dealt to me and merging them.
+    pub finalize: Duration,
+    /// Fetching and validating the commitments of the other groups.
+    pub other_groups: Duration,
+    /// Time spent awaiting the messenger in all of the above.
+    pub network: Duration,
+}
+
+/// What `algo_keygen_with_progress` is doing, reported as each phase starts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeygenPhase {
+    /// Dealing the secrets of a shard and broadcasting its commitment.
+    Commit,
+    /// Awaiting the commitments of the group, and checking that every member
+    /// got the same ones.
+    ExchangeCommitments,
+    /// Dealing the vss shares of the shard to the members of its group.
+    ExchangeShares,
+    /// Awaiting the shares dealt to the shard, verifying and merging them.
+    Finalize,
+}
+
+/// State of one shard between the keygen rounds.
+///
+/// A caller may persist it after `keygen_round1_commit` and resume with
+/// `keygen_round2_shares` after a transport failure, instead of re-running
+/// the whole keygen. It holds secrets, so persist it encrypted.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct KeygenState {
+    pub my_id: MpcAddr,
+    pub party_key: PartyKey,
+    /// The commitment I broadcast in round 1.
+    pub dkg_commitment: KeyGenDKGProposedCommitment,
+    /// The vss shares I deal to each member in round 2.
+    pub shares: HashMap<MpcAddr, Scalar>,
+    /// Validated commitments of my group, set by round 2.
+    pub vss_com_dict: Option<HashMap<MpcAddr, Vec<EdwardsPoint>>>,
+    /// The proofs that came with them, which the keystore keeps for
+    /// `KeyStoreExt::public_transcript`. States persisted before have none.
+    #[serde(default)]
+    pub zkp_dict: Option<HashMap<MpcAddr, KeyGenZKP>>,
+}
+
+impl Drop for KeygenState {
+    fn drop(&mut self) {
+        self.party_key.u_i.zeroize();
+        self.party_key.k_i.zeroize();
+        for x in self.shares.values_mut() {
+            x.zeroize();
+        }
+        #[cfg(test)]
+        count_zeroized(2 + self.shares.len());
+    }
+}
+
+/// The shards `run_keygen` has merged so far, zeroized unless it returns
+/// them, e.g. when the keygen future is dropped while awaiting another group.
+#[derive(Default)]
+struct MergedShards(KeyStore);
+
+impl Drop for MergedShards {
+    fn drop(&mut self) {
+        let ks = &mut self.0;
+        #[cfg(test)]
+        count_zeroized(ks.ui_pergroup.len() + ks.xi_pergroup.len());
+        for x in ks.ui_pergroup.values_mut() {
+            x.zeroize();
+        }
+        for x in ks.xi_pergroup.values_mut() {
+            x.zeroize();
+        }
+    }
+}
 
+#[cfg(test)]
+thread_local! {
+    /// Secret scalars zeroized by the drop guards on this thread,
+    /// for the tests to tell that a dropped keygen cleaned up.
+    static ZEROIZED: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+#[cfg(test)]
+fn count_zeroized(n: usize) {
+    ZEROIZED.with(|count| count.set(count.get() + n));
+}
+
+/// Runs under any executor: the rounds spawn no task and only await the
+/// `messenger`. Only a `KeygenOptions::deadline` or `sesman.retries` of
+/// `algo_keygen_with_options` needs tokio, for its timers.
+/// A group has at most `MAX_MEMBERS` members.
 pub async fn algo_keygen(
     messenger: &impl Messenger,
     key_arch: &HashMap<u16 /*group_id*/, (usize /*th*/, HashSet<MpcAddr>)>,
     whoami: &[MpcAddr], // My shard_ids
     context: &str,      // Other parties challenge against this ctx
 ) -> Outcome<KeyStore> {
-    let mut keystore = KeyStore::default();
+    let opts = KeygenOptions::default();
+    algo_keygen_with_options(messenger, key_arch, whoami, context, &opts).await
+}
+
+/// `algo_keygen` driven to completion on the current thread, for callers
+/// without an async runtime. It blocks until every peer has done its part.
+pub fn algo_keygen_blocking(
+    messenger: &impl Messenger,
+    key_arch: &HashMap<u16 /*group_id*/, (usize /*th*/, HashSet<MpcAddr>)>,
+    whoami: &[MpcAddr], // My shard_ids
+    context: &str,      // Other parties challenge against this ctx
+) -> Outcome<KeyStore> {
+    futures::executor::block_on(algo_keygen(messenger, key_arch, whoami, context))
+}
+
+pub async fn algo_keygen_with_options(
+    messenger: &impl Messenger,
+    key_arch: &HashMap<u16 /*group_id*/, (usize /*th*/, HashSet<MpcAddr>)>,
+    whoami: &[MpcAddr], // My shard_ids
+    context: &str,      // Other parties challenge against this ctx
+    opts: &KeygenOptions,
+) -> Outcome<KeyStore> {
+    let mut rng = OsRng;
+    algo_keygen_with_rng(messenger, key_arch, whoami, context, opts, &mut rng).await
+}
+
+/// Same as `algo_keygen_with_options`, but all randomness of the shards
+/// (party keys and vss polynomials) is drawn from `rng`. A seeded `rng` makes
+/// the keystore reproducible, e.g. in tests.
+pub async fn algo_keygen_with_rng<R: RngCore + CryptoRng>(
+    messenger: &impl Messenger,
+    key_arch: &HashMap<u16 /*group_id*/, (usize /*th*/, HashSet<MpcAddr>)>,
+    whoami: &[MpcAddr], // My shard_ids
+    context: &str,      // Other parties challenge against this ctx
+    opts: &KeygenOptions,
+    rng: &mut R,
+) -> Outcome<KeyStore> {
+    let no_progress = |_| {};
+    let (keystore, _) = run_keygen(
+        messenger,
+        key_arch,
+        whoami,
+        context,
+        opts,
+        rng,
+        &no_progress,
+    )
+    .await?;
+    Ok(keystore)
+}
+
+/// Same as `algo_keygen_with_options`, but calls `on_progress` as keygen
+/// enters each `KeygenPhase`, e.g. to drive a progress bar. The phases come
+/// in order, once for each shard of `whoami`.
+pub async fn algo_keygen_with_progress(
+    messenger: &impl Messenger,
+    key_arch: &HashMap<u16 /*group_id*/, (usize /*th*/, HashSet<MpcAddr>)>,
+    whoami: &[MpcAddr], // My shard_ids
+    context: &str,      // Other parties challenge against this ctx
+    opts: &KeygenOptions,
+    on_progress: impl Fn(KeygenPhase) + Sync,
+) -> Outcome<KeyStore> {
+    let mut rng = OsRng;
+    let (keystore, _) = run_keygen(
+        messenger,
+        key_arch,
+        whoami,
+        context,
+        opts,
+        &mut rng,
+        &on_progress,
+    )
+    .await?;
+    Ok(keystore)
+}
+
+/// Same as `algo_keygen_with_options`, but also returns how long each round
+/// took and how much of it was spent awaiting `messenger`, to tell whether
+/// a slow keygen is network-bound or CPU-bound.
+pub async fn algo_keygen_timed<M: Messenger + Sync>(
+    messenger: &M,
+    key_arch: &HashMap<u16 /*group_id*/, (usize /*th*/, HashSet<MpcAddr>)>,
+    whoami: &[MpcAddr], // My shard_ids
+    context: &str,      // Other parties challenge against this ctx
+    opts: &KeygenOptions,
+) -> Outcome<(KeyStore, KeygenTimings)> {
+    let timed = TimedMessenger {
+        inner: messenger,
+        waited: Mutex::new(Duration::ZERO),
+    };
+    let mut rng = OsRng;
+    let no_progress = |_| {};
+    let (keystore, mut timings) = run_keygen(
+        &timed,
+        key_arch,
+        whoami,
+        context,
+        opts,
+        &mut rng,
+        &no_progress,
+    )
+    .await?;
+    timings.network = *timed.waited.lock().unwrap();
+    Ok((keystore, timings))
+}
+
+#[tracing::instrument(name = "keygen", skip_all, fields(context = %context, whoami = ?whoami))]
+async fn run_keygen<R: RngCore + CryptoRng>(
+    messenger: &impl Messenger,
+    key_arch: &HashMap<u16 /*group_id*/, (usize /*th*/, HashSet<MpcAddr>)>,
+    whoami: &[MpcAddr], // My shard_ids
+    context: &str,      // Other parties challenge against this ctx
+    opts: &KeygenOptions,
+    rng: &mut R,
+    on_progress: &(dyn Fn(KeygenPhase) + Sync),
+) -> Outcome<(KeyStore, KeygenTimings)> {
+    algo_keygen_precheck(key_arch, whoami, context)?;
+    // Awaited across rounds, so zeroized by the guard if the future is dropped.
+    let mut merged = MergedShards::default();
+    let keystore = &mut merged.0;
+    let mut timings = KeygenTimings::default();
 
     // shard_id should be traversed in ascending order to avoid deadlock.
     for my_id in whoami.iter() {
-        // extract useful params
-        let my_id = *my_id;
+        let start = Instant::now();
+        on_progress(KeygenPhase::Commit);
+        let state = keygen_round1_commit(messenger, key_arch, *my_id, context, opts, rng).await?;
+        timings.commit += start.elapsed();
+        let start = Instant::now();
+        let state = round2_shares(messenger, key_arch, state, context, opts, on_progress).await?;
+        timings.shares += start.elapsed();
+        let start = Instant::now();
+        on_progress(KeygenPhase::Finalize);
+        let shard = keygen_finalize(messenger, key_arch, state, context, opts).await?;
+        timings.finalize += start.elapsed();
+        keystore.ui_pergroup.extend(shard.ui_pergroup);
+        keystore.xi_pergroup.extend(shard.xi_pergroup);
+        keystore.vss_com_grid.extend(shard.vss_com_grid);
+        keystore.vss_zkp_grid.extend(shard.vss_zkp_grid);
+    }
+
+    // Fetch vss_com of members in other groups
+    let start = Instant::now();
+    let mut key_arch = key_arch.clone();
+    for my_id in whoami.iter() {
         let gid = my_id.group_id();
-        let (th, members) = key_arch.get(&gid).ifnone("NoGroup", gid.to_string())?;
-        let gcast_id = MpcAddr::gcast_id(gid);
-
-        // print ids
-        print!("Me: {} , Group members: ", my_id);
-        for id in members.iter() {
-            print!("{} ", id);
-        }
-        println!();
-
-        // generate party key $u_i$ and ephemeral key $k_i$.
-        let mut rng = OsRng;
-        let party_key = PartyKey::new(&mut rng);
-        if false {
-            use bip32::{Language, Mnemonic};
-            let mnemonic = Mnemonic::from_entropy(party_key.u_i.to_bytes(), Language::English);
-            let phrase = mnemonic.phrase().to_string();
-            drop(phrase);
-        }
-
-        // generate vss commmitment and vss shares
-        let _obj: _ = generate_vss_share(&party_key.u_i, my_id, members, *th, &mut rng).catch_()?;
-        let shares_com: Vec<EdwardsPoint> = _obj.0;
-        let mut shares: HashMap<MpcAddr, Scalar> = _obj.1;
-
-        // generate challenge
-        let challenge = generate_dkg_challenge(
-            my_id,
-            context,            // known to all participants
-            &party_key.g_u_i(), // public key of shard
-            &party_key.g_k_i(), // commitment of shard
+        key_arch.remove(&gid);
+    }
+    for (gid, (th, members)) in key_arch.iter() {
+        let gcast_id = MpcAddr::gcast_id(*gid);
+        let round = "dkg_com";
+        let fut = with_retry(messenger, &opts.sesman, round, || {
+            messenger.gather(round, members, gcast_id)
+        });
+        let wire_com_dict: HashMap<MpcAddr, WireDKGCommitment> =
+            until_or_aborted(messenger, opts.deadline, round, members, fut).await?;
+        let proposed_com_dict = decode_commitments_strict(&wire_com_dict)?;
+
+        // verify and collect others' vss_com_dict
+        let vss_com_dict = validate_commitments(&proposed_com_dict, *th, members, context)?;
+        let zkp_dict = zkps_of(&proposed_com_dict);
+        drop(proposed_com_dict);
+
+        keystore.vss_com_grid.insert(*gid, vss_com_dict);
+        keystore.vss_zkp_grid.insert(*gid, zkp_grid_of(&zkp_dict));
+        debug!(
+            gid,
+            round,
+            n_members = members.len(),
+            "Fetched commitments of other group"
+        );
+    }
+
+    timings.other_groups = start.elapsed();
+
+    // Archive my shard_ids
+    keystore.ids = whoami.iter().cloned().collect();
+    debug!(?timings, "Finished keygen");
+
+    Ok((std::mem::take(&mut merged.0), timings))
+}
+
+/// Round 1: generate the secrets of shard `my_id`, and broadcast the
+/// commitment to them within its group.
+pub async fn keygen_round1_commit<R: RngCore + CryptoRng>(
+    messenger: &impl Messenger,
+    key_arch: &HashMap<u16 /*group_id*/, (usize /*th*/, HashSet<MpcAddr>)>,
+    my_id: MpcAddr,
+    context: &str,
+    opts: &KeygenOptions,
+    rng: &mut R,
+) -> Outcome<KeygenState> {
+    let state = keygen_build_commitment(key_arch, my_id, context, rng)?;
+    let gcast_id = MpcAddr::gcast_id(my_id.group_id());
+
+    let round = "dkg_com";
+    let fut = with_retry(messenger, &opts.sesman, round, || {
+        messenger.send(round, my_id, gcast_id, &state.dkg_commitment)
+    });
+    until(opts.deadline, round, fut).await?;
+    debug!(%my_id, round, "Broadcast dkg commitment");
+
+    Ok(state)
+}
+
+/// Round 2: validate the commitments of the group and check with its members
+/// that every dealer sent us all the same one, then deal my vss shares to each
+/// member via aes-gcm encrypted channel.
+///
+/// Re-running it with the same round-1 `state` is harmless, so it is the
+/// place to resume from after a transport failure.
+pub async fn keygen_round2_shares(
+    messenger: &impl Messenger,
+    key_arch: &HashMap<u16 /*group_id*/, (usize /*th*/, HashSet<MpcAddr>)>,
+    state: KeygenState,
+    context: &str,
+    opts: &KeygenOptions,
+) -> Outcome<KeygenState> {
+    round2_shares(messenger, key_arch, state, context, opts, &|_| {}).await
+}
+
+async fn round2_shares(
+    messenger: &impl Messenger,
+    key_arch: &HashMap<u16 /*group_id*/, (usize /*th*/, HashSet<MpcAddr>)>,
+    mut state: KeygenState,
+    context: &str,
+    opts: &KeygenOptions,
+    on_progress: &(dyn Fn(KeygenPhase) + Sync),
+) -> Outcome<KeygenState> {
+    on_progress(KeygenPhase::ExchangeCommitments);
+    let my_id = state.my_id;
+    let gid = my_id.group_id();
+    let (_, members) = key_arch.get(&gid).ifnone(
+        InvalidParameters,
+        format!("group {} is not in key_arch", gid),
+    )?;
+    let gcast_id = MpcAddr::gcast_id(gid);
+
+    let round = "dkg_com";
+    let fut = with_retry(messenger, &opts.sesman, round, || {
+        messenger.gather(round, members, gcast_id)
+    });
+    let wire_com_dict: HashMap<MpcAddr, WireDKGCommitment> =
+        until_or_aborted(messenger, opts.deadline, round, members, fut).await?;
+    let decoded = decode_commitments_strict(&wire_com_dict);
+    let proposed_com_dict = abort_on_err(messenger, my_id, decoded).await?;
+    let processed = keygen_process_commitments(key_arch, my_id, &proposed_com_dict, context);
+    let zkp_dict = zkps_of(&proposed_com_dict);
+    drop(proposed_com_dict);
+    let (vss_com_dict, digests) = abort_on_err(messenger, my_id, processed).await?;
+
+    // compare with the group what each dealer committed to, before dealing
+    let round = "dkg_com_echo";
+    let fut = with_retry(messenger, &opts.sesman, round, || {
+        messenger.send(round, my_id, gcast_id, &digests)
+    });
+    until(opts.deadline, round, fut).await?;
+    let fut = with_retry(messenger, &opts.sesman, round, || {
+        messenger.gather(round, members, gcast_id)
+    });
+    let echoes: HashMap<MpcAddr, HashMap<MpcAddr, [u8; 32]>> =
+        until_or_aborted(messenger, opts.deadline, round, members, fut).await?;
+    let consistent = check_echoes(gid, &digests, &echoes);
+    abort_on_err(messenger, my_id, consistent).await?;
+    info!(%my_id, round = "dkg_com", n_members = members.len(), "Exchanged commitments");
+
+    // scatter vss shares via aes-gcm encrypted channel
+    on_progress(KeygenPhase::ExchangeShares);
+    let aead_packs =
+        keygen_deal_shares(key_arch, &state, &vss_com_dict, context, opts.aead_scheme)?;
+    let round = "aead_share";
+    for (id, aead_pack_i) in aead_packs.iter() {
+        let fut = with_retry(messenger, &opts.sesman, round, || {
+            messenger.send(round, my_id, *id, aead_pack_i)
+        });
+        until(opts.deadline, round, fut).await?;
+    }
+    debug!(%my_id, round, "Dealt vss shares");
+
+    state.vss_com_dict = Some(vss_com_dict);
+    state.zkp_dict = Some(zkp_dict);
+    Ok(state)
+}
+
+/// Compute $x_i$ from the shares dealt to me, returning a keystore of the
+/// single shard `state.my_id`.
+pub async fn keygen_finalize(
+    messenger: &impl Messenger,
+    key_arch: &HashMap<u16 /*group_id*/, (usize /*th*/, HashSet<MpcAddr>)>,
+    mut state: KeygenState,
+    context: &str,
+    opts: &KeygenOptions,
+) -> Outcome<KeyStore> {
+    let my_id = state.my_id;
+    let gid = my_id.group_id();
+    let (_, members) = key_arch.get(&gid).ifnone(
+        InvalidParameters,
+        format!("group {} is not in key_arch", gid),
+    )?;
+    let vss_com_dict = state
+        .vss_com_dict
+        .take()
+        .ifnone("KeygenRoundSkipped", "Round 2 has not been run")?;
+
+    let round = "aead_share";
+    let fut = with_retry(messenger, &opts.sesman, round, || {
+        messenger.gather(round, members, my_id)
+    });
+    let aead_dict: HashMap<MpcAddr, AEAD> =
+        until_or_aborted(messenger, opts.deadline, round, members, fut).await?;
+
+    let shard = keygen_merge_shares(key_arch, &state, vss_com_dict, &aead_dict, context);
+    let shard = abort_on_err(messenger, my_id, shard).await?;
+    info!(%my_id, round, n_members = members.len(), "Merged vss shares");
+    Ok(shard)
+}
+
+// The pure cores of the rounds above: all the crypto of keygen, with no I/O,
+// so that it can be tested and driven by other transports.
+
+/// Core of round 1: draw the secrets of shard `my_id` from `rng`, deal its
+/// vss shares and prove knowledge of its secret. The commitment to broadcast
+/// within the group is `dkg_commitment` of the returned state.
+pub fn keygen_build_commitment<R: RngCore + CryptoRng>(
+    key_arch: &HashMap<u16 /*group_id*/, (usize /*th*/, HashSet<MpcAddr>)>,
+    my_id: MpcAddr,
+    context: &str,
+    rng: &mut R,
+) -> Outcome<KeygenState> {
+    // extract useful params
+    validate_context(context)?;
+    validate_key_arch(key_arch, my_id)?;
+    let gid = my_id.group_id();
+    let (th, members) = key_arch.get(&gid).ifnone(
+        InvalidParameters,
+        format!("group {} is not in key_arch", gid),
+    )?;
+
+    debug!(%my_id, round = "dkg_com", n_members = members.len(), th, "Start keygen of shard");
+
+    // generate party key $u_i$ and ephemeral key $k_i$.
+    let party_key = PartyKey::new(rng);
+
+    // generate vss commmitment and vss shares
+    let _obj: _ = generate_vss_share(&party_key.u_i, my_id, members, *th, rng)
+        .catch(InvalidParameters, "Cannot deal vss shares")?;
+    let shares_com: Vec<EdwardsPoint> = _obj.0;
+    let shares: HashMap<MpcAddr, Scalar> = _obj.1;
+
+    // generate challenge
+    let challenge = generate_dkg_challenge(
+        my_id,
+        context,            // known to all participants
+        &party_key.g_u_i(), // public key of shard
+        &party_key.g_k_i(), // commitment of shard
+    )
+    .catch(InvalidParameters, "Cannot generate dkg challenge")?;
+
+    // construct dkg commitment
+    let dkg_commitment = KeyGenDKGProposedCommitment {
+        shares_commitment: shares_com,
+        zkp: KeyGenZKP {
+            g_k_i: party_key.g_k_i(),
+            sigma: &party_key.k_i + &party_key.u_i * challenge,
+        },
+    };
+    Ok(KeygenState {
+        my_id,
+        party_key,
+        dkg_commitment,
+        shares,
+        vss_com_dict: None,
+        zkp_dict: None,
+    })
+}
+
+/// Core of round 2 up to the echo: validate the commitments of the group of
+/// `my_id`, whichever peers they came from. Returns the vss commitment of
+/// every member, and the digest of every commitment to echo to the group.
+#[allow(clippy::type_complexity)]
+pub fn keygen_process_commitments(
+    key_arch: &HashMap<u16 /*group_id*/, (usize /*th*/, HashSet<MpcAddr>)>,
+    my_id: MpcAddr,
+    proposed_com_dict: &HashMap<MpcAddr, KeyGenDKGProposedCommitment>,
+    context: &str,
+) -> Outcome<(
+    HashMap<MpcAddr, Vec<EdwardsPoint>>,
+    HashMap<MpcAddr, [u8; 32]>,
+)> {
+    let gid = my_id.group_id();
+    let (th, members) = key_arch.get(&gid).ifnone(
+        InvalidParameters,
+        format!("group {} is not in key_arch", gid),
+    )?;
+    let vss_com_dict = validate_commitments(proposed_com_dict, *th, members, context)?;
+    let digests: HashMap<MpcAddr, [u8; 32]> = proposed_com_dict
+        .iter()
+        .map(|(id, com)| (*id, com.digest()))
+        .collect();
+    Ok((vss_com_dict, digests))
+}
+
+/// Core of round 2 after the echo: encrypt the vss share of each member
+/// under our channel key, returning the packs by receiver.
+pub fn keygen_deal_shares(
+    key_arch: &HashMap<u16 /*group_id*/, (usize /*th*/, HashSet<MpcAddr>)>,
+    state: &KeygenState,
+    vss_com_dict: &HashMap<MpcAddr, Vec<EdwardsPoint>>,
+    context: &str,
+    aead_scheme: AeadScheme,
+) -> Outcome<HashMap<MpcAddr, AEAD>> {
+    let my_id = state.my_id;
+    let gid = my_id.group_id();
+    let (_, members) = key_arch.get(&gid).ifnone(
+        InvalidParameters,
+        format!("group {} is not in key_arch", gid),
+    )?;
+    let aes_keys = keygen_channel_keys(state, vss_com_dict, members, context)?;
+    let mut aead_packs = HashMap::with_capacity(members.len());
+    for id in members.iter() {
+        let aes_key: &[u8; 32] = aes_keys.get(id).ifnone_()?;
+        let share = state.shares.get(id).ifnone(
+            InvalidParameters,
+            format!("No share was dealt to member {} in round 1", id),
+        )?;
+        // Canonical little-endian, see the crate docs.
+        let plaintext = Zeroizing::new(share.to_bytes());
+        let aad = p2p_aad(my_id, *id, "aead_share");
+        // The only message under this key in this direction.
+        let counter = 0;
+        let aead_pack_i =
+            aead_encrypt_seq(aead_scheme, aes_key, &*plaintext, &aad, my_id, *id, counter).catch(
+                InvalidParameters,
+                format!("Cannot encrypt share to party {}", id),
+            )?;
+        aead_packs.insert(*id, aead_pack_i);
+    }
+    Ok(aead_packs)
+}
+
+/// Core of `keygen_finalize`: decrypt and verify the shares dealt to
+/// `state.my_id` in `aead_dict`, and merge them into its keystore.
+pub fn keygen_merge_shares(
+    key_arch: &HashMap<u16 /*group_id*/, (usize /*th*/, HashSet<MpcAddr>)>,
+    state: &KeygenState,
+    vss_com_dict: HashMap<MpcAddr, Vec<EdwardsPoint>>,
+    aead_dict: &HashMap<MpcAddr, AEAD>,
+    context: &str,
+) -> Outcome<KeyStore> {
+    let my_id = state.my_id;
+    let gid = my_id.group_id();
+    let (_, members) = key_arch.get(&gid).ifnone(
+        InvalidParameters,
+        format!("group {} is not in key_arch", gid),
+    )?;
+
+    // gather vss shares, collecting every party whose share is bad
+    let mut party_shares = PartyShares::default();
+    let mut bad_ids: Vec<MpcAddr> = Vec::new();
+    let mut reasons: Vec<String> = Vec::new();
+    let aes_keys = keygen_channel_keys(state, &vss_com_dict, members, context)?;
+    for j in members.iter() {
+        let aes_key: &[u8; 32] = aes_keys.get(j).ifnone_()?;
+        let aead_pack = aead_dict.get(j).ifnone(
+            TransportError,
+            format!("No share received from party {}", j),
+        )?;
+        let aad = p2p_aad(*j, my_id, "aead_share");
+        let out = match aes_decrypt_with_aad(aes_key, aead_pack, &aad) {
+            Ok(out) => Zeroizing::new(out),
+            _ => {
+                bad_ids.push(*j);
+                continue;
+            }
+        };
+        // Not reduced mod l, so that only the bytes an honest dealer sends pass.
+        match decode_scalar_strict(&out, *j) {
+            Ok(out_fe) => {
+                party_shares.0.insert(*j, out_fe);
+            }
+            Err(e) => {
+                bad_ids.push(*j);
+                reasons.extend(e.get_context().map(|ctx| ctx.to_string()));
+            }
+        }
+    }
+
+    bad_ids.extend(find_invalid_shares(&party_shares.0, &vss_com_dict, my_id)?);
+    if !bad_ids.is_empty() {
+        bad_ids.sort();
+        reasons.sort();
+        let reasons: String = reasons.iter().map(|r| format!("; {}", r)).collect();
+        throw!(
+            ShareVerifyFailed,
+            format!("Invalid share from parties {:?}{}", bad_ids, reasons)
+        );
+    }
+
+    // compute x_i
+    let signing_key: Scalar =
+        merge_vss_share(&party_shares.0, &vss_com_dict, my_id).catch(ShareVerifyFailed, "")?;
+
+    let mut shard = KeyStore::default();
+    shard.ui_pergroup.insert(gid, state.party_key.u_i);
+    shard.xi_pergroup.insert(gid, signing_key);
+    shard.vss_com_grid.insert(gid, vss_com_dict);
+    if let Some(zkp_dict) = state.zkp_dict.as_ref() {
+        shard.vss_zkp_grid.insert(gid, zkp_grid_of(zkp_dict));
+    }
+    shard.ids.insert(my_id);
+    Ok(shard)
+}
+
+/// The proof of each commitment in `proposed_com_dict`.
+fn zkps_of(
+    proposed_com_dict: &HashMap<MpcAddr, KeyGenDKGProposedCommitment>,
+) -> HashMap<MpcAddr, KeyGenZKP> {
+    proposed_com_dict
+        .iter()
+        .map(|(id, com)| (*id, com.zkp.clone()))
+        .collect()
+}
+
+/// `zkp_dict` as the keystore holds it, in `vss_zkp_grid`.
+fn zkp_grid_of(zkp_dict: &HashMap<MpcAddr, KeyGenZKP>) -> HashMap<MpcAddr, (EdwardsPoint, Scalar)> {
+    zkp_dict
+        .iter()
+        .map(|(id, zkp)| (*id, (zkp.g_k_i, zkp.sigma)))
+        .collect()
+}
+
+/// What `algo_keygen_precheck` found consistent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeygenPrecheck {
+    /// `(group_id, th, n_members)` of every group, in ascending group id.
+    pub groups: Vec<(u16, usize, usize)>,
+    /// My shard ids, in ascending order.
+    pub shards: Vec<MpcAddr>,
+}
+
+/// Run every local check of `algo_keygen` without touching the network,
+/// so that an orchestrator can fail fast before coordinating the parties.
+///
+/// Throws `InvalidParameters` explaining the first violated constraint.
+pub fn algo_keygen_precheck(
+    key_arch: &HashMap<u16 /*group_id*/, (usize /*th*/, HashSet<MpcAddr>)>,
+    whoami: &[MpcAddr],
+    context: &str,
+) -> Outcome<KeygenPrecheck> {
+    const IKA: &str = InvalidParameters;
+    validate_context(context)?;
+    assert_throw!(!whoami.is_empty(), IKA, "whoami must not be empty");
+
+    let mut groups: Vec<(u16, usize, usize)> = Vec::with_capacity(key_arch.len());
+    for (gid, (th, members)) in key_arch.iter() {
+        validate_group(*gid, *th, members)?;
+        groups.push((*gid, *th, members.len()));
+    }
+    groups.sort();
+
+    let mut shards = whoami.to_vec();
+    shards.sort();
+    for (i, my_id) in shards.iter().enumerate() {
+        validate_key_arch(key_arch, *my_id)?;
+        if i > 0 && shards[i - 1].group_id() == my_id.group_id() {
+            throw!(
+                IKA,
+                format!(
+                    "shards {} and {} are in the same group, at most one is allowed",
+                    shards[i - 1],
+                    my_id
+                )
+            );
+        }
+    }
+    Ok(KeygenPrecheck { groups, shards })
+}
+
+/// Check that shard `my_id` can run keygen under `key_arch`.
+/// Throws `InvalidParameters` explaining the violated constraint.
+pub fn validate_key_arch(
+    key_arch: &HashMap<u16 /*group_id*/, (usize /*th*/, HashSet<MpcAddr>)>,
+    my_id: MpcAddr,
+) -> Outcome<()> {
+    let gid = my_id.group_id();
+    let (th, members) = key_arch.get(&gid).ifnone(
+        InvalidParameters,
+        format!("group {} of my_id={} is not in key_arch", gid, my_id),
+    )?;
+    validate_group(gid, *th, members)?;
+    if !members.contains(&my_id) {
+        let mut ids: Vec<u16> = members.iter().map(|id| id.member_id()).collect();
+        ids.sort();
+        throw!(
+            InvalidParameters,
+            format!(
+                "my_id={} must be one of the members {:?} of group {}",
+                my_id, ids, gid
+            )
+        );
+    }
+    Ok(())
+}
+
+/// The `context` of the challenges must separate this keygen from every other,
+/// so an empty one is a bug.
+fn validate_context(context: &str) -> Outcome<()> {
+    const IKA: &str = InvalidParameters;
+    assert_throw!(!context.is_empty(), IKA, "context must not be empty");
+    assert_throw!(
+        context.len() <= MAX_CONTEXT_LEN,
+        IKA,
+        format!(
+            "context has {} bytes, at most {} are supported",
+            context.len(),
+            MAX_CONTEXT_LEN
         )
-        .catch_()?;
+    );
+    Ok(())
+}
 
-        // construct dkg commitment
-        let dkg_commitment = KeyGenDKGProposedCommitment {
-            shares_commitment: shares_com,
-            zkp: KeyGenZKP {
-                g_k_i: party_key.g_k_i(),
-                sigma: &party_key.k_i + &party_key.u_i * challenge,
-            },
+pub(crate) fn validate_group(gid: u16, th: usize, members: &HashSet<MpcAddr>) -> Outcome<()> {
+    const IKA: &str = InvalidParameters;
+    let n_members = members.len();
+    assert_throw!(
+        n_members >= 2,
+        IKA,
+        format!(
+            "group {} has n_members={}, at least 2 are required",
+            gid, n_members
+        )
+    );
+    assert_throw!(
+        n_members <= MAX_MEMBERS,
+        IKA,
+        format!(
+            "group {} has n_members={}, at most {} are supported",
+            gid, n_members, MAX_MEMBERS
+        )
+    );
+    assert_throw!(
+        th >= 1,
+        IKA,
+        format!("threshold th={} of group {} must be at least 1", th, gid)
+    );
+    assert_throw!(
+        th <= n_members,
+        IKA,
+        format!("threshold th={} cannot exceed n_members={}", th, n_members)
+    );
+    for id in members.iter() {
+        // A share evaluated at x=0 is the dealer's secret itself.
+        assert_throw!(
+            id.group_id() == gid && id.member_id() != 0,
+            IKA,
+            format!("member {} is not a valid member id of group {}", id, gid)
+        );
+    }
+    Ok(())
+}
+
+/// use others' pubkey to construct aes key
+fn eval_aes_key(
+    party_key: &PartyKey,
+    vss_com_dict: &HashMap<MpcAddr, Vec<EdwardsPoint>>,
+    my_id: MpcAddr,
+    peer: MpcAddr,
+    context: &str,
+) -> Outcome<Zeroizing<[u8; 32]>> {
+    let com = vss_com_dict.get(&peer).ifnone(
+        CommitmentVerifyFailed,
+        format!("No commitment from party {}", peer),
+    )?;
+    let mut shared = com[0] * &party_key.u_i; // shared = u_j * g_u_i
+    let aes_key = eval_channel_key(&shared, context, my_id, peer);
+    shared.zeroize();
+    Ok(Zeroizing::new(aes_key?))
+}
+
+/// The key of the channel between `state.my_id` and each of `peers`, which
+/// `keygen_deal_shares` and `keygen_merge_shares` encrypt the shares under.
+///
+/// They are derived one peer at a time. Each is $u_i$ times a different
+/// peer's $C_j[0]$, and the products are needed apart, not summed, so a
+/// multiscalar multiplication has nothing to share. Spreading them over rayon
+/// measured no faster at 32 peers, and only added a thread-pool hop.
+pub fn keygen_channel_keys(
+    state: &KeygenState,
+    vss_com_dict: &HashMap<MpcAddr, Vec<EdwardsPoint>>,
+    peers: &HashSet<MpcAddr>,
+    context: &str,
+) -> Outcome<HashMap<MpcAddr, Zeroizing<[u8; 32]>>> {
+    let my_id = state.my_id;
+    peers
+        .iter()
+        .map(|peer| {
+            let aes_key = eval_aes_key(&state.party_key, vss_com_dict, my_id, *peer, context)?;
+            Ok((*peer, aes_key))
+        })
+        .collect()
+}
+
+/// Check the proofs and thresholds of the commitments of a group, and that
+/// they come from exactly its `members`, whatever the messenger returned.
+pub(crate) fn validate_commitments(
+    proposed_com_dict: &HashMap<MpcAddr, KeyGenDKGProposedCommitment>,
+    th: usize,
+    members: &HashSet<MpcAddr>,
+    context: &str,
+) -> Outcome<HashMap<MpcAddr, Vec<EdwardsPoint>>> {
+    let mut missing: Vec<&MpcAddr> = members
+        .iter()
+        .filter(|id| !proposed_com_dict.contains_key(id))
+        .collect();
+    missing.sort();
+    assert_throw!(
+        missing.is_empty(),
+        CommitmentVerifyFailed,
+        format!("No commitment from members {:?}", missing)
+    );
+    let mut strangers: Vec<&MpcAddr> = proposed_com_dict
+        .keys()
+        .filter(|id| !members.contains(id))
+        .collect();
+    strangers.sort();
+    assert_throw!(
+        strangers.is_empty(),
+        CommitmentVerifyFailed,
+        format!("Commitments from non-members {:?}", strangers)
+    );
+    let vss_com_dict =
+        keygen_validate_peers(proposed_com_dict, context).catch(CommitmentVerifyFailed, "")?;
+    for (id, vss_com) in vss_com_dict.iter() {
+        // to avoid DKG attack via increasing threshold on the fly.
+        assert_throw!(
+            vss_com.len() == th,
+            CommitmentVerifyFailed,
+            format!("Commitment from party {} is not of threshold th={}", id, th)
+        );
+    }
+    Ok(vss_com_dict)
+}
+
+/// The shares dealt to me, zeroized however `keygen_finalize` returns,
+/// including early on a failed round.
+#[derive(Default)]
+struct PartyShares(HashMap<MpcAddr, Scalar>);
+
+impl Zeroize for PartyShares {
+    fn zeroize(&mut self) {
+        for x in self.0.values_mut() {
+            x.zeroize();
+        }
+    }
+}
+
+impl Drop for PartyShares {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+/// Await a network call of `round`, racing it against `deadline` if any.
+async fn until<T, E>(
+    deadline: Option<Instant>,
+    round: &str,
+    fut: impl Future<Output = Result<T, E>>,
+) -> Outcome<T>
+where
+    E: std::fmt::Display + Send + Sync + 'static,
+{
+    match deadline {
+        None => fut
+            .await
+            .catch(TransportError, format!("At round \"{}\"", round)),
+        Some(deadline) => match tokio::time::timeout_at(deadline.into(), fut).await {
+            Ok(res) => res.catch(TransportError, format!("At round \"{}\"", round)),
+            Err(_) => throw!(
+                "KeygenTimeout",
+                format!("Deadline exceeded at round \"{}\"", round)
+            ),
+        },
+    }
+}
+
+/// Same as `until`, but fails with `Aborted` as soon as any of `members`
+/// broadcasts an `Abort` within its group, since `fut` may then never end.
+async fn until_or_aborted<T, E>(
+    messenger: &impl Messenger,
+    deadline: Option<Instant>,
+    round: &str,
+    members: &HashSet<MpcAddr>,
+    fut: impl Future<Output = Result<T, E>>,
+) -> Outcome<T>
+where
+    E: std::fmt::Display + Send + Sync + 'static,
+{
+    let awaited = pin!(until(deadline, round, fut));
+    let aborted = pin!(aborted_by(messenger, members));
+    match select(awaited, aborted).await {
+        Either::Left((res, _)) | Either::Right((res, _)) => res,
+    }
+}
+
+/// Wait for the first `Abort` from `members`, and throw it as `Aborted`.
+async fn aborted_by<T>(messenger: &impl Messenger, members: &HashSet<MpcAddr>) -> Outcome<T> {
+    let receives = members.iter().map(|src| {
+        Box::pin(async move {
+            let gcast_id = MpcAddr::gcast_id(src.group_id());
+            match messenger.receive(ABORT_ROUND, *src, gcast_id).await {
+                Ok(abort) => (*src, abort),
+                // Leave the round to fail on its own.
+                Err(_) => pending::<(MpcAddr, Abort)>().await,
+            }
+        })
+    });
+    let ((by, abort), _, _) = select_all(receives).await;
+    throw!(
+        Aborted,
+        format!("Party {} aborted the keygen: {}", by, abort.reason)
+    )
+}
+
+/// Throws `Equivocation` naming the dealers whose commitment `digests`
+/// differ from what the members of group `gid` echoed.
+fn check_echoes(
+    gid: u16,
+    digests: &HashMap<MpcAddr, [u8; 32]>,
+    echoes: &HashMap<MpcAddr, HashMap<MpcAddr, [u8; 32]>>,
+) -> Outcome<()> {
+    let equivocators = find_equivocating_dealers(digests, echoes);
+    assert_throw!(
+        equivocators.is_empty(),
+        Equivocation,
+        format!(
+            "Dealers {:?} sent different commitments to members of group {}",
+            equivocators, gid
+        )
+    );
+    Ok(())
+}
+
+/// Pass `res` through, first broadcasting an `Abort` within the group of
+/// `my_id` if it failed with one of the errors listed at `Abort`.
+/// The broadcast is best effort: its own failure is dropped in favor of
+/// the error that caused it.
+async fn abort_on_err<T>(
+    messenger: &impl Messenger,
+    my_id: MpcAddr,
+    res: Outcome<T>,
+) -> Outcome<T> {
+    const FATAL: [&str; 4] = [
+        CommitmentVerifyFailed,
+        Equivocation,
+        ShareVerifyFailed,
+        NonCanonicalEncoding,
+    ];
+    let err = match &res {
+        Err(err) if FATAL.contains(&err.get_name()) => err,
+        _ => return res,
+    };
+    let abort = Abort {
+        reason: format!(
+            "{}: {}",
+            err.get_name(),
+            err.get_context().unwrap_or_default()
+        ),
+    };
+    let gcast_id = MpcAddr::gcast_id(my_id.group_id());
+    let _ = messenger.send(ABORT_ROUND, my_id, gcast_id, &abort).await;
+    res
+}
+
+/// Forwards to `inner`, adding the time spent in each call to `waited`.
+struct TimedMessenger<'a, M> {
+    inner: &'a M,
+    waited: Mutex<Duration>,
+}
+
+impl<M> TimedMessenger<'_, M> {
+    async fn timed<T>(&self, fut: impl Future<Output = T>) -> T {
+        let start = Instant::now();
+        let res = fut.await;
+        *self.waited.lock().unwrap() += start.elapsed();
+        res
+    }
+}
+
+#[async_trait]
+impl<M: Messenger + Sync> Messenger for TimedMessenger<'_, M> {
+    type E = M::E;
+
+    async fn send<T>(&self, topic: &str, src: MpcAddr, dst: MpcAddr, obj: &T) -> Result<(), M::E>
+    where
+        T: Serialize + DeserializeOwned + Send + Sync,
+    {
+        self.timed(self.inner.send(topic, src, dst, obj)).await
+    }
+
+    async fn receive<T>(&self, topic: &str, src: MpcAddr, dst: MpcAddr) -> Result<T, M::E>
+    where
+        T: Serialize + DeserializeOwned + Send + Sync,
+    {
+        // An abort is awaited alongside another call, which is already timed.
+        if topic == ABORT_ROUND {
+            return self.inner.receive(topic, src, dst).await;
+        }
+        self.timed(self.inner.receive(topic, src, dst)).await
+    }
+
+    async fn scatter<T>(
+        &self,
+        topic: &str,
+        src: MpcAddr,
+        dsts: &HashSet<MpcAddr>,
+        obj: &T,
+    ) -> Result<(), M::E>
+    where
+        T: Serialize + DeserializeOwned + Send + Sync,
+    {
+        self.timed(self.inner.scatter(topic, src, dsts, obj)).await
+    }
+
+    async fn gather<T>(
+        &self,
+        topic: &str,
+        srcs: &HashSet<MpcAddr>,
+        dst: MpcAddr,
+    ) -> Result<HashMap<MpcAddr, T>, M::E>
+    where
+        T: Serialize + DeserializeOwned + Send + Sync,
+    {
+        self.timed(self.inner.gather(topic, srcs, dst)).await
+    }
+
+    fn is_transient(&self, err: &M::E) -> bool {
+        self.inner.is_transient(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+    use serde::de::DeserializeOwned;
+
+    use super::super::test_util::{
+        group_of, key_arch_of, keygen_for_test, keygen_parties, keygen_seeded_for_test,
+    };
+    use super::*;
+    use crate::KeyStoreExt;
+
+    fn rejection(key_arch: &HashMap<u16, (usize, HashSet<MpcAddr>)>, my_id: MpcAddr) -> String {
+        let err = validate_key_arch(key_arch, my_id).unwrap_err();
+        assert_eq!(err.get_name(), InvalidParameters);
+        err.get_context().unwrap().to_string()
+    }
+
+    #[test]
+    fn invalid_key_arch_is_explained() {
+        let me = MpcAddr::new(1, 1);
+        assert!(validate_key_arch(&key_arch_of(2, 3), me).is_ok());
+        assert!(validate_key_arch(&key_arch_of(3, 3), me).is_ok());
+
+        let msg = rejection(&key_arch_of(5, 3), me);
+        assert!(
+            msg.contains("threshold th=5 cannot exceed n_members=3"),
+            "{}",
+            msg
+        );
+
+        let msg = rejection(&key_arch_of(0, 3), me);
+        assert!(
+            msg.contains("th=0 of group 1 must be at least 1"),
+            "{}",
+            msg
+        );
+
+        let msg = rejection(&key_arch_of(1, 1), me);
+        assert!(msg.contains("n_members=1, at least 2"), "{}", msg);
+
+        let msg = rejection(&key_arch_of(2, 4), MpcAddr::new(1, 5));
+        assert!(
+            msg.contains("must be one of the members [1, 2, 3, 4]"),
+            "{}",
+            msg
+        );
+
+        let msg = rejection(&key_arch_of(2, 3), MpcAddr::new(2, 1));
+        assert!(msg.contains("group 2 of my_id"), "{}", msg);
+
+        let mut key_arch = key_arch_of(2, 3);
+        key_arch.get_mut(&1).unwrap().1.insert(MpcAddr::new(1, 0));
+        let msg = rejection(&key_arch, me);
+        assert!(
+            msg.contains("is not a valid member id of group 1"),
+            "{}",
+            msg
+        );
+    }
+
+    #[test]
+    fn group_beyond_max_members_is_refused() {
+        let me = MpcAddr::new(1, 1);
+        let key_arch = key_arch_of(2, MAX_MEMBERS as u16);
+        algo_keygen_precheck(&key_arch, &[me], "ses").unwrap();
+        let key_arch = key_arch_of(2, MAX_MEMBERS as u16 + 1);
+        let msg = rejection(&key_arch, me);
+        assert!(msg.contains("at most 4096 are supported"), "{}", msg);
+    }
+
+    #[test]
+    fn precheck_reports_the_ceremony() {
+        let mut key_arch = key_arch_of(2, 3);
+        let other: HashSet<MpcAddr> = (1..=4).map(|i| MpcAddr::new(2, i)).collect();
+        key_arch.insert(2, (3, other));
+        let whoami = [MpcAddr::new(2, 4), MpcAddr::new(1, 1)];
+
+        let report = algo_keygen_precheck(&key_arch, &whoami, "ses").unwrap();
+        assert_eq!(report.groups, vec![(1, 2, 3), (2, 3, 4)]);
+        assert_eq!(report.shards, vec![MpcAddr::new(1, 1), MpcAddr::new(2, 4)]);
+
+        let rejection_of = |key_arch, whoami: &[MpcAddr], context| {
+            let err = algo_keygen_precheck(key_arch, whoami, context).unwrap_err();
+            assert_eq!(err.get_name(), InvalidParameters);
+            err.get_context().unwrap().to_string()
+        };
+        let msg = rejection_of(&key_arch, &whoami, "");
+        assert!(msg.contains("context must not be empty"), "{}", msg);
+        let long = "c".repeat(MAX_CONTEXT_LEN);
+        algo_keygen_precheck(&key_arch, &whoami, &long).unwrap();
+        let too_long = long + "c";
+        let msg = rejection_of(&key_arch, &whoami, too_long.as_str());
+        assert!(msg.contains("context has 1025 bytes"), "{}", msg);
+        // So are the callers of the rounds.
+        let res = keygen_build_commitment(&key_arch, MpcAddr::new(1, 1), "", &mut OsRng);
+        assert_eq!(res.err().unwrap().get_name(), InvalidParameters);
+        let msg = rejection_of(&key_arch, &[], "ses");
+        assert!(msg.contains("whoami must not be empty"), "{}", msg);
+        let msg = rejection_of(&key_arch, &[MpcAddr::new(1, 1), MpcAddr::new(1, 2)], "ses");
+        assert!(msg.contains("are in the same group"), "{}", msg);
+        let msg = rejection_of(&key_arch, &[MpcAddr::new(3, 1)], "ses");
+        assert!(msg.contains("group 3 of my_id"), "{}", msg);
+    }
+
+    #[test]
+    fn precheck_agrees_with_dealing_at_the_threshold_boundary() {
+        let whoami = [MpcAddr::new(1, 1)];
+        for th in 1..=4 {
+            let key_arch = key_arch_of(th, 3);
+            let members = &key_arch[&1].1;
+            let precheck = algo_keygen_precheck(&key_arch, &whoami, "ses");
+            let dealt = crate::frost::deal_vss_share(&Scalar::one(), members, th, &mut OsRng);
+            assert_eq!(precheck.is_ok(), dealt.is_ok(), "th={}", th);
+            assert_eq!(precheck.is_ok(), th <= 3, "th={}", th);
+        }
+    }
+
+    #[tokio::test]
+    async fn seeded_2_of_3_keygen_is_reproducible() {
+        let keystores = keygen_seeded_for_test(2, 3).await;
+        let (ks1, ks2, ks3) = (&keystores[0], &keystores[1], &keystores[2]);
+
+        let pk = ks1.pk().unwrap();
+        assert_eq!(pk, ks2.pk().unwrap());
+        assert_eq!(pk, ks3.pk().unwrap());
+        assert_eq!(
+            hex::encode(pk.compress().to_bytes()),
+            "f767d4aa50dd807264ba0ccbd70698220daa2cdbcf29712b88bce049d16accd4"
+        );
+    }
+
+    /// Serialize `ks` with its maps sorted, so equal keystores give equal bytes.
+    fn canonical_bytes(ks: &KeyStore) -> Vec<u8> {
+        use std::collections::BTreeMap;
+        let scalars = |m: &HashMap<u16, Scalar>| -> BTreeMap<u16, [u8; 32]> {
+            m.iter().map(|(k, v)| (*k, v.to_bytes())).collect()
+        };
+        let grid: BTreeMap<u16, BTreeMap<MpcAddr, Vec<[u8; 32]>>> = ks
+            .vss_com_grid
+            .iter()
+            .map(|(gid, dict)| {
+                let dict = dict
+                    .iter()
+                    .map(|(id, com)| (*id, com.iter().map(|p| p.compress().to_bytes()).collect()))
+                    .collect();
+                (*gid, dict)
+            })
+            .collect();
+        let mut ids: Vec<MpcAddr> = ks.ids.iter().cloned().collect();
+        ids.sort();
+        let canonical = (
+            scalars(&ks.ui_pergroup),
+            scalars(&ks.xi_pergroup),
+            grid,
+            ids,
+            &ks.aux,
+            ks.version,
+        );
+        serde_pickle::to_vec(&canonical, Default::default()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn staged_keygen_matches_monolithic() {
+        let key_arch = key_arch_of(2, 3);
+        let opts = KeygenOptions::default();
+
+        let expected = keygen_seeded_for_test(2, 3).await;
+
+        let messenger = MemoryTransport::default();
+        let staged = |i: u16| {
+            let (messenger, key_arch, opts) = (messenger.clone(), &key_arch, &opts);
+            async move {
+                let mut rng = ChaCha20Rng::seed_from_u64(i as u64);
+                let my_id = MpcAddr::new(1, i);
+                let state =
+                    keygen_round1_commit(&messenger, key_arch, my_id, "test", opts, &mut rng)
+                        .await
+                        .unwrap();
+                let state = keygen_round2_shares(&messenger, key_arch, state, "test", opts)
+                    .await
+                    .unwrap();
+                keygen_finalize(&messenger, key_arch, state, "test", opts)
+                    .await
+                    .unwrap()
+            }
+        };
+        let got = tokio::join!(staged(1), staged(2), staged(3));
+
+        assert_eq!(canonical_bytes(&got.0), canonical_bytes(&expected[0]));
+        assert_eq!(canonical_bytes(&got.1), canonical_bytes(&expected[1]));
+        assert_eq!(canonical_bytes(&got.2), canonical_bytes(&expected[2]));
+    }
+
+    /// Round-1 states of members 1..=3 of a 2-of-3 group, seeded like
+    /// `keygen_seeded_for_test`.
+    fn seeded_states(key_arch: &HashMap<u16, (usize, HashSet<MpcAddr>)>) -> Vec<KeygenState> {
+        (1..=3)
+            .map(|i| {
+                let mut rng = ChaCha20Rng::seed_from_u64(i as u64);
+                keygen_build_commitment(key_arch, MpcAddr::new(1, i), "test", &mut rng).unwrap()
+            })
+            .collect()
+    }
+
+    fn commitments_of(states: &[KeygenState]) -> HashMap<MpcAddr, KeyGenDKGProposedCommitment> {
+        states
+            .iter()
+            .map(|st| (st.my_id, st.dkg_commitment.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn built_commitment_is_reproducible_and_proven() {
+        let key_arch = key_arch_of(2, 3);
+        let (states, again) = (seeded_states(&key_arch), seeded_states(&key_arch));
+        for (st, st2) in states.iter().zip(again.iter()) {
+            assert_eq!(st.dkg_commitment.digest(), st2.dkg_commitment.digest());
+            assert_eq!(st.dkg_commitment.shares_commitment.len(), 2);
+            assert_eq!(st.shares.len(), 3);
+            assert!(verify_dkg_zkp(st.my_id, &st.dkg_commitment, "test").unwrap());
+            assert!(!verify_dkg_zkp(st.my_id, &st.dkg_commitment, "other").unwrap());
+        }
+
+        let stranger = MpcAddr::new(1, 4);
+        let err = keygen_build_commitment(&key_arch, stranger, "test", &mut OsRng).err();
+        assert_eq!(err.unwrap().get_name(), InvalidParameters);
+    }
+
+    #[test]
+    fn processed_commitments_reject_a_bad_proof() {
+        let key_arch = key_arch_of(2, 3);
+        let states = seeded_states(&key_arch);
+        let mut coms = commitments_of(&states);
+        let me = MpcAddr::new(1, 1);
+
+        let (vss_com_dict, digests) =
+            keygen_process_commitments(&key_arch, me, &coms, "test").unwrap();
+        for st in states.iter() {
+            let com = &st.dkg_commitment;
+            assert_eq!(vss_com_dict[&st.my_id], com.shares_commitment);
+            assert_eq!(digests[&st.my_id], com.digest());
+        }
+
+        let cheater = MpcAddr::new(1, 3);
+        coms.get_mut(&cheater).unwrap().zkp.sigma += Scalar::one();
+        let err = keygen_process_commitments(&key_arch, me, &coms, "test").unwrap_err();
+        assert_eq!(err.get_name(), CommitmentVerifyFailed);
+        assert!(err.to_string().contains(&format!("{:?}", vec![cheater])));
+
+        coms.remove(&cheater);
+        let err = keygen_process_commitments(&key_arch, me, &coms, "test").unwrap_err();
+        assert_eq!(err.get_name(), CommitmentVerifyFailed);
+    }
+
+    #[test]
+    fn pure_rounds_make_the_seeded_keystores() {
+        let key_arch = key_arch_of(2, 3);
+        let states = seeded_states(&key_arch);
+        let coms = commitments_of(&states);
+        let (vss_com_dict, _) =
+            keygen_process_commitments(&key_arch, states[0].my_id, &coms, "test").unwrap();
+        let dealt: HashMap<MpcAddr, HashMap<MpcAddr, AEAD>> = states
+            .iter()
+            .map(|st| {
+                let packs =
+                    keygen_deal_shares(&key_arch, st, &vss_com_dict, "test", AeadScheme::AesGcm);
+                (st.my_id, packs.unwrap())
+            })
+            .collect();
+        let inbox = |me: MpcAddr| -> HashMap<MpcAddr, AEAD> {
+            dealt
+                .iter()
+                .map(|(dealer, packs)| (*dealer, packs[&me].clone()))
+                .collect()
+        };
+
+        let keystores: Vec<KeyStore> = states
+            .iter()
+            .map(|st| {
+                let aead_dict = inbox(st.my_id);
+                keygen_merge_shares(&key_arch, st, vss_com_dict.clone(), &aead_dict, "test")
+                    .unwrap()
+            })
+            .collect();
+        for ks in keystores.iter() {
+            assert_eq!(
+                hex::encode(ks.pk().unwrap().compress().to_bytes()),
+                "f767d4aa50dd807264ba0ccbd70698220daa2cdbcf29712b88bce049d16accd4"
+            );
+        }
+
+        // Member 1 deals member 2 a pack meant for member 3.
+        let mut aead_dict = inbox(states[1].my_id);
+        aead_dict.insert(
+            states[0].my_id,
+            dealt[&states[0].my_id][&states[2].my_id].clone(),
+        );
+        let err = keygen_merge_shares(&key_arch, &states[1], vss_com_dict, &aead_dict, "test")
+            .unwrap_err();
+        assert_eq!(err.get_name(), ShareVerifyFailed);
+        assert!(err
+            .get_context()
+            .unwrap()
+            .contains(&format!("{:?}", vec![states[0].my_id])));
+    }
+
+    #[tokio::test]
+    async fn n_of_n_keygen_needs_every_share() {
+        for n in [2, 3] {
+            let keystores = keygen_for_test(n as usize, n).await;
+            let pk = keystores[0].group_public_key();
+            let shares = keystores[0].public_shares();
+            let interpolate = |signers: &HashSet<MpcAddr>| {
+                shares
+                    .iter()
+                    .filter(|(id, _)| signers.contains(id))
+                    .map(|(id, y_i)| crate::frost::lagrange_lambda(*id, signers).unwrap() * y_i)
+                    .sum::<EdwardsPoint>()
+            };
+            let everyone: HashSet<MpcAddr> = shares.iter().map(|(id, _)| *id).collect();
+            assert_eq!(interpolate(&everyone), pk, "n={}", n);
+            for left_out in everyone.iter() {
+                let mut others = everyone.clone();
+                others.remove(left_out);
+                assert_ne!(interpolate(&others), pk, "n={}", n);
+            }
+        }
+    }
+
+    /// `obj` after a trip through CBOR, then through pickle.
+    fn across_formats<T: Serialize + DeserializeOwned>(obj: &T) -> T {
+        let obj: T = CborCodec.decode(&CborCodec.encode(obj).unwrap()).unwrap();
+        PickleCodec
+            .decode(&PickleCodec.encode(&obj).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn round_messages_cross_wire_formats() {
+        let key_arch = key_arch_of(2, 3);
+        let states = seeded_states(&key_arch);
+        let coms = commitments_of(&states);
+        let (vss_com_dict, _) =
+            keygen_process_commitments(&key_arch, states[0].my_id, &coms, "test").unwrap();
+        let packs = keygen_deal_shares(
+            &key_arch,
+            &states[0],
+            &vss_com_dict,
+            "test",
+            AeadScheme::AesGcm,
+        )
+        .unwrap();
+
+        for (id, com) in across_formats(&coms).iter() {
+            assert_eq!(com.digest(), coms[id].digest());
+        }
+        assert_eq!(across_formats(&packs), packs);
+
+        // The layout a party in another language reads, see `CborCodec`.
+        let pack = AEAD {
+            ciphertext: vec![1, 2],
+            tag: vec![3],
+            scheme: AeadScheme::AesGcm,
+            tag_len: 16,
         };
+        let expected = [
+            "a4",                     // map of 4, by key length:
+            "63746167",               // "tag"
+            "8103",                   // [3]
+            "66736368656d65",         // "scheme"
+            "6641657347636d",         // "AesGcm"
+            "677461675f6c656e",       // "tag_len"
+            "10",                     // 16
+            "6a63697068657274657874", // "ciphertext"
+            "820102",                 // [1, 2]
+        ];
+        assert_eq!(
+            hex::encode(CborCodec.encode(&pack).unwrap()),
+            expected.concat()
+        );
+        let point = &states[0].dkg_commitment.zkp.g_k_i;
+        let mut expected = String::from("9820"); // array of 32
+        for b in point.compress().to_bytes() {
+            expected += &hex::encode(CborCodec.encode(&b).unwrap());
+        }
+        assert_eq!(hex::encode(CborCodec.encode(point).unwrap()), expected);
+    }
+
+    #[tokio::test]
+    async fn keygen_resumes_from_persisted_state() {
+        let key_arch = key_arch_of(2, 3);
+        let messenger = MemoryTransport::default();
+        let opts = KeygenOptions::default();
 
-        messenger
-            .send("dkg_com", my_id, gcast_id, &dkg_commitment)
+        // Party 1 persists its state after round 1 and "crashes".
+        let my_id = MpcAddr::new(1, 1);
+        let state = keygen_round1_commit(&messenger, &key_arch, my_id, "test", &opts, &mut OsRng)
             .await
-            .catch_()?;
-        let proposed_com_dict: HashMap<MpcAddr, KeyGenDKGProposedCommitment> = messenger
-            .gather("dkg_com", members, gcast_id)
+            .unwrap();
+        let persisted = serde_pickle::to_vec(&state, Default::default()).unwrap();
+        drop(state);
+
+        let resume = async {
+            let state: KeygenState =
+                serde_pickle::from_slice(&persisted, Default::default()).unwrap();
+            let state = keygen_round2_shares(&messenger, &key_arch, state, "test", &opts)
+                .await
+                .unwrap();
+            keygen_finalize(&messenger, &key_arch, state, "test", &opts)
+                .await
+                .unwrap()
+        };
+        let (ks1, others) = tokio::join!(
+            resume,
+            keygen_parties(&messenger, &key_arch, &[2, 3], &opts)
+        );
+        for ks in others {
+            assert_eq!(ks.unwrap().pk().unwrap(), ks1.pk().unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn timings_split_rounds_from_network() {
+        let messenger = MemoryTransport::default();
+        let key_arch = key_arch_of(2, 3);
+        let opts = KeygenOptions::default();
+        let futs = (1..=3).map(|i| {
+            let (messenger, key_arch, opts) = (&messenger, &key_arch, &opts);
+            async move {
+                let whoami = [MpcAddr::new(1, i)];
+                algo_keygen_timed(messenger, key_arch, &whoami, "test", opts).await
+            }
+        });
+        for res in futures::future::join_all(futs).await {
+            let (keystore, timings) = res.unwrap();
+            assert_eq!(keystore.th(1).unwrap(), 2);
+            for round in [timings.commit, timings.shares, timings.finalize] {
+                assert!(round > Duration::ZERO, "{:?}", timings);
+            }
+            let total = timings.commit + timings.shares + timings.finalize + timings.other_groups;
+            assert!(Duration::ZERO < timings.network && timings.network <= total);
+            assert!(total < Duration::from_secs(60));
+        }
+    }
+
+    #[tokio::test]
+    async fn progress_is_reported_in_order() {
+        let messenger = MemoryTransport::default();
+        let key_arch = key_arch_of(2, 3);
+        let opts = KeygenOptions::default();
+        let futs = (1..=3).map(|i| {
+            let (messenger, key_arch, opts) = (&messenger, &key_arch, &opts);
+            async move {
+                let phases = Mutex::new(Vec::new());
+                let whoami = [MpcAddr::new(1, i)];
+                let on_progress = |phase| phases.lock().unwrap().push(phase);
+                algo_keygen_with_progress(messenger, key_arch, &whoami, "test", opts, on_progress)
+                    .await
+                    .unwrap();
+                phases.into_inner().unwrap()
+            }
+        });
+        for phases in futures::future::join_all(futs).await {
+            let expected = [
+                KeygenPhase::Commit,
+                KeygenPhase::ExchangeCommitments,
+                KeygenPhase::ExchangeShares,
+                KeygenPhase::Finalize,
+            ];
+            assert_eq!(phases, expected);
+        }
+    }
+
+    #[test]
+    fn keygen_runs_without_tokio() {
+        let key_arch = key_arch_of(2, 3);
+        let messenger = MemoryTransport::default();
+        let opts = KeygenOptions::default();
+        let keystores =
+            futures::executor::block_on(keygen_parties(&messenger, &key_arch, &[1, 2, 3], &opts));
+        let pk = keystores[0].as_ref().unwrap().pk().unwrap();
+        for ks in keystores {
+            assert_eq!(ks.unwrap().pk().unwrap(), pk);
+        }
+
+        // one blocking party per thread, as in one process per party
+        let messenger = MemoryTransport::default();
+        let threads: Vec<_> = (1..=3)
+            .map(|i| {
+                let (messenger, key_arch) = (messenger.clone(), key_arch.clone());
+                std::thread::spawn(move || {
+                    let whoami = [MpcAddr::new(1, i)];
+                    algo_keygen_blocking(&messenger, &key_arch, &whoami, "test").unwrap()
+                })
+            })
+            .collect();
+        let keystores: Vec<KeyStore> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+        assert!(keystores.iter().all(|ks| ks.pk() == keystores[0].pk()));
+    }
+
+    #[tokio::test]
+    async fn parties_agree_on_group_public_key() {
+        let keystores = keygen_for_test(2, 3).await;
+        let (ks1, ks2, ks3) = (&keystores[0], &keystores[1], &keystores[2]);
+
+        let pk_bytes = ks1.group_public_key_bytes();
+        assert_eq!(pk_bytes, ks2.group_public_key_bytes());
+        assert_eq!(pk_bytes, ks3.group_public_key_bytes());
+        assert_eq!(ks1.group_public_key(), ks1.pk().unwrap());
+    }
+
+    /// Run rounds 1 and 2 for every member of group 1 in `key_arch`.
+    async fn run_round2(
+        messenger: &MemoryTransport,
+        key_arch: &HashMap<u16, (usize, HashSet<MpcAddr>)>,
+        opts: &KeygenOptions,
+    ) -> Vec<KeygenState> {
+        let mut ids: Vec<MpcAddr> = key_arch[&1].1.iter().cloned().collect();
+        ids.sort();
+        let futs = ids.into_iter().map(|my_id| async move {
+            let state = keygen_round1_commit(messenger, key_arch, my_id, "test", opts, &mut OsRng)
+                .await
+                .unwrap();
+            keygen_round2_shares(messenger, key_arch, state, "test", opts)
+                .await
+                .unwrap()
+        });
+        futures::future::join_all(futs).await
+    }
+
+    #[tokio::test]
+    async fn every_bad_share_is_reported() {
+        let key_arch = key_arch_of(2, 4);
+        let messenger = MemoryTransport::default();
+        let opts = KeygenOptions::default();
+        let mut states = run_round2(&messenger, &key_arch, &opts).await;
+
+        // Party 4 deals me a share that does not decrypt, and party 2 one
+        // that decrypts but does not match its commitment. The mailbox keeps
+        // their first shares, so I read from another where 1 and 3 are honest.
+        let me = MpcAddr::new(1, 1);
+        let tampered = MemoryTransport::default();
+        for honest in [me, MpcAddr::new(1, 3)] {
+            let pack: AEAD = messenger.receive("aead_share", honest, me).await.unwrap();
+            tampered
+                .send("aead_share", honest, me, &pack)
+                .await
+                .unwrap();
+        }
+        let garbage = aes_encrypt(&[0u8; 32], &[0u8; 32]).unwrap();
+        tampered
+            .send("aead_share", MpcAddr::new(1, 4), me, &garbage)
+            .await
+            .unwrap();
+        let state2 = &states[1];
+        let vss_com_dict = state2.vss_com_dict.as_ref().unwrap();
+        let aes_key =
+            eval_aes_key(&state2.party_key, vss_com_dict, state2.my_id, me, "test").unwrap();
+        let tampered_share = state2.shares[&me] + Scalar::one();
+        let aad = p2p_aad(state2.my_id, me, "aead_share");
+        let pack = aes_encrypt_with_aad(&*aes_key, &tampered_share.to_bytes(), &aad).unwrap();
+        tampered
+            .send("aead_share", state2.my_id, me, &pack)
             .await
-            .catch_()?;
+            .unwrap();
 
-        // verify and collect others' vss_com_dict
-        let vss_com_dict: HashMap<MpcAddr, Vec<EdwardsPoint>> =
-            keygen_validate_peers(&proposed_com_dict, &context).catch_()?;
-        drop(proposed_com_dict);
-        for (_, vss_com) in vss_com_dict.iter() {
-            assert_throw!(vss_com.len() == *th); // to avoid DKG attack via increasing threshold on the fly.
-        }
-
-        // use others' pubkey to construct aes key
-        let mut aes_key_dict: HashMap<MpcAddr, [u8; 32]> = HashMap::new();
-        for j in members.iter() {
-            let com = vss_com_dict.get(j).ifnone_()?;
-            let aes_key = com[0] * &party_key.u_i; // aes_key = u_j * g_u_i
-            let aes_key = aes_key.compress().to_bytes();
-            aes_key_dict.insert(*j, aes_key);
-        }
-
-        // scatter vss shares via aes-gcm encrypted channel
-        for id in members.iter() {
-            let aes_key = aes_key_dict.get(id).ifnone_()?;
-            let plaintext = shares.get(id).ifnone_()?.to_bytes();
-            let aead_pack_i = aes_encrypt(aes_key, &plaintext).catch_()?;
-            messenger
-                .send("aead_share", my_id, *id, &aead_pack_i)
+        let state1 = states.remove(0);
+        let err = keygen_finalize(&tampered, &key_arch, state1, "test", &opts)
+            .await
+            .unwrap_err();
+        assert_eq!(err.get_name(), ShareVerifyFailed);
+        let expected = format!("{:?}", vec![MpcAddr::new(1, 2), MpcAddr::new(1, 4)]);
+        assert!(err.get_context().unwrap().contains(&expected), "{:?}", err);
+    }
+
+    #[test]
+    fn channel_keys_match_per_peer_ones() {
+        let key_arch = key_arch_of(3, 5);
+        let members = &key_arch[&1].1;
+        let states: Vec<KeygenState> = (1..=5)
+            .map(|i| {
+                let id = MpcAddr::new(1, i);
+                keygen_build_commitment(&key_arch, id, "test", &mut OsRng).unwrap()
+            })
+            .collect();
+        let vss_com_dict: HashMap<MpcAddr, Vec<EdwardsPoint>> = states
+            .iter()
+            .map(|st| (st.my_id, st.dkg_commitment.shares_commitment.clone()))
+            .collect();
+        for state in states.iter() {
+            let keys = keygen_channel_keys(state, &vss_com_dict, members, "test").unwrap();
+            assert_eq!(keys.len(), 5);
+            for (peer, key) in keys.iter() {
+                let one = eval_aes_key(&state.party_key, &vss_com_dict, state.my_id, *peer, "test");
+                assert_eq!(*key, one.unwrap());
+                // the peer derives the same key from its side
+                let theirs = &states[peer.member_id() as usize - 1];
+                let mirror =
+                    eval_aes_key(&theirs.party_key, &vss_com_dict, *peer, state.my_id, "test");
+                assert_eq!(*key, mirror.unwrap());
+            }
+        }
+        let stranger = HashSet::from([MpcAddr::new(1, 9)]);
+        let err = keygen_channel_keys(&states[0], &vss_com_dict, &stranger, "test").unwrap_err();
+        assert_eq!(err.get_name(), CommitmentVerifyFailed);
+    }
+
+    #[tokio::test]
+    async fn short_share_is_reported() {
+        let key_arch = key_arch_of(2, 3);
+        let messenger = MemoryTransport::default();
+        let opts = KeygenOptions::default();
+        let mut states = run_round2(&messenger, &key_arch, &opts).await;
+
+        // Party 2 deals me a well-encrypted share of 31 bytes.
+        let me = MpcAddr::new(1, 1);
+        let tampered = MemoryTransport::default();
+        for honest in [me, MpcAddr::new(1, 3)] {
+            let pack: AEAD = messenger.receive("aead_share", honest, me).await.unwrap();
+            tampered
+                .send("aead_share", honest, me, &pack)
                 .await
-                .catch_()?;
+                .unwrap();
         }
-        let aead_dict: HashMap<MpcAddr, AEAD> = messenger
-            .gather("aead_share", members, my_id)
+        let state2 = &states[1];
+        let vss_com_dict = state2.vss_com_dict.as_ref().unwrap();
+        let aes_key =
+            eval_aes_key(&state2.party_key, vss_com_dict, state2.my_id, me, "test").unwrap();
+        let aad = p2p_aad(state2.my_id, me, "aead_share");
+        let share = state2.shares[&me].to_bytes();
+        let pack = aes_encrypt_with_aad(&*aes_key, &share[..31], &aad).unwrap();
+        tampered
+            .send("aead_share", state2.my_id, me, &pack)
             .await
-            .catch_()?;
+            .unwrap();
 
-        for x in shares.values_mut() {
-            x.zeroize();
+        let state1 = states.remove(0);
+        let err = keygen_finalize(&tampered, &key_arch, state1, "test", &opts)
+            .await
+            .unwrap_err();
+        assert_eq!(err.get_name(), ShareVerifyFailed);
+        let msg = err.get_context().unwrap();
+        assert!(
+            msg.contains("Party 1.2 sent a scalar of 31 bytes, expected 32"),
+            "{}",
+            msg
+        );
+    }
+
+    #[tokio::test]
+    async fn unreduced_share_is_reported() {
+        let key_arch = key_arch_of(2, 3);
+        let messenger = MemoryTransport::default();
+        let opts = KeygenOptions::default();
+        let mut states = run_round2(&messenger, &key_arch, &opts).await;
+
+        // Party 2 deals me its share plus l, which reduces to the right share.
+        let me = MpcAddr::new(1, 1);
+        let tampered = MemoryTransport::default();
+        for honest in [me, MpcAddr::new(1, 3)] {
+            let pack: AEAD = messenger.receive("aead_share", honest, me).await.unwrap();
+            tampered
+                .send("aead_share", honest, me, &pack)
+                .await
+                .unwrap();
         }
-        drop(shares);
+        let state2 = &states[1];
+        let vss_com_dict = state2.vss_com_dict.as_ref().unwrap();
+        let aes_key =
+            eval_aes_key(&state2.party_key, vss_com_dict, state2.my_id, me, "test").unwrap();
+        let share = state2.shares[&me].to_bytes();
+        let l = curve25519_dalek::constants::BASEPOINT_ORDER.to_bytes();
+        let mut unreduced = [0u8; 32];
+        let mut carry = 0u16;
+        for i in 0..32 {
+            let sum = share[i] as u16 + l[i] as u16 + carry;
+            unreduced[i] = sum as u8;
+            carry = sum >> 8;
+        }
+        assert_eq!(Scalar::from_bytes_mod_order(unreduced), state2.shares[&me]);
+        let aad = p2p_aad(state2.my_id, me, "aead_share");
+        let pack = aes_encrypt_with_aad(&*aes_key, &unreduced, &aad).unwrap();
+        tampered
+            .send("aead_share", state2.my_id, me, &pack)
+            .await
+            .unwrap();
+
+        let state1 = states.remove(0);
+        let err = keygen_finalize(&tampered, &key_arch, state1, "test", &opts)
+            .await
+            .unwrap_err();
+        assert_eq!(err.get_name(), ShareVerifyFailed);
+        let expected = format!("{:?}", vec![MpcAddr::new(1, 2)]);
+        assert!(err.get_context().unwrap().contains(&expected), "{:?}", err);
+    }
 
-        // gather vss shares
-        let mut party_shares: HashMap<MpcAddr, Scalar> = HashMap::new();
-        for j in members.iter() {
-            let aes_key = aes_key_dict.get(j).ifnone_()?;
-            let aead_pack = aead_dict.get(j).ifnone_()?;
-            let out = aes_decrypt(aes_key, &aead_pack).catch_()?;
-            assert_throw!(out.len() == 32);
-            let mut out_arr = [0u8; 32];
-            out_arr.copy_from_slice(&out);
-            let out_fe = Scalar::from_bytes_mod_order(out_arr);
-            party_shares.insert(*j, out_fe);
+    #[tokio::test]
+    async fn rebroadcast_commitment_is_rejected_at_its_sender() {
+        let key_arch = key_arch_of(2, 3);
+        let messenger = MemoryTransport::default();
+        let opts = KeygenOptions::default();
+
+        // Party 3 tries to broadcast a second commitment, then deals from the first.
+        let my_id = MpcAddr::new(1, 3);
+        let first = keygen_round1_commit(&messenger, &key_arch, my_id, "test", &opts, &mut OsRng)
+            .await
+            .unwrap();
+        let res =
+            keygen_round1_commit(&messenger, &key_arch, my_id, "test", &opts, &mut OsRng).await;
+        let err = res.err().unwrap();
+        assert_eq!(err.get_name(), TransportError);
+        assert!(err.to_string().contains("DuplicateMessage"), "{}", err);
+        assert!(err.to_string().contains(&my_id.to_string()), "{}", err);
+
+        let rest = async {
+            let state = keygen_round2_shares(&messenger, &key_arch, first, "test", &opts).await?;
+            keygen_finalize(&messenger, &key_arch, state, "test", &opts).await
+        };
+        let (res3, others) =
+            tokio::join!(rest, keygen_parties(&messenger, &key_arch, &[1, 2], &opts));
+        let pk = res3.unwrap().group_public_key();
+        for res in others {
+            assert_eq!(res.unwrap().group_public_key(), pk);
         }
+    }
 
-        // compute x_i
-        let signing_key: Scalar = merge_vss_share(&party_shares, &vss_com_dict, my_id).catch_()?;
-        for x in party_shares.values_mut() {
-            x.zeroize();
+    #[test]
+    fn commitments_are_looked_up_by_sender() {
+        let members = group_of(&[1, 2, 3]);
+        let th = 2;
+        let propose = |id: MpcAddr| {
+            // dealt as member 1, since a stranger cannot deal to the group
+            let party_key = PartyKey::new(&mut OsRng);
+            let dealer = MpcAddr::new(1, 1);
+            let (shares_commitment, _) =
+                generate_vss_share(&party_key.u_i, dealer, &members, th, &mut OsRng).unwrap();
+            let challenge =
+                generate_dkg_challenge(id, "test", &party_key.g_u_i(), &party_key.g_k_i()).unwrap();
+            KeyGenDKGProposedCommitment {
+                shares_commitment,
+                zkp: KeyGenZKP {
+                    g_k_i: party_key.g_k_i(),
+                    sigma: party_key.k_i + party_key.u_i * challenge,
+                },
+            }
+        };
+
+        // arriving in descending order changes nothing
+        let mut proposed: Vec<(MpcAddr, KeyGenDKGProposedCommitment)> = [3, 1, 2]
+            .iter()
+            .map(|i| MpcAddr::new(1, *i))
+            .map(|id| (id, propose(id)))
+            .collect();
+        let expected: HashMap<MpcAddr, Vec<EdwardsPoint>> = proposed
+            .iter()
+            .map(|(id, com)| (*id, com.shares_commitment.clone()))
+            .collect();
+        let dict: HashMap<_, _> = proposed.iter().cloned().collect();
+        let got = validate_commitments(&dict, th, &members, "test").unwrap();
+        assert_eq!(got, expected);
+
+        // a gap is named
+        proposed.retain(|(id, _)| id.member_id() != 2);
+        proposed.push((MpcAddr::new(1, 4), propose(MpcAddr::new(1, 4))));
+        let mut dict: HashMap<_, _> = proposed.into_iter().collect();
+        let err = validate_commitments(&dict, th, &members, "test").unwrap_err();
+        assert_eq!(err.get_name(), CommitmentVerifyFailed);
+        assert!(err
+            .get_context()
+            .unwrap()
+            .contains(&format!("{:?}", vec![MpcAddr::new(1, 2)])));
+
+        // so is a stranger
+        dict.insert(MpcAddr::new(1, 2), propose(MpcAddr::new(1, 2)));
+        let err = validate_commitments(&dict, th, &members, "test").unwrap_err();
+        assert!(err
+            .get_context()
+            .unwrap()
+            .contains(&format!("{:?}", vec![MpcAddr::new(1, 4)])));
+    }
+
+    #[test]
+    fn party_shares_are_zeroed() {
+        let ids = [MpcAddr::new(1, 1), MpcAddr::new(1, 2)];
+        let mut shares = PartyShares::default();
+        for (i, id) in ids.iter().enumerate() {
+            shares.0.insert(*id, Scalar::from(7u64 + i as u64));
+        }
+        // `Drop` runs exactly this, so every exit of `keygen_finalize` clears them.
+        shares.zeroize();
+        for id in ids.iter() {
+            assert_eq!(shares.0[id].to_bytes(), [0u8; 32]);
         }
+    }
 
-        keystore.ui_pergroup.insert(gid, party_key.u_i);
-        keystore.xi_pergroup.insert(gid, signing_key);
-        keystore.vss_com_grid.insert(gid, vss_com_dict);
+    /// Forwards the first `budget` sends, then loses the connection for good.
+    #[derive(Clone)]
+    struct CutoffMessenger {
+        inner: MemoryTransport,
+        budget: Arc<AtomicU32>,
     }
 
-    // Fetch vss_com of members in other groups
-    let mut key_arch = key_arch.clone();
-    for my_id in whoami.iter() {
-        let gid = my_id.group_id();
-        key_arch.remove(&gid);
+    #[async_trait]
+    impl Messenger for CutoffMessenger {
+        type E = Box<Exception>;
+
+        async fn send<T>(&self, topic: &str, src: MpcAddr, dst: MpcAddr, obj: &T) -> Outcome<()>
+        where
+            T: Serialize + DeserializeOwned + Send + Sync,
+        {
+            let budget = self.budget.load(Ordering::SeqCst);
+            assert_throw!(budget > 0, "ConnectionLost", "mocked");
+            self.budget.store(budget - 1, Ordering::SeqCst);
+            self.inner.send(topic, src, dst, obj).await
+        }
+
+        async fn receive<T>(&self, topic: &str, src: MpcAddr, dst: MpcAddr) -> Outcome<T>
+        where
+            T: Serialize + DeserializeOwned + Send + Sync,
+        {
+            self.inner.receive(topic, src, dst).await
+        }
+
+        async fn scatter<T>(
+            &self,
+            topic: &str,
+            src: MpcAddr,
+            dsts: &HashSet<MpcAddr>,
+            obj: &T,
+        ) -> Outcome<()>
+        where
+            T: Serialize + DeserializeOwned + Send + Sync,
+        {
+            self.inner.scatter(topic, src, dsts, obj).await
+        }
+
+        async fn gather<T>(
+            &self,
+            topic: &str,
+            srcs: &HashSet<MpcAddr>,
+            dst: MpcAddr,
+        ) -> Outcome<HashMap<MpcAddr, T>>
+        where
+            T: Serialize + DeserializeOwned + Send + Sync,
+        {
+            self.inner.gather(topic, srcs, dst).await
+        }
     }
-    for (gid, (th, members)) in key_arch.iter() {
-        let gcast_id = MpcAddr::gcast_id(*gid);
-        let proposed_com_dict: HashMap<MpcAddr, KeyGenDKGProposedCommitment> = messenger
-            .gather("dkg_com", members, gcast_id)
-            .await
-            .catch_()?;
 
-        // verify and collect others' vss_com_dict
-        let vss_com_dict: HashMap<MpcAddr, Vec<EdwardsPoint>> =
-            keygen_validate_peers(&proposed_com_dict, &context).catch_()?;
-        drop(proposed_com_dict);
-        for (_, vss_com) in vss_com_dict.iter() {
-            assert_throw!(vss_com.len() == *th); // to avoid DKG attack via increasing threshold on the fly.
+    /// Shows its holder another round-1 commitment of `forger` than the one
+    /// `forger` broadcast, as if `forger` had sent each member its own.
+    #[derive(Clone)]
+    struct EquivocatingMessenger {
+        inner: MemoryTransport,
+        forger: MpcAddr,
+        forged: Vec<u8>,
+    }
+
+    #[async_trait]
+    impl Messenger for EquivocatingMessenger {
+        type E = Box<Exception>;
+
+        async fn send<T>(&self, topic: &str, src: MpcAddr, dst: MpcAddr, obj: &T) -> Outcome<()>
+        where
+            T: Serialize + DeserializeOwned + Send + Sync,
+        {
+            self.inner.send(topic, src, dst, obj).await
         }
 
-        keystore.vss_com_grid.insert(*gid, vss_com_dict);
+        async fn receive<T>(&self, topic: &str, src: MpcAddr, dst: MpcAddr) -> Outcome<T>
+        where
+            T: Serialize + DeserializeOwned + Send + Sync,
+        {
+            self.inner.receive(topic, src, dst).await
+        }
+
+        async fn scatter<T>(
+            &self,
+            topic: &str,
+            src: MpcAddr,
+            dsts: &HashSet<MpcAddr>,
+            obj: &T,
+        ) -> Outcome<()>
+        where
+            T: Serialize + DeserializeOwned + Send + Sync,
+        {
+            self.inner.scatter(topic, src, dsts, obj).await
+        }
+
+        async fn gather<T>(
+            &self,
+            topic: &str,
+            srcs: &HashSet<MpcAddr>,
+            dst: MpcAddr,
+        ) -> Outcome<HashMap<MpcAddr, T>>
+        where
+            T: Serialize + DeserializeOwned + Send + Sync,
+        {
+            let mut objs = self.inner.gather(topic, srcs, dst).await?;
+            if topic == "dkg_com" {
+                let forged = serde_pickle::from_slice(&self.forged, Default::default()).catch_()?;
+                objs.insert(self.forger, forged);
+            }
+            Ok(objs)
+        }
     }
 
-    // Archive my shard_ids
-    keystore.ids = whoami.iter().cloned().collect();
+    #[tokio::test]
+    async fn equivocating_dealer_is_named_by_every_member() {
+        let key_arch = key_arch_of(2, 3);
+        let messenger = MemoryTransport::default();
+        let opts = KeygenOptions::default();
 
-    Ok(keystore)
+        // A commitment of member 3 with a valid proof, but not the one it broadcast.
+        let forger = MpcAddr::new(1, 3);
+        let members = &key_arch[&1].1;
+        let party_key = PartyKey::new(&mut OsRng);
+        let (shares_commitment, _) =
+            generate_vss_share(&party_key.u_i, forger, members, 2, &mut OsRng).unwrap();
+        let challenge =
+            generate_dkg_challenge(forger, "test", &party_key.g_u_i(), &party_key.g_k_i()).unwrap();
+        let forged = KeyGenDKGProposedCommitment {
+            shares_commitment,
+            zkp: KeyGenZKP {
+                g_k_i: party_key.g_k_i(),
+                sigma: party_key.k_i + party_key.u_i * challenge,
+            },
+        };
+        let equivocating = EquivocatingMessenger {
+            inner: messenger.clone(),
+            forger,
+            forged: serde_pickle::to_vec(&forged, Default::default()).unwrap(),
+        };
+
+        let whoami = [MpcAddr::new(1, 1)];
+        let (res1, others) = tokio::join!(
+            algo_keygen_with_options(&equivocating, &key_arch, &whoami, "test", &opts),
+            keygen_parties(&messenger, &key_arch, &[2, 3], &opts)
+        );
+        for res in others.into_iter().chain([res1]) {
+            let err = res.unwrap_err();
+            assert_eq!(err.get_name(), Equivocation);
+            assert!(err
+                .get_context()
+                .unwrap()
+                .contains(&format!("{:?}", vec![forger])));
+        }
+    }
+
+    #[tokio::test]
+    async fn dropped_keygen_zeroizes_its_secrets() {
+        // Group 2 never shows up, so group 1 merges its shards, then waits.
+        let mut key_arch = key_arch_of(2, 2);
+        let absent: HashSet<MpcAddr> = [MpcAddr::new(2, 1), MpcAddr::new(2, 2)].into();
+        key_arch.insert(2, (2, absent));
+        let messenger = MemoryTransport::default();
+        let futs = [1, 2].map(|i| {
+            let whoami = [MpcAddr::new(1, i)];
+            let key_arch = &key_arch;
+            let messenger = &messenger;
+            async move { algo_keygen(messenger, key_arch, &whoami, "test").await }
+        });
+        let before = ZEROIZED.with(|count| count.get());
+        let mut keygen = Box::pin(futures::future::join_all(futs));
+        let polled = tokio::time::timeout(std::time::Duration::from_millis(300), &mut keygen);
+        assert!(polled.await.is_err());
+        drop(keygen);
+
+        // Each of the 2 shards zeroized its round state, u_i, k_i and the
+        // 2 shares it dealt, and at the drop its merged u_i and x_i.
+        let zeroized = ZEROIZED.with(|count| count.get()) - before;
+        assert_eq!(zeroized, 2 * (2 + 2) + 2 * 2);
+    }
+
+    #[tokio::test]
+    async fn abort_releases_the_waiting_peers() {
+        let key_arch = key_arch_of(2, 3);
+        let messenger = MemoryTransport::default();
+        let opts = KeygenOptions {
+            deadline: Some(Instant::now() + std::time::Duration::from_secs(30)),
+            ..Default::default()
+        };
+
+        // Party 1 is shown a commitment of member 3 with a bad proof,
+        // so it never echoes the commitments its peers wait for.
+        let forger = MpcAddr::new(1, 3);
+        let members = &key_arch[&1].1;
+        let party_key = PartyKey::new(&mut OsRng);
+        let (shares_commitment, _) =
+            generate_vss_share(&party_key.u_i, forger, members, 2, &mut OsRng).unwrap();
+        let forged = KeyGenDKGProposedCommitment {
+            shares_commitment,
+            zkp: KeyGenZKP {
+                g_k_i: party_key.g_k_i(),
+                sigma: party_key.k_i,
+            },
+        };
+        let equivocating = EquivocatingMessenger {
+            inner: messenger.clone(),
+            forger,
+            forged: serde_pickle::to_vec(&forged, Default::default()).unwrap(),
+        };
+
+        let whoami = [MpcAddr::new(1, 1)];
+        let start = Instant::now();
+        let (res1, others) = tokio::join!(
+            algo_keygen_with_options(&equivocating, &key_arch, &whoami, "test", &opts),
+            keygen_parties(&messenger, &key_arch, &[2, 3], &opts)
+        );
+        assert!(start.elapsed() < std::time::Duration::from_secs(10));
+        assert_eq!(res1.err().unwrap().get_name(), CommitmentVerifyFailed);
+        for res in others {
+            let err = res.unwrap_err();
+            assert_eq!(err.get_name(), Aborted);
+            let ctx = err.get_context().unwrap();
+            assert!(ctx.contains("Party 1.1") && ctx.contains(CommitmentVerifyFailed));
+        }
+    }
+
+    #[tokio::test]
+    async fn non_canonical_commitment_point_names_its_sender() {
+        let key_arch = key_arch_of(2, 3);
+        let messenger = MemoryTransport::default();
+        let opts = KeygenOptions {
+            deadline: Some(Instant::now() + std::time::Duration::from_secs(30)),
+            ..Default::default()
+        };
+
+        // Party 1 is shown a commitment of member 3 with a coefficient
+        // encoded as $y = p$, which dalek's serde would read as $y = 0$.
+        let forger = MpcAddr::new(1, 3);
+        let state = keygen_build_commitment(&key_arch, forger, "test", &mut OsRng).unwrap();
+        let mut wire = WireDKGCommitment::from(&state.dkg_commitment);
+        let mut y_is_p = [0xffu8; 32];
+        y_is_p[0] = 0xed;
+        y_is_p[31] = 0x7f;
+        wire.shares_commitment[1] = curve25519_dalek::edwards::CompressedEdwardsY(y_is_p);
+        let equivocating = EquivocatingMessenger {
+            inner: messenger.clone(),
+            forger,
+            forged: serde_pickle::to_vec(&wire, Default::default()).unwrap(),
+        };
+
+        let whoami = [MpcAddr::new(1, 1)];
+        let (res1, others) = tokio::join!(
+            algo_keygen_with_options(&equivocating, &key_arch, &whoami, "test", &opts),
+            keygen_parties(&messenger, &key_arch, &[2, 3], &opts)
+        );
+        let err = res1.unwrap_err();
+        assert_eq!(err.get_name(), NonCanonicalEncoding);
+        assert!(
+            err.get_context().unwrap().contains("Party 1.3"),
+            "{:?}",
+            err
+        );
+        for res in others {
+            let ctx = res.unwrap_err().get_context().unwrap().to_string();
+            assert!(ctx.contains(NonCanonicalEncoding), "{}", ctx);
+        }
+    }
+
+    #[tokio::test]
+    async fn messenger_failing_mid_round_is_a_transport_error() {
+        let key_arch = key_arch_of(2, 3);
+        let messenger = MemoryTransport::default();
+        // Party 1 sends its commitment, its echo and one of its three shares,
+        // then drops.
+        let cutoff = CutoffMessenger {
+            inner: messenger.clone(),
+            budget: Arc::new(AtomicU32::new(3)),
+        };
+        let opts = KeygenOptions {
+            deadline: Some(Instant::now() + std::time::Duration::from_millis(500)),
+            ..Default::default()
+        };
+
+        let whoami = [MpcAddr::new(1, 1)];
+        let (res1, others) = tokio::join!(
+            algo_keygen_with_options(&cutoff, &key_arch, &whoami, "test", &opts),
+            keygen_parties(&messenger, &key_arch, &[2, 3], &opts)
+        );
+        let err = res1.unwrap_err();
+        assert_eq!(err.get_name(), TransportError);
+        assert!(err.get_context().unwrap().contains("\"aead_share\""));
+        assert_eq!(cutoff.budget.load(Ordering::SeqCst), 0);
+        // Party 1 delivered at most one share, so a peer lacks its share.
+        let stalled: Vec<_> = others.into_iter().filter_map(|res| res.err()).collect();
+        assert!(!stalled.is_empty());
+        for err in stalled {
+            assert_eq!(err.get_name(), "KeygenTimeout");
+        }
+    }
+
+    #[tokio::test]
+    async fn keygen_failures_are_categorized() {
+        let key_arch = key_arch_of(2, 3);
+        let messenger = MemoryTransport::default();
+
+        let whoami = [MpcAddr::new(1, 1)];
+        let err = algo_keygen(&messenger, &key_arch, &whoami, "")
+            .await
+            .unwrap_err();
+        assert_eq!(err.get_name(), InvalidParameters);
+
+        // Every party refuses every commitment.
+        let strict = messenger.with_max_message_size(1);
+        let keygen = |i: u16| {
+            let (strict, key_arch) = (strict.clone(), &key_arch);
+            async move {
+                let whoami = [MpcAddr::new(1, i)];
+                algo_keygen(&strict, key_arch, &whoami, "test").await
+            }
+        };
+        let (res1, _, _) = tokio::join!(keygen(1), keygen(2), keygen(3));
+        let err = res1.unwrap_err();
+        assert_eq!(err.get_name(), TransportError);
+        assert!(err.get_context().unwrap().contains("dkg_com"));
+    }
+
+    #[tokio::test]
+    async fn absent_party_times_out_naming_the_round() {
+        let key_arch = key_arch_of(2, 3);
+        let opts = KeygenOptions {
+            deadline: Some(Instant::now() + std::time::Duration::from_millis(200)),
+            ..Default::default()
+        };
+
+        // Party 3 never joins, so nobody gets its commitment.
+        let messenger = MemoryTransport::default();
+        for res in keygen_parties(&messenger, &key_arch, &[1, 2], &opts).await {
+            let err = res.unwrap_err();
+            assert_eq!(err.get_name(), "KeygenTimeout");
+            assert!(err.get_context().unwrap().contains("\"dkg_com\""));
+        }
+
+        // Party 3 commits and echoes, but never deals its shares.
+        let messenger = MemoryTransport::default();
+        let opts = KeygenOptions {
+            deadline: Some(Instant::now() + std::time::Duration::from_millis(200)),
+            ..Default::default()
+        };
+        let my_id = MpcAddr::new(1, 3);
+        let state = keygen_round1_commit(&messenger, &key_arch, my_id, "test", &opts, &mut OsRng)
+            .await
+            .unwrap();
+        let cutoff = CutoffMessenger {
+            inner: messenger.clone(),
+            budget: Arc::new(AtomicU32::new(1)),
+        };
+        let (res3, others) = tokio::join!(
+            keygen_round2_shares(&cutoff, &key_arch, state, "test", &opts),
+            keygen_parties(&messenger, &key_arch, &[1, 2], &opts)
+        );
+        assert_eq!(res3.err().unwrap().get_name(), TransportError);
+        for res in others {
+            let err = res.unwrap_err();
+            assert_eq!(err.get_name(), "KeygenTimeout");
+            assert!(err.get_context().unwrap().contains("\"aead_share\""));
+        }
+    }
 }
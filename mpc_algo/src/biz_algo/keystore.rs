@@ -0,0 +1,1238 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use bincode::Options;
+#[cfg(feature = "hd")]
+use bip32::{ChainCode, Language, Mnemonic};
+use curve25519_dalek::constants;
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use curve25519_dalek::scalar::Scalar;
+use libexception::*;
+use mpc_spec::*;
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use super::aes::*;
+#[cfg(feature = "hd")]
+use super::hd::export_xpub;
+use super::sign::is_signable_path;
+use super::transcript::public_transcript_of;
+use super::{validate_group, PublicTranscript};
+use crate::frost::{eval_xi_com, verify_signing_share};
+
+pub type KeyStore = MultiShard<Scalar, EdwardsPoint>;
+
+/// Thrown by `KeyStore::unseal` when the password is wrong or the blob was tampered with.
+pub const KeyStoreAuthException: &str = "KeyStoreAuthException";
+
+// Layout of a sealed keystore:
+// MAGIC | log_n (1) | r (4, BE) | p (4, BE) | salt (16) | nonce (12) | ciphertext
+const SEAL_MAGIC: &[u8; 4] = b"KSS1";
+const SEAL_SALT_LEN: usize = 16;
+const SEAL_NONCE_LEN: usize = 12;
+const SEAL_HEADER_LEN: usize = SEAL_MAGIC.len() + 1 + 4 + 4 + SEAL_SALT_LEN;
+// The header is read before the password is checked, so its scrypt cost is
+// bounded: at most 128 * r * 2^log_n = 2 GiB of memory and 4 lanes.
+const SEAL_MAX_LOG_N: u8 = 20;
+const SEAL_MAX_R: u32 = 16;
+const SEAL_MAX_P: u32 = 4;
+// The interactive parameters of the scrypt paper, 16 MiB of memory.
+const SEAL_MIN_LOG_N: u8 = 14;
+const SEAL_MIN_R: u32 = 8;
+
+/// scrypt cost of `KeyStoreExt::seal_with_options`, kept in the header so
+/// that `unseal` needs only the password. `SealOptions::default()` gives the
+/// `log_n = 17, r = 8, p = 1` of `seal`.
+///
+/// Sealing refuses, with `InvalidSealOptions`, less than `log_n = 14, r = 8,
+/// p = 1`, or more than `unseal` accepts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SealOptions {
+    /// The memory and time cost: $N = 2^{log_n}$ blocks of $128 \cdot r$ bytes.
+    pub log_n: u8,
+    /// The block size.
+    pub r: u32,
+    /// The parallelism, i.e. how many times the time cost is paid.
+    pub p: u32,
+}
+
+impl Default for SealOptions {
+    fn default() -> Self {
+        let params = scrypt::Params::recommended();
+        SealOptions {
+            log_n: params.log_n(),
+            r: params.r(),
+            p: params.p(),
+        }
+    }
+}
+
+/// Ed25519-specific operations on `KeyStore`.
+pub trait KeyStoreExt: Sized {
+    /// Export the signing share $x_i$ of group `gid` as a 24-word BIP39 phrase.
+    ///
+    /// NOTE: The phrase backs up ONE share of ONE group. It is not the group
+    /// secret and cannot restore any other member's share.
+    #[cfg(feature = "hd")]
+    fn to_mnemonic(&self, gid: u16, lang: Language) -> Outcome<String>;
+
+    /// Restore a single-shard keystore from a phrase made by `to_mnemonic`.
+    ///
+    /// The phrase only carries $x_i$, so the group's public VSS commitments
+    /// (`vss_com_dict`) must be provided from elsewhere, e.g. a peer's keystore.
+    /// The restored $x_i$ is checked against them.
+    ///
+    /// What is lost is this party's own dealer secret $u_i$: `ui_pergroup`
+    /// stays empty. Signing and reshare only read `xi_pergroup`, so both work
+    /// on a restored keystore.
+    #[cfg(feature = "hd")]
+    fn from_mnemonic(
+        phrase: &str,
+        lang: Language,
+        my_id: MpcAddr,
+        vss_com_dict: HashMap<MpcAddr, Vec<EdwardsPoint>>,
+    ) -> Outcome<Self>;
+
+    /// Serialize and encrypt the keystore under `password`.
+    /// The AES key is stretched from the password with scrypt
+    /// (`scrypt::Params::recommended()`, i.e. `log_n = 17, r = 8, p = 1`),
+    /// whose salt and parameters are kept in the header.
+    fn seal(&self, password: &str) -> Outcome<Vec<u8>>;
+
+    /// Same as `seal`, with the scrypt cost of `opts`.
+    fn seal_with_options(&self, password: &str, opts: &SealOptions) -> Outcome<Vec<u8>>;
+
+    /// Decrypt a blob made by `seal`.
+    /// A wrong password throws `KeyStoreAuthException`. A header asking for
+    /// more scrypt work than `log_n = 20, r = 16, p = 4` throws
+    /// `KeyStoreFormatException` before any key is derived.
+    fn unseal(bytes: &[u8], password: &str) -> Outcome<Self>;
+
+    /// The aggregate public key, i.e. the sum of every dealer's zeroth
+    /// commitment coefficient across all groups.
+    /// All honest parties of a keygen get the same point.
+    fn group_public_key(&self) -> EdwardsPoint;
+
+    /// `group_public_key` in its 32-byte compressed form, which is also
+    /// the Solana/ed25519 public key.
+    fn group_public_key_bytes(&self) -> [u8; 32] {
+        self.group_public_key().compress().to_bytes()
+    }
+
+    /// Short id of the ceremony the keystore comes from, for operators to
+    /// compare (e.g. hex-encoded) across machines without exposing secrets.
+    ///
+    /// The first 8 bytes of SHA-256 over `group_public_key_bytes`, then for
+    /// each group in ascending order its id, `th` and member count, so every
+    /// member of a keygen gets the same fingerprint.
+    fn group_fingerprint(&self) -> [u8; 8];
+
+    /// Whether `other` comes from the same keygen, whichever members the two
+    /// are: both hold the same commitments of every dealer of every group,
+    /// hence the same thresholds and group public key. It compares all of the
+    /// commitments rather than an 8-byte `group_fingerprint`, and no secret.
+    fn same_group(&self, other: &Self) -> bool;
+
+    /// Public verification share $Y_i = x_i \ast G$ of every member of every
+    /// group, sorted by address and derived from `vss_com_grid` alone, so a
+    /// coordinator can check partial signatures without any secret. Any keystore
+    /// with the commitments of a group, e.g. one restored by `from_mnemonic`,
+    /// has the shares of all its members, whether or not they sign.
+    /// Interpolating the shares of any `th` members of each group at zero
+    /// and summing over groups gives `group_public_key`.
+    fn public_shares(&self) -> Vec<(MpcAddr, EdwardsPoint)>;
+
+    /// Threshold `th` of group `gid`, i.e. how many of its members must sign.
+    /// Throws `NoSuchGroup` for a group the keystore doesn't know, like the rest below.
+    fn threshold(&self, gid: u16) -> Outcome<u16>;
+
+    /// Number of members of group `gid`, i.e. of its dealers in keygen.
+    fn n_members(&self, gid: u16) -> Outcome<u16>;
+
+    /// Member ids of group `gid` in ascending order, e.g. for `ses_arch_of`.
+    fn member_ids(&self, gid: u16) -> Outcome<Vec<u16>>;
+
+    /// Copy of this keystore that is `new_id` in group `new_id.group_id()`,
+    /// e.g. to repair a mislabeled keystore. The share $x_i$ is a point of
+    /// the group polynomial at $x = i$, so only the id it was dealt at is
+    /// accepted; any other throws `InvalidRelabel`. To genuinely re-index a
+    /// member, run `algo_reshare` with `new_id` among the new members,
+    /// joining under it as a newcomer.
+    fn with_member_id(&self, new_id: MpcAddr) -> Outcome<Self>;
+
+    /// Check that the keystore is consistent, e.g. after loading it:
+    /// every group has a well-formed commitment from each of its members,
+    /// and for each of my `ids` the share $x_i$ (and $u_i$, if kept) has the
+    /// public image the commitments give it at `id`. A tampered or
+    /// mismatched share throws `KeyStoreInconsistent`.
+    fn validate(&self) -> Outcome<()>;
+
+    /// The public part of the keygen that made this keystore, to publish for
+    /// auditors, see `verify_public_transcript`. Throws `IncompleteTranscript`
+    /// if a group keeps no proofs, e.g. one reshared since.
+    fn public_transcript(&self) -> Outcome<PublicTranscript>;
+
+    /// Whether `algo_sign` can sign for the child at `drv_path`, e.g. for a
+    /// wallet to gray out the addresses it cannot. Threshold signing only
+    /// applies public (soft) tweaks, so a path with a hardened segment, or
+    /// any path but `""` and `"m"` without the `hd` feature, gives `false`.
+    /// A path that does not parse throws, as `algo_sign` would.
+    fn can_sign_path(&self, drv_path: &str) -> Outcome<bool> {
+        is_signable_path(drv_path, &self.group_public_key())
+    }
+
+    /// Base58 `xpub` of `group_public_key` with `chain_code` at depth 0,
+    /// see `export_xpub` in `hd.rs` for derived children.
+    #[cfg(feature = "hd")]
+    fn export_xpub(&self, chain_code: &ChainCode) -> Outcome<String> {
+        export_xpub("m", &self.group_public_key(), chain_code)
+    }
+
+    /// Always throws `UnsupportedOperation`: no party holds the group
+    /// private key, only a share of it, so there is no xprv to export.
+    #[cfg(feature = "hd")]
+    fn export_xprv(&self) -> Outcome<String> {
+        throw!(
+            "UnsupportedOperation",
+            "The group private key is split across parties and never exists as an xprv"
+        )
+    }
+
+    /// Compact binary form (bincode), with every scalar and point as its
+    /// 32 raw bytes. It holds the secret shares in the clear, see `seal`.
+    fn to_bytes(&self) -> Outcome<Vec<u8>>;
+
+    /// Inverse of `to_bytes`. Throws `KeyStoreFormatException` on malformed
+    /// bytes and `KeyStoreVersionException` on a newer layout version.
+    fn from_bytes(bytes: &[u8]) -> Outcome<Self>;
+
+    /// JSON form for inspection and interop, with every scalar and point
+    /// as a 64-digit hex string rather than a list of numbers.
+    /// It holds the secret shares in the clear, see `seal`.
+    fn to_json(&self) -> Outcome<String>;
+
+    /// Inverse of `to_json`, throwing like `from_bytes`.
+    fn from_json(json: &str) -> Outcome<Self>;
+
+    /// Load a pickled keystore of any known layout version,
+    /// upgrading it to `KeyStore::CURRENT_VERSION`.
+    /// Versions newer than this build throw `KeyStoreVersionException`.
+    fn migrate(bytes: &[u8]) -> Outcome<Self>;
+}
+
+impl KeyStoreExt for KeyStore {
+    #[cfg(feature = "hd")]
+    fn to_mnemonic(&self, gid: u16, lang: Language) -> Outcome<String> {
+        let x_i = self
+            .xi_pergroup
+            .get(&gid)
+            .ifnone("NoSuchGroup", gid.to_string())?;
+        // A canonical scalar is 32 bytes, which is exactly 256-bit BIP39 entropy.
+        let mnemonic = Mnemonic::from_entropy(x_i.to_bytes(), lang);
+        Ok(mnemonic.phrase().to_string())
+    }
+
+    #[cfg(feature = "hd")]
+    fn from_mnemonic(
+        phrase: &str,
+        lang: Language,
+        my_id: MpcAddr,
+        vss_com_dict: HashMap<MpcAddr, Vec<EdwardsPoint>>,
+    ) -> Outcome<Self> {
+        const MNE: &str = "MnemonicException";
+        let mnemonic = Mnemonic::new(phrase, lang).catch(MNE, "Invalid phrase")?;
+        // Not every 256-bit entropy is below the group order.
+        let x_i = Scalar::from_canonical_bytes(*mnemonic.entropy())
+            .ifnone(MNE, "Phrase does not encode a canonical scalar")?;
+
+        verify_signing_share(my_id, &x_i, &vss_com_dict)
+            .catch(MNE, format!("Phrase does not match the share of {}", my_id))?;
+
+        let gid = my_id.group_id();
+        let mut keystore = KeyStore::default();
+        keystore.xi_pergroup.insert(gid, x_i);
+        keystore.vss_com_grid.insert(gid, vss_com_dict);
+        keystore.ids = HashSet::from([my_id]);
+        Ok(keystore)
+    }
+
+    fn seal(&self, password: &str) -> Outcome<Vec<u8>> {
+        self.seal_with_options(password, &SealOptions::default())
+    }
+
+    fn seal_with_options(&self, password: &str, opts: &SealOptions) -> Outcome<Vec<u8>> {
+        let SealOptions { log_n, r, p } = *opts;
+        assert_throw!(
+            log_n >= SEAL_MIN_LOG_N && r >= SEAL_MIN_R && p >= 1,
+            "InvalidSealOptions",
+            format!(
+                "scrypt parameters log_n={}, r={}, p={} are below the minimum {}, {}, 1",
+                log_n, r, p, SEAL_MIN_LOG_N, SEAL_MIN_R
+            )
+        );
+        assert_throw!(
+            log_n <= SEAL_MAX_LOG_N && r <= SEAL_MAX_R && p <= SEAL_MAX_P,
+            "InvalidSealOptions",
+            format!(
+                "scrypt parameters log_n={}, r={}, p={} exceed the limits {}, {}, {}",
+                log_n, r, p, SEAL_MAX_LOG_N, SEAL_MAX_R, SEAL_MAX_P
+            )
+        );
+        seal_with_params(self, password, log_n, r, p)
+    }
+
+    fn unseal(bytes: &[u8], password: &str) -> Outcome<Self> {
+        const SFE: &str = "KeyStoreFormatException";
+        assert_throw!(
+            bytes.len() > SEAL_HEADER_LEN + SEAL_NONCE_LEN,
+            SFE,
+            "Sealed keystore is truncated"
+        );
+        let (magic, rest) = bytes.split_at(SEAL_MAGIC.len());
+        assert_throw!(magic == SEAL_MAGIC, SFE, "Not a sealed keystore");
+        let log_n = rest[0];
+        let r = u32::from_be_bytes(rest[1..5].try_into().unwrap());
+        let p = u32::from_be_bytes(rest[5..9].try_into().unwrap());
+        let salt = &rest[9..9 + SEAL_SALT_LEN];
+        assert_throw!(
+            log_n <= SEAL_MAX_LOG_N && r <= SEAL_MAX_R && p <= SEAL_MAX_P,
+            SFE,
+            format!(
+                "scrypt parameters log_n={}, r={}, p={} exceed the limits {}, {}, {}",
+                log_n, r, p, SEAL_MAX_LOG_N, SEAL_MAX_R, SEAL_MAX_P
+            )
+        );
+        let (nonce, ciphertext) = bytes[SEAL_HEADER_LEN..].split_at(SEAL_NONCE_LEN);
+
+        let aes_key = derive_seal_key(password, salt, log_n, r, p)?;
+        let aead = AEAD {
+            ciphertext: ciphertext.to_vec(),
+            tag: nonce.to_vec(),
+            scheme: AeadScheme::AesGcm,
+            tag_len: 16,
+        };
+        let plaintext = Zeroizing::new(aes_decrypt(aes_key.as_ref(), &aead).catch(
+            KeyStoreAuthException,
+            "Wrong password, or the sealed keystore is corrupted",
+        )?);
+        KeyStore::migrate(&plaintext)
+    }
+
+    fn group_public_key(&self) -> EdwardsPoint {
+        // `MultiShard::pk` only sums commitments and never returns `Err`.
+        self.pk().expect("MultiShard::pk is infallible")
+    }
+
+    fn same_group(&self, other: &Self) -> bool {
+        self.vss_com_grid == other.vss_com_grid
+    }
+
+    fn group_fingerprint(&self) -> [u8; 8] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.group_public_key_bytes());
+        let mut gids: Vec<&u16> = self.vss_com_grid.keys().collect();
+        gids.sort();
+        for gid in gids {
+            let vss_com_dict = &self.vss_com_grid[gid];
+            let th = vss_com_dict
+                .values()
+                .map(|com| com.len())
+                .max()
+                .unwrap_or(0);
+            hasher.update(gid.to_be_bytes());
+            hasher.update((th as u64).to_be_bytes());
+            hasher.update((vss_com_dict.len() as u64).to_be_bytes());
+        }
+        let digest = hasher.finalize();
+        let mut fingerprint = [0u8; 8];
+        fingerprint.copy_from_slice(&digest[..8]);
+        fingerprint
+    }
+
+    fn public_shares(&self) -> Vec<(MpcAddr, EdwardsPoint)> {
+        let mut shares: Vec<(MpcAddr, EdwardsPoint)> = self
+            .vss_com_grid
+            .values()
+            .flat_map(|vss_com_dict| {
+                vss_com_dict
+                    .keys()
+                    .map(move |id| (*id, eval_xi_com(*id, vss_com_dict)))
+            })
+            .collect();
+        shares.sort_by_key(|(id, _)| *id);
+        shares
+    }
+
+    fn threshold(&self, gid: u16) -> Outcome<u16> {
+        Ok(self.th(gid)? as u16)
+    }
+
+    fn n_members(&self, gid: u16) -> Outcome<u16> {
+        Ok(self.member_ids(gid)?.len() as u16)
+    }
+
+    fn member_ids(&self, gid: u16) -> Outcome<Vec<u16>> {
+        let vss_com_dict = self
+            .vss_com_grid
+            .get(&gid)
+            .ifnone("NoSuchGroup", gid.to_string())?;
+        let mut ids: Vec<u16> = vss_com_dict.keys().map(|id| id.member_id()).collect();
+        ids.sort();
+        Ok(ids)
+    }
+
+    fn with_member_id(&self, new_id: MpcAddr) -> Outcome<Self> {
+        let gid = new_id.group_id();
+        let x_i = self
+            .xi_pergroup
+            .get(&gid)
+            .ifnone("NoSuchGroup", gid.to_string())?;
+        let vss_com_dict = self
+            .vss_com_grid
+            .get(&gid)
+            .ifnone("NoSuchGroup", gid.to_string())?;
+        verify_signing_share(new_id, x_i, vss_com_dict).catch(
+            "InvalidRelabel",
+            format!(
+                "The share of group {} was not dealt at {}, re-index it with algo_reshare",
+                gid, new_id
+            ),
+        )?;
+        let mut keystore = self.clone();
+        keystore.ids.retain(|id| id.group_id() != gid);
+        keystore.ids.insert(new_id);
+        Ok(keystore)
+    }
+
+    fn validate(&self) -> Outcome<()> {
+        const KIE: &str = "KeyStoreInconsistent";
+        for (gid, vss_com_dict) in self.vss_com_grid.iter() {
+            let th = self
+                .th(*gid)
+                .catch(KIE, format!("Group {} has no member", gid))?;
+            let members: HashSet<MpcAddr> = vss_com_dict.keys().cloned().collect();
+            validate_group(*gid, th, &members).catch(KIE, format!("Malformed group {}", gid))?;
+            for (id, vss_com) in vss_com_dict.iter() {
+                assert_throw!(
+                    vss_com.len() == th,
+                    KIE,
+                    format!("Commitment of {} is not of threshold th={}", id, th)
+                );
+            }
+        }
+
+        let my_gids: HashSet<u16> = self.ids.iter().map(|id| id.group_id()).collect();
+        assert_throw!(
+            my_gids.len() == self.ids.len(),
+            KIE,
+            "Two of my ids are in the same group"
+        );
+        for gid in self.xi_pergroup.keys().chain(self.ui_pergroup.keys()) {
+            assert_throw!(
+                my_gids.contains(gid),
+                KIE,
+                format!("Share of group {} belongs to none of my ids", gid)
+            );
+        }
+        for my_id in self.ids.iter() {
+            let gid = my_id.group_id();
+            let vss_com_dict = self
+                .vss_com_grid
+                .get(&gid)
+                .ifnone(KIE, format!("No commitments of the group of {}", my_id))?;
+            let x_i = self
+                .xi_pergroup
+                .get(&gid)
+                .ifnone(KIE, format!("No share of {}", my_id))?;
+            verify_signing_share(*my_id, x_i, vss_com_dict).catch(KIE, "")?;
+            if let Some(u_i) = self.ui_pergroup.get(&gid) {
+                let com = vss_com_dict
+                    .get(my_id)
+                    .ifnone(KIE, format!("{} is not a member of group {}", my_id, gid))?;
+                assert_throw!(
+                    &constants::ED25519_BASEPOINT_TABLE * u_i == com[0],
+                    KIE,
+                    format!("Secret u_i of {} does not match its commitment", my_id)
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn public_transcript(&self) -> Outcome<PublicTranscript> {
+        public_transcript_of(self)
+    }
+
+    fn to_bytes(&self) -> Outcome<Vec<u8>> {
+        bincode::serialize(self).catch("KeyStoreFormatException", "Cannot encode the keystore")
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Outcome<Self> {
+        let keystore: KeyStore = match bincode::deserialize(bytes) {
+            Ok(keystore) => keystore,
+            // bincode has no field names, so a v1 blob needs the v1 layout,
+            // and must be all of `bytes` not to pass for a truncated v2 one.
+            Err(_) => bincode::DefaultOptions::new()
+                .with_fixint_encoding()
+                .reject_trailing_bytes()
+                .deserialize::<KeyStoreV1>(bytes)
+                .ok()
+                .filter(|v1| v1.version <= 1)
+                .ifnone("KeyStoreFormatException", "Malformed binary keystore")?
+                .into(),
+        };
+        check_version(keystore.version)?;
+        Ok(keystore)
+    }
+
+    fn to_json(&self) -> Outcome<String> {
+        let hex_of = |x: &Scalar| hex::encode(x.as_bytes());
+        let hex_of_point = |p: &EdwardsPoint| hex::encode(p.compress().as_bytes());
+        let json = KeyStoreJson {
+            ui_pergroup: self
+                .ui_pergroup
+                .iter()
+                .map(|(g, x)| (*g, hex_of(x)))
+                .collect(),
+            xi_pergroup: self
+                .xi_pergroup
+                .iter()
+                .map(|(g, x)| (*g, hex_of(x)))
+                .collect(),
+            vss_com_grid: self
+                .vss_com_grid
+                .iter()
+                .map(|(gid, vss_com_dict)| {
+                    let dict = vss_com_dict
+                        .iter()
+                        .map(|(id, com)| (*id, com.iter().map(hex_of_point).collect()))
+                        .collect();
+                    (*gid, dict)
+                })
+                .collect(),
+            vss_zkp_grid: self
+                .vss_zkp_grid
+                .iter()
+                .map(|(gid, zkp_dict)| {
+                    let dict = zkp_dict
+                        .iter()
+                        .map(|(id, (g_k, sigma))| (*id, (hex_of_point(g_k), hex_of(sigma))))
+                        .collect();
+                    (*gid, dict)
+                })
+                .collect(),
+            ids: self.ids.clone(),
+            aux: self.aux.as_ref().map(hex::encode),
+            version: self.version,
+        };
+        serde_json::to_string(&json).catch("KeyStoreFormatException", "Cannot encode the keystore")
+    }
+
+    fn from_json(json: &str) -> Outcome<Self> {
+        const KFE: &str = "KeyStoreFormatException";
+        let json: KeyStoreJson =
+            serde_json::from_str(json).catch(KFE, "Malformed JSON keystore")?;
+        check_version(json.version)?;
+        let bytes_of = |s: &str| -> Outcome<[u8; 32]> {
+            let bytes = hex::decode(s).catch(KFE, format!("\"{}\" is not hex", s))?;
+            bytes
+                .try_into()
+                .ok()
+                .ifnone(KFE, format!("\"{}\" is not 32 bytes long", s))
+        };
+        let scalar_of = |s: &String| -> Outcome<Scalar> {
+            Scalar::from_canonical_bytes(bytes_of(s)?)
+                .ifnone(KFE, format!("\"{}\" is not a canonical scalar", s))
+        };
+        let point_of = |s: &String| -> Outcome<EdwardsPoint> {
+            let bytes = bytes_of(s)?;
+            CompressedEdwardsY(bytes)
+                .decompress()
+                .filter(|point| point.compress().to_bytes() == bytes)
+                .ifnone(KFE, format!("\"{}\" is not a canonical curve point", s))
+        };
+
+        let mut keystore = KeyStore {
+            ids: json.ids,
+            version: json.version,
+            ..Default::default()
+        };
+        for (gid, x) in json.ui_pergroup.iter() {
+            keystore.ui_pergroup.insert(*gid, scalar_of(x)?);
+        }
+        for (gid, x) in json.xi_pergroup.iter() {
+            keystore.xi_pergroup.insert(*gid, scalar_of(x)?);
+        }
+        for (gid, dict) in json.vss_com_grid.iter() {
+            let mut vss_com_dict = HashMap::new();
+            for (id, com) in dict.iter() {
+                let com: Vec<EdwardsPoint> = com.iter().map(point_of).collect::<Outcome<_>>()?;
+                vss_com_dict.insert(*id, com);
+            }
+            keystore.vss_com_grid.insert(*gid, vss_com_dict);
+        }
+        for (gid, dict) in json.vss_zkp_grid.iter() {
+            let mut zkp_dict = HashMap::new();
+            for (id, (g_k, sigma)) in dict.iter() {
+                zkp_dict.insert(*id, (point_of(g_k)?, scalar_of(sigma)?));
+            }
+            keystore.vss_zkp_grid.insert(*gid, zkp_dict);
+        }
+        if let Some(aux) = json.aux {
+            keystore.aux = Some(hex::decode(&aux).catch(KFE, "aux is not hex")?);
+        }
+        Ok(keystore)
+    }
+
+    fn migrate(bytes: &[u8]) -> Outcome<Self> {
+        #[derive(Deserialize)]
+        struct VersionProbe {
+            #[serde(default)]
+            version: u16,
+        }
+        let probe: VersionProbe = serde_pickle::from_slice(bytes, Default::default())
+            .catch("KeyStoreFormatException", "Not a pickled keystore")?;
+
+        match probe.version {
+            // v0 lacks the version field, which `serde(default)` fills with 0,
+            // and v0 and v1 lack `vss_zkp_grid`, which it leaves empty.
+            0 | 1 | KeyStore::CURRENT_VERSION => {
+                let mut keystore: KeyStore = serde_pickle::from_slice(bytes, Default::default())
+                    .catch(
+                        "KeyStoreFormatException",
+                        format!("Malformed v{} keystore", probe.version),
+                    )?;
+                keystore.version = KeyStore::CURRENT_VERSION;
+                Ok(keystore)
+            }
+            v => throw!(
+                "KeyStoreVersionException",
+                format!(
+                    "Keystore version {} is newer than the supported version {}",
+                    v,
+                    KeyStore::CURRENT_VERSION
+                )
+            ),
+        }
+    }
+}
+
+/// Inverse of `KeyStoreExt::group_public_key_bytes`, e.g. to load a group
+/// key exported to ed25519 tooling. Keys here are Edwards points, which
+/// already encode as standard ed25519 public keys, so no Ristretto mapping
+/// is involved either way.
+/// Throws `InvalidPublicKey` for bytes that are not a point, or a small-order one.
+pub fn group_public_key_from_bytes(bytes: &[u8; 32]) -> Outcome<EdwardsPoint> {
+    let pk = CompressedEdwardsY(*bytes)
+        .decompress()
+        .ifnone("InvalidPublicKey", "Not a compressed Edwards point")?;
+    assert_throw!(
+        !pk.is_small_order(),
+        "InvalidPublicKey",
+        "Small-order points cannot be group keys"
+    );
+    Ok(pk)
+}
+
+/// Prints a point as hex of its compressed form, e.g. in error reports.
+pub struct HexPoint<'a>(pub &'a EdwardsPoint);
+
+impl fmt::Debug for HexPoint<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(self.0.compress().as_bytes()))
+    }
+}
+
+/// `Debug` of a keystore as `KeyStore`'s own, which already redacts the
+/// secret scalars, but with every commitment as a `HexPoint`:
+/// `format!("{:?}", HexKeyStore(&keystore))`.
+pub struct HexKeyStore<'a>(pub &'a KeyStore);
+
+impl fmt::Debug for HexKeyStore<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ks = self.0;
+        let redacted = |per_group: &HashMap<u16, Scalar>| -> BTreeMap<u16, &str> {
+            per_group.keys().map(|gid| (*gid, "<redacted>")).collect()
+        };
+        let vss_com_grid: BTreeMap<&u16, BTreeMap<&MpcAddr, Vec<HexPoint>>> = ks
+            .vss_com_grid
+            .iter()
+            .map(|(gid, dict)| {
+                let dict = dict
+                    .iter()
+                    .map(|(id, com)| (id, com.iter().map(HexPoint).collect()))
+                    .collect();
+                (gid, dict)
+            })
+            .collect();
+        let vss_zkp_grid: BTreeMap<&u16, Vec<&MpcAddr>> = ks
+            .vss_zkp_grid
+            .iter()
+            .map(|(gid, dict)| {
+                let mut dealers: Vec<&MpcAddr> = dict.keys().collect();
+                dealers.sort();
+                (gid, dealers)
+            })
+            .collect();
+        let mut ids: Vec<&MpcAddr> = ks.ids.iter().collect();
+        ids.sort();
+        f.debug_struct("KeyStore")
+            .field("group_public_key", &HexPoint(&ks.group_public_key()))
+            .field("ui_pergroup", &redacted(&ks.ui_pergroup))
+            .field("xi_pergroup", &redacted(&ks.xi_pergroup))
+            .field("vss_com_grid", &vss_com_grid)
+            .field("vss_zkp_grid", &vss_zkp_grid)
+            .field("ids", &ids)
+            .field(
+                "aux",
+                &ks.aux.as_ref().map(|aux| format!("<{} bytes>", aux.len())),
+            )
+            .field("version", &ks.version)
+            .finish()
+    }
+}
+
+/// `KeyStore` with its scalars, points and `aux` as hex strings, for `to_json`.
+#[derive(Serialize, Deserialize)]
+struct KeyStoreJson {
+    ui_pergroup: HashMap<u16, String>,
+    xi_pergroup: HashMap<u16, String>,
+    vss_com_grid: HashMap<u16, HashMap<MpcAddr, Vec<String>>>,
+    /// Absent from JSON written before layout version 2.
+    #[serde(default)]
+    vss_zkp_grid: HashMap<u16, HashMap<MpcAddr, (String, String)>>,
+    ids: HashSet<MpcAddr>,
+    aux: Option<String>,
+    version: u16,
+}
+
+/// `KeyStore` of layout version 1, as `to_bytes` wrote it.
+#[derive(Deserialize)]
+struct KeyStoreV1 {
+    ui_pergroup: HashMap<u16, Scalar>,
+    xi_pergroup: HashMap<u16, Scalar>,
+    vss_com_grid: HashMap<u16, HashMap<MpcAddr, Vec<EdwardsPoint>>>,
+    ids: HashSet<MpcAddr>,
+    aux: Option<Vec<u8>>,
+    version: u16,
+}
+
+impl From<KeyStoreV1> for KeyStore {
+    fn from(v1: KeyStoreV1) -> Self {
+        KeyStore {
+            ui_pergroup: v1.ui_pergroup,
+            xi_pergroup: v1.xi_pergroup,
+            vss_com_grid: v1.vss_com_grid,
+            ids: v1.ids,
+            aux: v1.aux,
+            // upgraded, like `migrate` does
+            version: KeyStore::CURRENT_VERSION,
+            ..Default::default()
+        }
+    }
+}
+
+fn check_version(version: u16) -> Outcome<()> {
+    assert_throw!(
+        version <= KeyStore::CURRENT_VERSION,
+        "KeyStoreVersionException",
+        format!(
+            "Keystore version {} is newer than the supported version {}",
+            version,
+            KeyStore::CURRENT_VERSION
+        )
+    );
+    Ok(())
+}
+
+fn seal_with_params(
+    keystore: &KeyStore,
+    password: &str,
+    log_n: u8,
+    r: u32,
+    p: u32,
+) -> Outcome<Vec<u8>> {
+    let mut salt = [0u8; SEAL_SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let aes_key = derive_seal_key(password, &salt, log_n, r, p)?;
+
+    let plaintext = Zeroizing::new(serde_pickle::to_vec(keystore, Default::default()).catch_()?);
+    let aead = aes_encrypt(aes_key.as_ref(), &plaintext).catch_()?;
+
+    let mut out = Vec::with_capacity(SEAL_HEADER_LEN + SEAL_NONCE_LEN + aead.ciphertext.len());
+    out.extend_from_slice(SEAL_MAGIC);
+    out.push(log_n);
+    out.extend_from_slice(&r.to_be_bytes());
+    out.extend_from_slice(&p.to_be_bytes());
+    out.extend_from_slice(&salt);
+    out.extend_from_slice(&aead.tag);
+    out.extend_from_slice(&aead.ciphertext);
+    Ok(out)
+}
+
+fn derive_seal_key(
+    password: &str,
+    salt: &[u8],
+    log_n: u8,
+    r: u32,
+    p: u32,
+) -> Outcome<Zeroizing<[u8; 32]>> {
+    const SFE: &str = "KeyStoreFormatException";
+    let params = scrypt::Params::new(log_n, r, p, 32).catch(SFE, "Invalid scrypt parameters")?;
+    let mut key = Zeroizing::new([0u8; 32]);
+    scrypt::scrypt(password.as_bytes(), salt, &params, key.as_mut()).catch_()?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_util::keygen_for_test;
+    use super::*;
+    #[cfg(feature = "hd")]
+    use crate::frost::generate_vss_share;
+    use crate::frost::{generate_challenge, is_valid_response, lagrange_lambda};
+    use curve25519_dalek::constants;
+    use rand::rngs::OsRng;
+
+    #[cfg(feature = "hd")]
+    #[test]
+    fn mnemonic_round_trip() {
+        let members: HashSet<MpcAddr> = (1..=3).map(|i| MpcAddr::new(1, i)).collect();
+        let me = MpcAddr::new(1, 2);
+        let mut x_i = Scalar::zero();
+        let mut vss_com_dict = HashMap::new();
+        for dealer in members.iter() {
+            let u_i = Scalar::random(&mut OsRng);
+            let (com, shares) = generate_vss_share(&u_i, *dealer, &members, 2, &mut OsRng).unwrap();
+            x_i += shares[&me];
+            vss_com_dict.insert(*dealer, com);
+        }
+        let mut keystore = KeyStore::default();
+        keystore.xi_pergroup.insert(1, x_i);
+        keystore.vss_com_grid.insert(1, vss_com_dict.clone());
+        keystore.ids.insert(me);
+
+        let phrase = keystore.to_mnemonic(1, Language::English).unwrap();
+        assert_eq!(phrase.split_whitespace().count(), 24);
+        let restored =
+            KeyStore::from_mnemonic(&phrase, Language::English, me, vss_com_dict.clone()).unwrap();
+        assert_eq!(restored.xi_pergroup[&1], x_i);
+        assert_eq!(restored.pk().unwrap(), keystore.pk().unwrap());
+
+        let other = MpcAddr::new(1, 3);
+        assert!(KeyStore::from_mnemonic(&phrase, Language::English, other, vss_com_dict).is_err());
+    }
+
+    #[tokio::test]
+    async fn fingerprint_tells_ceremonies_apart() {
+        let ceremony = keygen_for_test(2, 3).await;
+        let fingerprint = ceremony[0].group_fingerprint();
+        for keystore in ceremony.iter() {
+            assert_eq!(keystore.group_fingerprint(), fingerprint);
+        }
+        let other = keygen_for_test(2, 3).await;
+        assert_ne!(other[0].group_fingerprint(), fingerprint);
+
+        // same key, other threshold
+        let mut tampered = ceremony[0].clone();
+        for com in tampered.vss_com_grid.get_mut(&1).unwrap().values_mut() {
+            com.push(constants::ED25519_BASEPOINT_POINT);
+        }
+        assert_eq!(tampered.group_public_key(), ceremony[0].group_public_key());
+        assert_ne!(tampered.group_fingerprint(), fingerprint);
+    }
+
+    #[tokio::test]
+    async fn same_group_ignores_the_member() {
+        let ceremony = keygen_for_test(2, 3).await;
+        assert!(ceremony[0].same_group(&ceremony[2]));
+        let mut restored = ceremony[1].clone();
+        restored.ui_pergroup.clear();
+        restored.vss_zkp_grid.clear();
+        assert!(restored.same_group(&ceremony[0]));
+
+        let other = keygen_for_test(2, 3).await;
+        assert!(!other[0].same_group(&ceremony[0]));
+        let mut tampered = ceremony[0].clone();
+        for com in tampered.vss_com_grid.get_mut(&1).unwrap().values_mut() {
+            com.push(constants::ED25519_BASEPOINT_POINT);
+        }
+        assert!(!tampered.same_group(&ceremony[1]));
+    }
+
+    #[tokio::test]
+    async fn debug_output_redacts_the_secret_share() {
+        let keystore = keygen_for_test(2, 3).await.remove(0);
+        let secrets = [keystore.xi_pergroup[&1], keystore.ui_pergroup[&1]];
+        let plain = format!("{:?}", keystore);
+        let hexed = format!("{:?}", HexKeyStore(&keystore));
+        for out in [&plain, &hexed] {
+            assert!(out.contains("<redacted>"));
+            for x in secrets.iter() {
+                assert!(!out.contains(&hex::encode(x.as_bytes())));
+                assert!(!out.contains(&format!("{:?}", x.as_bytes())));
+            }
+        }
+        let pk_hex = hex::encode(keystore.group_public_key_bytes());
+        assert!(hexed.contains(&pk_hex));
+        let com = &keystore.vss_com_grid[&1][&MpcAddr::new(1, 2)][1];
+        assert!(hexed.contains(&format!("{:?}", HexPoint(com))));
+    }
+
+    #[tokio::test]
+    async fn group_key_round_trips_through_ed25519_bytes() {
+        let keystore = keygen_for_test(2, 3).await.remove(0);
+        let bytes = keystore.group_public_key_bytes();
+        let lib_pk = ed25519_dalek::PublicKey::from_bytes(&bytes).unwrap();
+        assert_eq!(lib_pk.to_bytes(), bytes);
+        let pk = group_public_key_from_bytes(&lib_pk.to_bytes()).unwrap();
+        assert_eq!(pk, keystore.group_public_key());
+
+        let mut not_a_point = [0u8; 32];
+        not_a_point[0] = 2; // y = 2 has no x on the curve
+        let identity = EdwardsPoint::default().compress().to_bytes();
+        for bytes in [not_a_point, identity] {
+            let err = group_public_key_from_bytes(&bytes).unwrap_err();
+            assert_eq!(err.get_name(), "InvalidPublicKey");
+        }
+    }
+
+    #[tokio::test]
+    async fn group_shape_matches_the_keygen() {
+        let keystore = keygen_for_test(3, 5).await.remove(0);
+        assert_eq!(keystore.threshold(1).unwrap(), 3);
+        assert_eq!(keystore.n_members(1).unwrap(), 5);
+        assert_eq!(keystore.member_ids(1).unwrap(), vec![1, 2, 3, 4, 5]);
+        for res in [keystore.threshold(2), keystore.n_members(2)] {
+            assert_eq!(res.unwrap_err().get_name(), "NoSuchGroup");
+        }
+    }
+
+    #[tokio::test]
+    async fn relabel_only_to_the_dealt_id() {
+        let mut keystore = keygen_for_test(2, 3).await.remove(1);
+        let me = MpcAddr::new(1, 2);
+        keystore.ids = HashSet::from([MpcAddr::new(1, 3)]);
+        let relabeled = keystore.with_member_id(me).unwrap();
+        assert_eq!(relabeled.ids, HashSet::from([me]));
+        assert_eq!(relabeled.xi_pergroup, keystore.xi_pergroup);
+
+        for id in [MpcAddr::new(1, 3), MpcAddr::new(1, 4)] {
+            let err = relabeled.with_member_id(id).unwrap_err();
+            assert_eq!(err.get_name(), "InvalidRelabel");
+            assert!(err.to_string().contains("algo_reshare"));
+        }
+        let err = relabeled.with_member_id(MpcAddr::new(2, 2)).unwrap_err();
+        assert_eq!(err.get_name(), "NoSuchGroup");
+    }
+
+    #[tokio::test]
+    async fn loaded_keystore_is_validated() {
+        let keystore = keygen_for_test(2, 3).await.remove(0);
+        keystore.validate().unwrap();
+        KeyStore::from_bytes(&keystore.to_bytes().unwrap())
+            .unwrap()
+            .validate()
+            .unwrap();
+
+        let mut corrupted = keystore.clone();
+        *corrupted.xi_pergroup.get_mut(&1).unwrap() += Scalar::one();
+        let err = corrupted.validate().unwrap_err();
+        assert_eq!(err.get_name(), "KeyStoreInconsistent");
+        assert!(err.to_string().contains("does not match the group"));
+
+        let mut corrupted = keystore.clone();
+        *corrupted.ui_pergroup.get_mut(&1).unwrap() += Scalar::one();
+        assert!(corrupted.validate().is_err());
+
+        let mut corrupted = keystore.clone();
+        let other = MpcAddr::new(1, 2);
+        corrupted
+            .vss_com_grid
+            .get_mut(&1)
+            .unwrap()
+            .get_mut(&other)
+            .unwrap()
+            .pop();
+        assert!(corrupted.validate().is_err());
+
+        let mut corrupted = keystore;
+        corrupted.ids = HashSet::from([other]);
+        assert!(corrupted.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn public_shares_interpolate_to_the_group_key() {
+        let ceremony = keygen_for_test(3, 5).await;
+        let keystore = &ceremony[0];
+        let shares = keystore.public_shares();
+        assert_eq!(shares.len(), 5);
+        for other in ceremony.iter() {
+            let x_i = other.xi_pergroup[&1];
+            let id = *other.ids.iter().next().unwrap();
+            let y_i = shares.iter().find(|(j, _)| *j == id).unwrap().1;
+            assert_eq!(y_i, &constants::ED25519_BASEPOINT_TABLE * &x_i);
+        }
+
+        let ids: Vec<MpcAddr> = shares.iter().map(|(id, _)| *id).collect();
+        for a in 0..5 {
+            for b in a + 1..5 {
+                for c in b + 1..5 {
+                    let signers: HashSet<MpcAddr> = [ids[a], ids[b], ids[c]].into();
+                    let mut pk = EdwardsPoint::default();
+                    for (id, y_i) in shares.iter().filter(|(id, _)| signers.contains(id)) {
+                        pk += lagrange_lambda(*id, &signers).unwrap() * y_i;
+                    }
+                    assert_eq!(pk, keystore.group_public_key());
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn absent_member_response_checks_against_my_keystore() {
+        let ceremony = keygen_for_test(2, 3).await;
+        // All I keep is my share and the public commitments.
+        let mut mine = ceremony[0].clone();
+        mine.ui_pergroup.clear();
+        mine.vss_zkp_grid.clear();
+        let absent = MpcAddr::new(1, 3);
+        let shares = mine.public_shares();
+        let y_3 = shares.iter().find(|(id, _)| *id == absent).unwrap().1;
+
+        // Member 3 responds in a session with member 2 only.
+        let G = &constants::ED25519_BASEPOINT_TABLE;
+        let signers: HashSet<MpcAddr> = [MpcAddr::new(1, 2), absent].into();
+        let lambda_3 = lagrange_lambda(absent, &signers).unwrap();
+        let k = Scalar::random(&mut OsRng);
+        let (com, pk) = (G * &k, mine.group_public_key());
+        let c = generate_challenge(b"msg", &com, &pk);
+        let z_3 = k + lambda_3 * ceremony[2].xi_pergroup[&1] * c;
+        assert!(is_valid_response(&z_3, &y_3, &lambda_3, &com, &c));
+        assert!(!is_valid_response(
+            &(z_3 + Scalar::one()),
+            &y_3,
+            &lambda_3,
+            &com,
+            &c
+        ));
+
+        let mut pk = EdwardsPoint::default();
+        for (id, y_i) in shares.iter().filter(|(id, _)| signers.contains(id)) {
+            pk += lagrange_lambda(*id, &signers).unwrap() * y_i;
+        }
+        assert_eq!(pk, mine.group_public_key());
+    }
+
+    #[cfg(feature = "hd")]
+    #[test]
+    fn xpub_is_the_group_key() {
+        let mut keystore = KeyStore::default();
+        let g = constants::ED25519_BASEPOINT_POINT;
+        let dealer = MpcAddr::new(1, 1);
+        keystore
+            .vss_com_grid
+            .insert(1, HashMap::from([(dealer, vec![g * Scalar::from(5u64)])]));
+        let chain_code = [3u8; 32];
+
+        let xpub = keystore.export_xpub(&chain_code).unwrap();
+        let parsed: bip32::XPub = xpub.parse().unwrap();
+        assert_eq!(
+            parsed.public_key().compress().to_bytes(),
+            keystore.group_public_key_bytes()
+        );
+        assert_eq!(parsed.attrs().chain_code, chain_code);
+
+        let err = keystore.export_xprv().unwrap_err();
+        assert_eq!(err.get_name(), "UnsupportedOperation");
+    }
+
+    #[tokio::test]
+    async fn binary_and_json_round_trip() {
+        let mut keystore = keygen_for_test(2, 3).await.remove(0);
+        keystore.aux = Some(b"aux".to_vec());
+        let same = |other: &KeyStore| {
+            assert_eq!(other.ui_pergroup, keystore.ui_pergroup);
+            assert_eq!(other.xi_pergroup, keystore.xi_pergroup);
+            assert_eq!(other.vss_com_grid, keystore.vss_com_grid);
+            assert_eq!(other.ids, keystore.ids);
+            assert_eq!(other.aux, keystore.aux);
+            assert_eq!(other.version, keystore.version);
+        };
+
+        let bytes = keystore.to_bytes().unwrap();
+        same(&KeyStore::from_bytes(&bytes).unwrap());
+        let json = keystore.to_json().unwrap();
+        same(&KeyStore::from_json(&json).unwrap());
+
+        let x_i = keystore.xi_pergroup[&1];
+        assert!(json.contains(&hex::encode(x_i.as_bytes())));
+        // Derived serde would write every 32-byte array as a list of numbers.
+        let number_lists = serde_json::to_string(&keystore).unwrap();
+        assert!(bytes.len() * 2 < number_lists.len());
+        assert!(bytes.len() < json.len() && json.len() < number_lists.len());
+
+        let err = KeyStore::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err();
+        assert_eq!(err.get_name(), "KeyStoreFormatException");
+        let err =
+            KeyStore::from_json(&json.replace(&hex::encode(x_i.as_bytes()), "00")).unwrap_err();
+        assert_eq!(err.get_name(), "KeyStoreFormatException");
+
+        // $y = p$ decompresses as $y = 0$, but is not its encoding.
+        let com = keystore.vss_com_grid[&1].values().next().unwrap()[0];
+        let mut y_is_p = [0xffu8; 32];
+        y_is_p[0] = 0xed;
+        y_is_p[31] = 0x7f;
+        assert!(CompressedEdwardsY(y_is_p).decompress().is_some());
+        let tampered = json.replace(
+            &hex::encode(com.compress().as_bytes()),
+            &hex::encode(y_is_p),
+        );
+        let err = KeyStore::from_json(&tampered).unwrap_err();
+        assert_eq!(err.get_name(), "KeyStoreFormatException");
+        assert!(err.get_context().unwrap().contains("canonical"));
+    }
+
+    #[test]
+    fn seal_round_trip() {
+        let mut keystore = KeyStore::default();
+        keystore.xi_pergroup.insert(1, Scalar::random(&mut OsRng));
+        keystore.ids.insert(MpcAddr::new(1, 1));
+
+        // cheap scrypt parameters to keep the test fast
+        let sealed = seal_with_params(&keystore, "correct horse", 10, 8, 1).unwrap();
+        let unsealed = KeyStore::unseal(&sealed, "correct horse").unwrap();
+        assert_eq!(unsealed.xi_pergroup, keystore.xi_pergroup);
+        assert_eq!(unsealed.ids, keystore.ids);
+
+        let err = KeyStore::unseal(&sealed, "battery staple").unwrap_err();
+        assert_eq!(err.get_name(), KeyStoreAuthException);
+    }
+
+    #[test]
+    fn default_seal_round_trip() {
+        let mut keystore = KeyStore::default();
+        keystore.xi_pergroup.insert(1, Scalar::random(&mut OsRng));
+        keystore.ids.insert(MpcAddr::new(1, 1));
+
+        let sealed = keystore.seal("correct horse").unwrap();
+        let params = scrypt::Params::recommended();
+        assert_eq!(sealed[4], params.log_n());
+        let unsealed = KeyStore::unseal(&sealed, "correct horse").unwrap();
+        assert_eq!(unsealed.xi_pergroup, keystore.xi_pergroup);
+    }
+
+    #[test]
+    fn seal_options_are_read_back_from_the_header() {
+        let mut keystore = KeyStore::default();
+        keystore.xi_pergroup.insert(1, Scalar::random(&mut OsRng));
+        keystore.ids.insert(MpcAddr::new(1, 1));
+
+        let low = SealOptions {
+            log_n: 14,
+            r: 8,
+            p: 1,
+        };
+        let high = SealOptions { log_n: 15, ..low };
+        for opts in [low, high] {
+            let sealed = keystore.seal_with_options("correct horse", &opts).unwrap();
+            assert_eq!(sealed[4], opts.log_n);
+            let unsealed = KeyStore::unseal(&sealed, "correct horse").unwrap();
+            assert_eq!(unsealed.xi_pergroup, keystore.xi_pergroup);
+        }
+
+        for (log_n, r, p) in [(13, 8, 1), (14, 4, 1), (14, 8, 0), (21, 8, 1)] {
+            let opts = SealOptions { log_n, r, p };
+            let err = keystore.seal_with_options("pw", &opts).unwrap_err();
+            assert_eq!(err.get_name(), "InvalidSealOptions", "{:?}", opts);
+        }
+    }
+
+    #[test]
+    fn costly_scrypt_header_is_rejected() {
+        let mut keystore = KeyStore::default();
+        keystore.ids.insert(MpcAddr::new(1, 1));
+        let sealed = seal_with_params(&keystore, "pw", 10, 8, 1).unwrap();
+
+        for (offset, field) in [
+            (4, &[63u8][..]),
+            (5, &1024u32.to_be_bytes()),
+            (9, &64u32.to_be_bytes()),
+        ] {
+            let mut tampered = sealed.clone();
+            tampered[offset..offset + field.len()].copy_from_slice(field);
+            let err = KeyStore::unseal(&tampered, "pw").unwrap_err();
+            assert_eq!(err.get_name(), "KeyStoreFormatException");
+            assert!(err.get_context().unwrap().contains("exceed the limits"));
+        }
+    }
+
+    // Pickled before `version` existed: one group with a single dealer
+    // of polynomial 5 + 7x, so u_i = 5 and x_i = 12 for member 1.
+    const V0_KEYSTORE: &str = concat!(
+        "80037d28580b00000075695f70657267726f75707d284d0100284b054b004b004b004b004b004b004b004b",
+        "004b004b004b004b004b004b004b004b004b004b004b004b004b004b004b004b004b004b004b004b004b00",
+        "4b004b007475580b00000078695f70657267726f75707d284d0100284b0c4b004b004b004b004b004b004b",
+        "004b004b004b004b004b004b004b004b004b004b004b004b004b004b004b004b004b004b004b004b004b00",
+        "4b004b004b007475580c0000007673735f636f6d5f677269647d284d01007d284a010001005d28284bed4b",
+        "c84b764bd64b834b1f4bd24b104b5d4b0b4b434b894bca4b2e4b284b314b664b464b924b894b144b6e4b2c",
+        "4be04b6f4bae4bfe4b984bb24b254b484bdf74284bb84b624b404b9f4bb54bc44bc44b124b3d4bf24bab4b",
+        "f74b464b2b4b884bf04b414bad4b364bdd4b684b644bce4b874b2f4bd54b474b2b4be34b634bc54bb17465",
+        "757558030000006964735d284a010001006558030000006175784e752e",
+    );
+
+    #[test]
+    fn v0_keystore_migrates() {
+        let bytes = hex::decode(V0_KEYSTORE).unwrap();
+        let keystore = KeyStore::migrate(&bytes).unwrap();
+        assert_eq!(keystore.version, KeyStore::CURRENT_VERSION);
+
+        let me = MpcAddr::new(1, 1);
+        let g = constants::ED25519_BASEPOINT_POINT;
+        let x_i = keystore.xi_pergroup[&1];
+        assert_eq!(x_i, Scalar::from(12u64));
+        assert_eq!(g * x_i, eval_xi_com(me, &keystore.vss_com_grid[&1]));
+        assert_eq!(keystore.pk().unwrap(), g * Scalar::from(5u64));
+        assert_eq!(keystore.th(1).unwrap(), 2);
+
+        // and it survives a round trip in the current layout
+        let buf = serde_pickle::to_vec(&keystore, Default::default()).unwrap();
+        assert_eq!(
+            KeyStore::migrate(&buf).unwrap().version,
+            KeyStore::CURRENT_VERSION
+        );
+    }
+
+    #[tokio::test]
+    async fn v1_binary_keystore_loads() {
+        let keystore = keygen_for_test(2, 3).await.remove(0);
+        assert!(!keystore.vss_zkp_grid.is_empty());
+        let ks = &keystore;
+        let v1 = (
+            &ks.ui_pergroup,
+            &ks.xi_pergroup,
+            &ks.vss_com_grid,
+            &ks.ids,
+            &ks.aux,
+            1u16,
+        );
+        let loaded = KeyStore::from_bytes(&bincode::serialize(&v1).unwrap()).unwrap();
+        assert_eq!(loaded.xi_pergroup, keystore.xi_pergroup);
+        assert_eq!(loaded.vss_com_grid, keystore.vss_com_grid);
+        assert!(loaded.vss_zkp_grid.is_empty());
+        assert_eq!(loaded.version, KeyStore::CURRENT_VERSION);
+    }
+
+    #[test]
+    fn future_version_is_rejected() {
+        let keystore = KeyStore {
+            version: KeyStore::CURRENT_VERSION + 1,
+            ..Default::default()
+        };
+        let buf = serde_pickle::to_vec(&keystore, Default::default()).unwrap();
+        let err = KeyStore::migrate(&buf).unwrap_err();
+        assert_eq!(err.get_name(), "KeyStoreVersionException");
+    }
+}
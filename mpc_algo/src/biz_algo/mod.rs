@@ -1,10 +1,40 @@
+mod keystore;
+pub use keystore::*;
+
 mod keygen;
 pub use keygen::*;
 
+mod retry;
+pub use retry::*;
+
+mod quorum;
+pub use quorum::*;
+
 mod aes;
+pub use aes::*;
 
 mod sign;
 pub use sign::*;
 
+#[cfg(feature = "hd")]
 mod hd;
+#[cfg(feature = "hd")]
 pub use hd::*;
+
+mod reshare;
+pub use reshare::*;
+
+mod enroll;
+pub use enroll::*;
+
+mod preprocess;
+pub use preprocess::*;
+
+mod two_phase;
+pub use two_phase::*;
+
+mod transcript;
+pub use transcript::*;
+
+#[cfg(test)]
+mod test_util;
@@ -0,0 +1,439 @@
+use curve25519_dalek::{edwards::EdwardsPoint, scalar::Scalar};
+use libexception::*;
+use mpc_spec::*;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use zeroize::Zeroize;
+
+use super::{validate_ses_arch, KeyStore, Signature};
+use crate::frost::{
+    agg_nonce_com, eval_xi_com, gen_rho_i, generate_challenge, is_valid_response, lagrange_lambda,
+    sign_and_respond, sign_preprocess, verify_solana, SigningCommitmentPair, SigningNoncePair,
+};
+
+/// The public half of a nonce pair made by `algo_preprocess`: the `index`-th
+/// commitment $(dG, eG)$ of signer `id`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NonceCommitment {
+    pub id: MpcAddr,
+    pub index: u32,
+    pub com: SigningCommitmentPair,
+}
+
+/// The secret nonces of my shards made by `algo_preprocess`, waiting to be
+/// consumed by `algo_sign_preprocessed`, together with every commitment,
+/// mine or my peers', that a signing already used.
+///
+/// It lives in memory only: a nonce must never be used twice, and a cache
+/// restored from a copy would hand out spent ones again. Whatever is left
+/// is zeroized when it is dropped.
+#[derive(Default)]
+pub struct NonceCache {
+    inner: Mutex<NonceCacheInner>,
+}
+
+#[derive(Default)]
+struct NonceCacheInner {
+    batches: usize,
+    issued: HashMap<MpcAddr, u32>,
+    secrets: HashMap<(MpcAddr, u32), SigningNoncePair>,
+    spent: HashSet<(MpcAddr, u32)>,
+}
+
+impl NonceCache {
+    /// How many nonces of `id` are left to sign with.
+    pub fn remaining(&self, id: MpcAddr) -> usize {
+        let inner = self.inner.lock().unwrap();
+        inner.secrets.keys().filter(|(j, _)| *j == id).count()
+    }
+}
+
+impl Drop for NonceCache {
+    fn drop(&mut self) {
+        if let Ok(inner) = self.inner.get_mut() {
+            for nonce in inner.secrets.values_mut() {
+                nonce.zeroize();
+            }
+        }
+    }
+}
+
+/// FROST preprocessing: make `count` nonce pairs for each shard of
+/// `keystore`, keep their secrets in `cache`, and broadcast their
+/// commitments, so that a later `algo_sign_preprocessed` takes one round.
+///
+/// The `n`-th call on a `cache` broadcasts on round "nonce_preprocess_{n}",
+/// see `algo_gather_preprocessed`. The indices of a shard's commitments
+/// continue from one call to the next.
+pub async fn algo_preprocess(
+    messenger: &impl Messenger,
+    keystore: &KeyStore,
+    cache: &NonceCache,
+    count: usize,
+) -> Outcome<Vec<NonceCommitment>> {
+    let mut whoami_asc: Vec<MpcAddr> = keystore.ids.iter().cloned().collect();
+    whoami_asc.sort();
+    let mut rng = OsRng;
+
+    let (round, my_coms) = {
+        let mut inner = cache.inner.lock().unwrap();
+        let round = format!("nonce_preprocess_{}", inner.batches);
+        inner.batches += 1;
+        let mut my_coms: Vec<NonceCommitment> = Vec::with_capacity(count * whoami_asc.len());
+        for my_id in whoami_asc.iter() {
+            let first = *inner.issued.get(my_id).unwrap_or(&0);
+            for k in 0..count {
+                let (com, nonce) = sign_preprocess(&mut rng).catch_()?;
+                let index = first + k as u32;
+                inner.secrets.insert((*my_id, index), nonce);
+                my_coms.push(NonceCommitment {
+                    id: *my_id,
+                    index,
+                    com,
+                });
+            }
+            inner.issued.insert(*my_id, first + count as u32);
+        }
+        (round, my_coms)
+    };
+
+    let bcast_id = MpcAddr::bcast_id();
+    for my_id in whoami_asc.iter() {
+        let mine: Vec<NonceCommitment> =
+            my_coms.iter().filter(|c| c.id == *my_id).cloned().collect();
+        messenger
+            .send(&round, *my_id, bcast_id, &mine)
+            .await
+            .catch_()?;
+    }
+    Ok(my_coms)
+}
+
+/// The commitments that `signers` broadcast in their `batch`-th
+/// `algo_preprocess`, counting from 0.
+pub async fn algo_gather_preprocessed(
+    messenger: &impl Messenger,
+    signers: &HashSet<MpcAddr>,
+    batch: usize,
+) -> Outcome<HashMap<MpcAddr, Vec<NonceCommitment>>> {
+    let round = format!("nonce_preprocess_{}", batch);
+    let obj: HashMap<MpcAddr, Vec<NonceCommitment>> = messenger
+        .gather(&round, signers, MpcAddr::bcast_id())
+        .await
+        .catch_()?;
+    for (j, coms) in obj.iter() {
+        assert_throw!(
+            coms.iter().all(|c| c.id == *j),
+            "InvalidNonceCommitment",
+            format!("Party {} broadcast commitments of another signer", j)
+        );
+    }
+    Ok(obj)
+}
+
+/// Same as `algo_sign` with `drv_path` `""`, but in a single round: each signer
+/// in `ses_arch` signs with its commitment in `nonce_coms`, made beforehand by
+/// `algo_preprocess`. All signers must be given the same `nonce_coms`,
+/// e.g. the lowest unused commitment of each as published.
+///
+/// The nonces of my shards are taken out of `cache` and the commitments of
+/// all signers are marked spent before anything is sent, failed signing or
+/// not. A commitment that `cache` has seen spent throws `NonceReused`, and
+/// one of my shards beyond those it preprocessed throws `NoncesExhausted`.
+///
+/// The responses go out on round "presign_resp_{index}" of each signer's
+/// commitment, so successive signings don't share a round.
+pub async fn algo_sign_preprocessed(
+    messenger: &impl Messenger,
+    ses_arch: &HashMap<u16, HashSet<MpcAddr>>,
+    nonce_coms: &HashMap<MpcAddr, NonceCommitment>,
+    msg_hash: &[u8],
+    keystore: &KeyStore,
+    cache: &NonceCache,
+) -> Outcome<Signature> {
+    validate_ses_arch(ses_arch, keystore)?;
+    let signers: HashSet<MpcAddr> = ses_arch.values().flatten().cloned().collect();
+    let mut strays: Vec<MpcAddr> = signers
+        .symmetric_difference(&nonce_coms.keys().cloned().collect())
+        .cloned()
+        .collect();
+    strays.sort();
+    assert_throw!(
+        strays.is_empty(),
+        "InvalidNonceCommitment",
+        format!(
+            "Signers {:?} have no commitment, or are not signing",
+            strays
+        )
+    );
+    for (j, c) in nonce_coms.iter() {
+        assert_throw!(
+            c.id == *j,
+            "InvalidNonceCommitment",
+            format!("The commitment of {} is filed under {}", c.id, j)
+        );
+    }
+    let mut whoami_asc: Vec<MpcAddr> = keystore
+        .ids
+        .iter()
+        .filter(|id| signers.contains(id))
+        .cloned()
+        .collect();
+    whoami_asc.sort();
+
+    // Take my nonces out, and burn every commitment of this signing.
+    let mut my_nonce_dict: HashMap<MpcAddr, SigningNoncePair> = HashMap::new();
+    {
+        let mut inner = cache.inner.lock().unwrap();
+        let mut reused: Vec<MpcAddr> = nonce_coms
+            .values()
+            .filter(|c| inner.spent.contains(&(c.id, c.index)))
+            .map(|c| c.id)
+            .collect();
+        reused.sort();
+        assert_throw!(
+            reused.is_empty(),
+            "NonceReused",
+            format!("Commitments of signers {:?} were already used", reused)
+        );
+        for my_id in whoami_asc.iter() {
+            let c = nonce_coms.get(my_id).ifnone_()?;
+            let issued = *inner.issued.get(my_id).unwrap_or(&0);
+            assert_throw!(
+                c.index < issued,
+                "NoncesExhausted",
+                format!(
+                    "Only {} nonces of {} were preprocessed, not #{}",
+                    issued, my_id, c.index
+                )
+            );
+            let nonce = inner.secrets.get(&(*my_id, c.index)).ifnone(
+                "NonceReused",
+                format!("Nonce #{} of {} was already used", c.index, my_id),
+            )?;
+            let own = nonce.commitment();
+            assert_throw!(
+                own.g_d == c.com.g_d && own.g_e == c.com.g_e,
+                "InvalidNonceCommitment",
+                format!(
+                    "Commitment #{} of {} is not the preprocessed one",
+                    c.index, my_id
+                )
+            );
+        }
+        for c in nonce_coms.values() {
+            inner.spent.insert((c.id, c.index));
+        }
+        for my_id in whoami_asc.iter() {
+            let index = nonce_coms[my_id].index;
+            let nonce = inner.secrets.remove(&(*my_id, index)).ifnone_()?;
+            my_nonce_dict.insert(*my_id, nonce);
+        }
+    }
+
+    let com_dict: HashMap<MpcAddr, SigningCommitmentPair> = nonce_coms
+        .iter()
+        .map(|(j, c)| (*j, c.com.clone()))
+        .collect();
+    let mut rho_dict: HashMap<MpcAddr, Scalar> = HashMap::new();
+    for j in com_dict.keys() {
+        rho_dict.insert(*j, gen_rho_i(*j, msg_hash, &com_dict));
+    }
+    let sig_r = agg_nonce_com(&com_dict, &rho_dict).catch_()?;
+    let pk = keystore.pk().catch_()?;
+    let challenge = generate_challenge(msg_hash, &sig_r, &pk);
+
+    // The only round: broadcast my responses.
+    let bcast_id = MpcAddr::bcast_id();
+    for my_id in whoami_asc.iter() {
+        let mut my_nonce = my_nonce_dict.remove(my_id).ifnone_()?;
+        let x_i = keystore.xi_pergroup.get(&my_id.group_id()).ifnone_()?;
+        let group_members = ses_arch.get(&my_id.group_id()).ifnone_()?;
+        let resp = sign_and_respond(*my_id, x_i, &rho_dict, &my_nonce, group_members, &challenge);
+        my_nonce.zeroize();
+        let round = format!("presign_resp_{}", nonce_coms[my_id].index);
+        messenger
+            .send(&round, *my_id, bcast_id, &resp.catch_()?)
+            .await
+            .catch_()?;
+    }
+
+    // Gather, validate and aggregate the responses.
+    let mut sig_s = Scalar::zero();
+    let mut invalid_ids: Vec<MpcAddr> = Vec::new();
+    for (j, c) in nonce_coms.iter() {
+        let round = format!("presign_resp_{}", c.index);
+        let resp: Scalar = messenger.receive(&round, *j, bcast_id).await.catch_()?;
+        let vss_com_dict = keystore.vss_com_grid.get(&j.group_id()).ifnone_()?;
+        let xjg: EdwardsPoint = eval_xi_com(*j, vss_com_dict);
+        let lam_j = lagrange_lambda(*j, ses_arch.get(&j.group_id()).ifnone_()?).catch_()?;
+        let com = c.com.g_d + (c.com.g_e * rho_dict[j]);
+        if !is_valid_response(&resp, &xjg, &lam_j, &com, &challenge) {
+            invalid_ids.push(*j);
+        }
+        sig_s += resp;
+    }
+    invalid_ids.sort();
+    assert_throw!(
+        invalid_ids.is_empty(),
+        "InvalidSignerResponse",
+        format!(
+            "Responses of signers {:?} do not match their shares and nonces",
+            invalid_ids
+        )
+    );
+    let sig = Signature {
+        r: sig_r,
+        s: sig_s,
+        hash: msg_hash.to_vec(),
+    };
+    verify_solana(&sig, &pk).catch("InvalidSignature", "Most probably lack of signers")?;
+    Ok(sig)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_util::keygen_for_test;
+    use super::*;
+    use crate::ses_arch_of;
+
+    /// Preprocess `count` nonces for each of `keystores` and gather them.
+    async fn preprocess_all(
+        messenger: &MemoryTransport,
+        keystores: &[KeyStore],
+        caches: &[NonceCache],
+        count: usize,
+        batch: usize,
+    ) -> HashMap<MpcAddr, Vec<NonceCommitment>> {
+        let futs = keystores
+            .iter()
+            .zip(caches)
+            .map(|(ks, cache)| algo_preprocess(messenger, ks, cache, count));
+        for res in futures::future::join_all(futs).await {
+            assert_eq!(res.unwrap().len(), count);
+        }
+        let ids: HashSet<MpcAddr> = keystores.iter().flat_map(|ks| ks.ids.clone()).collect();
+        algo_gather_preprocessed(messenger, &ids, batch)
+            .await
+            .unwrap()
+    }
+
+    /// The `k`-th published commitment of each of `signers`.
+    fn pick(
+        published: &HashMap<MpcAddr, Vec<NonceCommitment>>,
+        signers: &[u16],
+        k: usize,
+    ) -> HashMap<MpcAddr, NonceCommitment> {
+        signers
+            .iter()
+            .map(|i| {
+                let id = MpcAddr::new(1, *i);
+                (id, published[&id][k].clone())
+            })
+            .collect()
+    }
+
+    /// Run `algo_sign_preprocessed` for the members `running` of the
+    /// signers in `nonce_coms`, returning their outcomes in order.
+    async fn sign_preprocessed_by(
+        messenger: &MemoryTransport,
+        keystores: &[KeyStore],
+        caches: &[NonceCache],
+        nonce_coms: &HashMap<MpcAddr, NonceCommitment>,
+        running: &[u16],
+        msg_hash: &[u8],
+    ) -> Vec<Outcome<Signature>> {
+        let signers: Vec<u16> = nonce_coms.keys().map(|id| id.member_id()).collect();
+        let ses_arch = ses_arch_of(1, &signers).unwrap();
+        let futs = running.iter().map(|i| {
+            let k = *i as usize - 1;
+            let ses_arch = &ses_arch;
+            async move {
+                algo_sign_preprocessed(
+                    messenger,
+                    ses_arch,
+                    nonce_coms,
+                    msg_hash,
+                    &keystores[k],
+                    &caches[k],
+                )
+                .await
+            }
+        });
+        futures::future::join_all(futs).await
+    }
+
+    #[tokio::test]
+    async fn preprocessed_nonces_sign_until_exhausted() {
+        let keystores = keygen_for_test(2, 3).await;
+        let caches: Vec<NonceCache> = (0..3).map(|_| NonceCache::default()).collect();
+        let messenger = MemoryTransport::default();
+        let published = preprocess_all(&messenger, &keystores, &caches, 2, 0).await;
+        let pk = keystores[0].pk().unwrap();
+
+        for (k, signers) in [[1u16, 2], [2, 3]].iter().enumerate() {
+            let nonce_coms = pick(&published, signers, k);
+            let msg_hash = format!("transaction #{}", k);
+            let sigs = sign_preprocessed_by(
+                &messenger,
+                &keystores,
+                &caches,
+                &nonce_coms,
+                signers,
+                msg_hash.as_bytes(),
+            )
+            .await;
+            for sig in sigs {
+                verify_solana(&sig.unwrap(), &pk).unwrap();
+            }
+        }
+        assert_eq!(caches[0].remaining(MpcAddr::new(1, 1)), 1);
+        assert_eq!(caches[1].remaining(MpcAddr::new(1, 2)), 0);
+
+        // Member 2 has spent both of its nonces.
+        let mut nonce_coms = pick(&published, &[1, 2], 1);
+        nonce_coms.get_mut(&MpcAddr::new(1, 2)).unwrap().index = 2;
+        let mut res =
+            sign_preprocessed_by(&messenger, &keystores, &caches, &nonce_coms, &[2], b"m").await;
+        assert_eq!(res.remove(0).unwrap_err().get_name(), "NoncesExhausted");
+
+        // A second batch continues the indices.
+        let published = preprocess_all(&messenger, &keystores, &caches, 1, 1).await;
+        assert_eq!(published[&MpcAddr::new(1, 2)][0].index, 2);
+        assert_eq!(caches[1].remaining(MpcAddr::new(1, 2)), 1);
+    }
+
+    #[tokio::test]
+    async fn reused_commitment_is_refused() {
+        let keystores = keygen_for_test(2, 3).await;
+        let caches: Vec<NonceCache> = (0..3).map(|_| NonceCache::default()).collect();
+        let messenger = MemoryTransport::default();
+        let published = preprocess_all(&messenger, &keystores, &caches, 2, 0).await;
+        let nonce_coms = pick(&published, &[1, 2], 0);
+        let sigs =
+            sign_preprocessed_by(&messenger, &keystores, &caches, &nonce_coms, &[1, 2], b"m1")
+                .await;
+        assert!(sigs.into_iter().all(|sig| sig.is_ok()));
+
+        // Signing another message with the same nonces would leak the shares.
+        let sigs =
+            sign_preprocessed_by(&messenger, &keystores, &caches, &nonce_coms, &[1, 2], b"m2")
+                .await;
+        for sig in sigs {
+            assert_eq!(sig.unwrap_err().get_name(), "NonceReused");
+        }
+
+        // Member 2 refuses member 1's spent commitment next to a fresh one of its own.
+        let mut nonce_coms = pick(&published, &[1, 2], 1);
+        nonce_coms.insert(
+            MpcAddr::new(1, 1),
+            published[&MpcAddr::new(1, 1)][0].clone(),
+        );
+        let mut res =
+            sign_preprocessed_by(&messenger, &keystores, &caches, &nonce_coms, &[2], b"m3").await;
+        assert_eq!(res.remove(0).unwrap_err().get_name(), "NonceReused");
+        assert_eq!(caches[1].remaining(MpcAddr::new(1, 2)), 1);
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::{HashMap, HashSet};
+
+use futures::stream::{FuturesUnordered, Stream, StreamExt};
+use libexception::*;
+use mpc_spec::*;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Like `Messenger::gather`, but yields the message of each of `srcs` as it
+/// arrives, e.g. to validate a share at once and abort on the first bad one.
+/// A failed `receive` is yielded as a `TransportError` naming its peer, and
+/// the stream ends after one item per peer.
+pub fn gather_stream<'a, M, T>(
+    messenger: &'a M,
+    topic: &'a str,
+    srcs: &HashSet<MpcAddr>,
+    dst: MpcAddr,
+) -> impl Stream<Item = (MpcAddr, Outcome<T>)> + 'a
+where
+    M: Messenger,
+    T: Serialize + DeserializeOwned + Send + Sync + 'a,
+{
+    srcs.iter()
+        .map(|src| {
+            let src = *src;
+            async move {
+                let res = messenger.receive::<T>(topic, src, dst).await.catch(
+                    "TransportError",
+                    format!("From {} at round \"{}\"", src, topic),
+                );
+                (src, res)
+            }
+        })
+        .collect::<FuturesUnordered<_>>()
+}
+
+/// Like `Messenger::gather`, but returns as soon as `min` of `srcs` have
+/// answered, keyed by those who did. Peers whose `receive` fails are skipped,
+/// until too few are left to reach `min`.
+///
+/// NOTE: Different parties may see different responders. Protocols whose math
+/// depends on the participant set (e.g. Lagrange coefficients in signing)
+/// must agree on it before use. Keygen always needs every member.
+pub async fn gather_quorum<M, T>(
+    messenger: &M,
+    topic: &str,
+    srcs: &HashSet<MpcAddr>,
+    dst: MpcAddr,
+    min: usize,
+) -> Outcome<HashMap<MpcAddr, T>>
+where
+    M: Messenger,
+    T: Serialize + DeserializeOwned + Send + Sync,
+{
+    assert_throw!(
+        min <= srcs.len(),
+        "QuorumUnreachable",
+        format!("quorum of {} out of {} peers", min, srcs.len())
+    );
+    let mut pending = gather_stream(messenger, topic, srcs, dst);
+
+    let mut ret = HashMap::new();
+    let mut failed = Vec::new();
+    while ret.len() < min {
+        let (src, res) = pending.next().await.ifnone_()?;
+        match res {
+            Ok(obj) => {
+                ret.insert(src, obj);
+            }
+            Err(e) => {
+                failed.push(src);
+                failed.sort();
+                assert_throw!(
+                    srcs.len() - failed.len() >= min,
+                    "QuorumUnreachable",
+                    format!(
+                        "topic \"{}\": {} of {} peers failed ({:?}), last error: {}",
+                        topic,
+                        failed.len(),
+                        srcs.len(),
+                        failed,
+                        e
+                    )
+                );
+            }
+        }
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn quorum_returns_who_answered() {
+        let messenger = MemoryTransport::default();
+        let me = MpcAddr::new(1, 1);
+        let peers: HashSet<MpcAddr> = (2..=4).map(|i| MpcAddr::new(1, i)).collect();
+        for i in [2, 4] {
+            let src = MpcAddr::new(1, i);
+            messenger.send("nonce_com", src, me, &i).await.unwrap();
+        }
+
+        let got: HashMap<MpcAddr, u16> = gather_quorum(&messenger, "nonce_com", &peers, me, 2)
+            .await
+            .unwrap();
+        let answered: HashSet<MpcAddr> = got.keys().cloned().collect();
+        assert_eq!(
+            answered,
+            HashSet::from([MpcAddr::new(1, 2), MpcAddr::new(1, 4)])
+        );
+        assert_eq!(got[&MpcAddr::new(1, 4)], 4);
+
+        let res: Outcome<HashMap<MpcAddr, u16>> =
+            gather_quorum(&messenger, "nonce_com", &peers, me, 4).await;
+        assert_eq!(res.unwrap_err().get_name(), "QuorumUnreachable");
+    }
+
+    #[tokio::test]
+    async fn stream_yields_each_peer_as_it_arrives() {
+        let messenger = MemoryTransport::default();
+        let me = MpcAddr::new(1, 1);
+        let peers: HashSet<MpcAddr> = (2..=3).map(|i| MpcAddr::new(1, i)).collect();
+        let mut stream = Box::pin(gather_stream::<_, u16>(
+            &messenger,
+            "aead_share",
+            &peers,
+            me,
+        ));
+
+        let late = MpcAddr::new(1, 2);
+        let early = MpcAddr::new(1, 3);
+        messenger
+            .send("aead_share", early, me, &3u16)
+            .await
+            .unwrap();
+        let (src, res) = stream.next().await.unwrap();
+        assert_eq!((src, res.unwrap()), (early, 3));
+        assert!(futures::poll!(stream.next()).is_pending());
+
+        messenger.send("aead_share", late, me, &2u16).await.unwrap();
+        let (src, res) = stream.next().await.unwrap();
+        assert_eq!((src, res.unwrap()), (late, 2));
+        assert!(stream.next().await.is_none());
+    }
+}
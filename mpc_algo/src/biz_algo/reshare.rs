@@ -0,0 +1,372 @@
+use std::collections::{HashMap, HashSet};
+
+use curve25519_dalek::constants;
+use curve25519_dalek::edwards::EdwardsPoint;
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::Identity;
+use libexception::*;
+use mpc_spec::*;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use zeroize::Zeroize;
+
+use super::aes::*;
+use super::{decode_scalar_strict, validate_group, KeyStore};
+pub use crate::frost::reconstruct_secret;
+use crate::frost::{
+    deal_vss_share, eval_xi_com, lagrange_lambda, merge_vss_share, verify_signing_share,
+};
+
+/// Broadcast by each dealer of a reshare.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReshareCommitment {
+    /// Commitment to the polynomial sharing $\lambda_i x_i$.
+    pub shares_commitment: Vec<EdwardsPoint>,
+    /// Ephemeral DH key to encrypt the shares with.
+    pub ephemeral_pk: EdwardsPoint,
+}
+
+/// Refresh the shares of group `my_id.group_id()`, possibly changing its
+/// threshold to `new_th` and its members to `new_members`,
+/// while keeping `group_public_key()` unchanged.
+///
+/// At least `th` current members (`dealers`) re-share $\lambda_i x_i$,
+/// whose sum is the group secret, with fresh polynomials; every new member
+/// sums the sub-shares dealt to it. A dealer's commitment must open to
+/// $\lambda_i X_i$, where $X_i$ is its public share under the old commitments,
+/// so a reshare that would alter the group key is rejected by all members.
+///
+/// `my_id` is who I am in the group, as a dealer and/or a new member.
+/// A newcomer provides a keystore holding at least the public
+/// `vss_com_grid` of the group. Leaving dealers get a keystore without
+/// the group's share. Old shares must be discarded afterwards,
+/// since they are incompatible with the new ones.
+#[tracing::instrument(name = "reshare", skip_all, fields(context = %context, %my_id))]
+pub async fn algo_reshare(
+    messenger: &impl Messenger,
+    keystore: &KeyStore,
+    my_id: MpcAddr,
+    dealers: &HashSet<MpcAddr>,
+    new_th: usize,
+    new_members: &HashSet<MpcAddr>,
+    context: &str,
+) -> Outcome<KeyStore> {
+    const IRE: &str = "InvalidReshare";
+    let gid = my_id.group_id();
+    let gcast_id = MpcAddr::gcast_id(gid);
+    let old_vss_com_dict = keystore
+        .vss_com_grid
+        .get(&gid)
+        .ifnone("NoSuchGroup", gid.to_string())?;
+    let old_th = keystore.th(gid).catch_()?;
+    assert_throw!(
+        dealers.len() >= old_th,
+        IRE,
+        format!(
+            "{} dealers cannot reshare group {} of threshold th={}",
+            dealers.len(),
+            gid,
+            old_th
+        )
+    );
+    assert_throw!(
+        dealers.iter().all(|id| id.group_id() == gid),
+        IRE,
+        format!("dealers are not all in group {}", gid)
+    );
+    validate_group(gid, new_th, new_members).catch_()?;
+    let is_dealer = dealers.contains(&my_id);
+    let is_member = new_members.contains(&my_id);
+    assert_throw!(
+        is_dealer || is_member,
+        IRE,
+        format!("my_id={} is neither a dealer nor a new member", my_id)
+    );
+
+    let mut ephemeral_sk = Scalar::random(&mut OsRng);
+    let ephemeral_pk = &constants::ED25519_BASEPOINT_TABLE * &ephemeral_sk;
+
+    // Round 1: newcomers announce where to receive their shares.
+    if is_member {
+        messenger
+            .send("reshare_ek", my_id, gcast_id, &ephemeral_pk)
+            .await
+            .catch_()?;
+    }
+
+    // Round 2: dealers commit to and deal $\lambda_i x_i$.
+    if is_dealer {
+        let x_i = keystore.xi_pergroup.get(&gid).ifnone(
+            IRE,
+            format!("dealer {} holds no share of group {}", my_id, gid),
+        )?;
+        verify_signing_share(my_id, x_i, old_vss_com_dict).catch(
+            IRE,
+            format!("share of dealer {} does not match the group", my_id),
+        )?;
+        let mut w_i = lagrange_lambda(my_id, dealers).catch_()? * x_i;
+        let (shares_commitment, mut shares) =
+            deal_vss_share(&w_i, new_members, new_th, &mut OsRng).catch_()?;
+        w_i.zeroize();
+
+        let com = ReshareCommitment {
+            shares_commitment,
+            ephemeral_pk,
+        };
+        messenger
+            .send("reshare_com", my_id, gcast_id, &com)
+            .await
+            .catch_()?;
+
+        let ek_dict: HashMap<MpcAddr, EdwardsPoint> = messenger
+            .gather("reshare_ek", new_members, gcast_id)
+            .await
+            .catch_()?;
+        for (id, share) in shares.iter_mut() {
+            let ek = ek_dict.get(id).ifnone_()?;
+            let mut aes_key = eval_channel_key(&(ek * ephemeral_sk), context, my_id, *id)?;
+            let aad = p2p_aad(my_id, *id, "reshare_share");
+            let aead_pack = aes_encrypt_with_aad(&aes_key, &share.to_bytes(), &aad).catch_()?;
+            aes_key.zeroize();
+            share.zeroize();
+            messenger
+                .send("reshare_share", my_id, *id, &aead_pack)
+                .await
+                .catch_()?;
+        }
+    }
+
+    // Round 3: everyone checks that the dealt polynomials keep the group key.
+    let com_dict: HashMap<MpcAddr, ReshareCommitment> = messenger
+        .gather("reshare_com", dealers, gcast_id)
+        .await
+        .catch_()?;
+    let mut invalid_ids = Vec::new();
+    for (id, com) in com_dict.iter() {
+        let expected = lagrange_lambda(*id, dealers).catch_()? * eval_xi_com(*id, old_vss_com_dict);
+        if com.shares_commitment.len() != new_th || com.shares_commitment[0] != expected {
+            invalid_ids.push(*id);
+        }
+    }
+    invalid_ids.sort();
+    assert_throw!(
+        invalid_ids.is_empty(),
+        "InvalidShare",
+        format!("Invalid reshare commitment from parties {:?}", invalid_ids)
+    );
+    let new_vss_com_dict: HashMap<MpcAddr, Vec<EdwardsPoint>> = com_dict
+        .iter()
+        .map(|(id, com)| (*id, com.shares_commitment.clone()))
+        .collect();
+    let sum_com0 = |dict: &HashMap<MpcAddr, Vec<EdwardsPoint>>| {
+        dict.values()
+            .fold(EdwardsPoint::identity(), |acc, com| acc + com[0])
+    };
+    assert_throw!(
+        sum_com0(&new_vss_com_dict) == sum_com0(old_vss_com_dict),
+        "GroupKeyChanged",
+        format!("Reshare would change the public key of group {}", gid)
+    );
+
+    let mut new_keystore = keystore.clone();
+    new_keystore.ui_pergroup.remove(&gid); // no longer dealt by a $u_i$
+    if let Some(x) = new_keystore.xi_pergroup.get_mut(&gid) {
+        x.zeroize();
+    }
+    new_keystore.xi_pergroup.remove(&gid);
+    new_keystore.ids.remove(&my_id);
+
+    // Round 4: new members collect their sub-shares.
+    if is_member {
+        let aead_dict: HashMap<MpcAddr, AEAD> = messenger
+            .gather("reshare_share", dealers, my_id)
+            .await
+            .catch_()?;
+        let mut party_shares: HashMap<MpcAddr, Scalar> = HashMap::new();
+        for (id, com) in com_dict.iter() {
+            let mut aes_key =
+                eval_channel_key(&(com.ephemeral_pk * ephemeral_sk), context, my_id, *id)?;
+            let aead_pack = aead_dict.get(id).ifnone_()?;
+            let aad = p2p_aad(*id, my_id, "reshare_share");
+            let out = aes_decrypt_with_aad(&aes_key, aead_pack, &aad).catch(
+                "InvalidShare",
+                format!("Cannot decrypt share from party {}", id),
+            )?;
+            aes_key.zeroize();
+            party_shares.insert(*id, decode_scalar_strict(&out, *id)?);
+        }
+        let x_i = merge_vss_share(&party_shares, &new_vss_com_dict, my_id).catch_()?;
+        for x in party_shares.values_mut() {
+            x.zeroize();
+        }
+        new_keystore.xi_pergroup.insert(gid, x_i);
+        new_keystore.ids.insert(my_id);
+    }
+    ephemeral_sk.zeroize();
+
+    new_keystore.vss_com_grid.insert(gid, new_vss_com_dict);
+    // The keygen proofs are of the commitments just replaced.
+    new_keystore.vss_zkp_grid.remove(&gid);
+    info!(
+        round = "reshare_share",
+        n_members = new_members.len(),
+        th = new_th,
+        "Reshared group {}",
+        gid
+    );
+    Ok(new_keystore)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_util::{group_of, keygen_for_test, keygen_seeded_for_test};
+    use super::*;
+    use crate::KeyStoreExt;
+
+    /// $\sum_i \lambda_i x_i$ over `signers`, i.e. the group secret.
+    fn interpolate(keystores: &[&KeyStore], signers: &[u16]) -> Scalar {
+        let signers = group_of(signers);
+        keystores
+            .iter()
+            .map(|ks| {
+                let id = *ks.ids.iter().next().unwrap();
+                lagrange_lambda(id, &signers).unwrap() * ks.xi_pergroup[&1]
+            })
+            .sum()
+    }
+
+    #[tokio::test]
+    async fn reshare_to_3_of_4_keeps_group_key() {
+        let messenger = MemoryTransport::default();
+        let old = keygen_for_test(2, 3).await;
+        let pk = old[0].group_public_key();
+        let secret = interpolate(&[&old[0], &old[1]], &[1, 2]);
+
+        let newcomer = KeyStore {
+            vss_com_grid: old[0].vss_com_grid.clone(),
+            ..Default::default()
+        };
+        let (dealers, new_members) = (group_of(&[1, 2]), group_of(&[1, 2, 3, 4]));
+        let reshare = |i: u16, ks: KeyStore| {
+            let (messenger, dealers, new_members) =
+                (messenger.clone(), dealers.clone(), new_members.clone());
+            async move {
+                let my_id = MpcAddr::new(1, i);
+                algo_reshare(&messenger, &ks, my_id, &dealers, 3, &new_members, "test")
+                    .await
+                    .unwrap()
+            }
+        };
+        let (ks1, ks2, ks3, ks4) = tokio::join!(
+            reshare(1, old[0].clone()),
+            reshare(2, old[1].clone()),
+            reshare(3, old[2].clone()),
+            reshare(4, newcomer),
+        );
+
+        for ks in [&ks1, &ks2, &ks3, &ks4] {
+            assert_eq!(ks.group_public_key(), pk);
+            assert_eq!(ks.th(1).unwrap(), 3);
+        }
+        assert_ne!(ks1.xi_pergroup[&1], old[0].xi_pergroup[&1]);
+        assert_eq!(interpolate(&[&ks1, &ks3, &ks4], &[1, 3, 4]), secret);
+        assert_eq!(interpolate(&[&ks2, &ks3, &ks4], &[2, 3, 4]), secret);
+    }
+
+    #[tokio::test]
+    async fn member_is_reindexed_through_a_reshare() {
+        let messenger = MemoryTransport::default();
+        let old = keygen_for_test(2, 3).await;
+        let secret = interpolate(&[&old[0], &old[1]], &[1, 2]);
+        let (from, to) = (MpcAddr::new(1, 3), MpcAddr::new(1, 4));
+        assert!(old[2].with_member_id(to).is_err());
+
+        // Member 3 leaves its share behind and joins again as 4.
+        let rejoining = KeyStore {
+            vss_com_grid: old[2].vss_com_grid.clone(),
+            ..Default::default()
+        };
+        let (dealers, new_members) = (group_of(&[1, 2]), group_of(&[1, 2, 4]));
+        let reshare = |my_id: MpcAddr, ks: KeyStore| {
+            let (messenger, dealers, new_members) =
+                (messenger.clone(), dealers.clone(), new_members.clone());
+            async move {
+                algo_reshare(&messenger, &ks, my_id, &dealers, 2, &new_members, "test")
+                    .await
+                    .unwrap()
+            }
+        };
+        let (ks1, _, ks4) = tokio::join!(
+            reshare(MpcAddr::new(1, 1), old[0].clone()),
+            reshare(MpcAddr::new(1, 2), old[1].clone()),
+            reshare(to, rejoining),
+        );
+
+        assert_eq!(ks4.ids, HashSet::from([to]));
+        assert_eq!(ks4.with_member_id(to).unwrap().ids, ks4.ids);
+        assert!(ks4.with_member_id(from).is_err());
+        assert_eq!(interpolate(&[&ks1, &ks4], &[1, 4]), secret);
+    }
+
+    #[tokio::test]
+    async fn inconsistent_dealer_is_rejected() {
+        let messenger = MemoryTransport::default();
+        let old = keygen_for_test(2, 3).await;
+        let members = group_of(&[1, 2, 3]);
+
+        // Dealer 2 weighs its share for the wrong dealer set,
+        // which would change the group key.
+        let (m, ks, mem) = (messenger.clone(), old[1].clone(), members.clone());
+        tokio::spawn(async move {
+            let dealers = group_of(&[1, 2, 3]);
+            let _ = algo_reshare(&m, &ks, MpcAddr::new(1, 2), &dealers, 2, &mem, "test").await;
+        });
+
+        let dealers = group_of(&[1, 2]);
+        let reshare = |i: u16| {
+            let (messenger, ks, dealers, members) = (
+                messenger.clone(),
+                old[i as usize - 1].clone(),
+                dealers.clone(),
+                members.clone(),
+            );
+            async move {
+                let my_id = MpcAddr::new(1, i);
+                algo_reshare(&messenger, &ks, my_id, &dealers, 2, &members, "test").await
+            }
+        };
+        let (r1, r3) = tokio::join!(reshare(1), reshare(3));
+        let cheater = format!("{:?}", vec![MpcAddr::new(1, 2)]);
+        for r in [r1, r3] {
+            let err = r.unwrap_err();
+            assert_eq!(err.get_name(), "InvalidShare");
+            assert!(err.get_context().unwrap().contains(&cheater));
+        }
+    }
+
+    #[tokio::test]
+    async fn reconstructed_secret_is_the_keygen_secret() {
+        let keystores = keygen_seeded_for_test(2, 3).await;
+        // Every dealer's u_i is a term of the group secret.
+        let expected: Scalar = keystores.iter().map(|ks| ks.ui_pergroup[&1]).sum();
+        let share_of = |i: usize| {
+            let ks = &keystores[i];
+            (*ks.ids.iter().next().unwrap(), ks.xi_pergroup[&1])
+        };
+
+        for picked in [&[0, 1][..], &[1, 2], &[0, 1, 2]] {
+            let shares: HashMap<MpcAddr, Scalar> = picked.iter().map(|i| share_of(*i)).collect();
+            assert_eq!(reconstruct_secret(&shares, 2).unwrap(), expected);
+        }
+        let g = constants::ED25519_BASEPOINT_POINT;
+        assert_eq!(g * expected, keystores[0].group_public_key());
+
+        let too_few = HashMap::from([share_of(0)]);
+        let err = reconstruct_secret(&too_few, 2).unwrap_err();
+        assert_eq!(err.get_name(), "InvalidParameters");
+        let (id, x) = share_of(1);
+        let foreign = HashMap::from([share_of(0), (MpcAddr::new(2, id.member_id()), x)]);
+        assert!(reconstruct_secret(&foreign, 2).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use std::future::Future;
+use std::time::Duration;
+
+use mpc_spec::*;
+use tracing::warn;
+
+/// How protocol functions cope with transient `Messenger` failures.
+/// `SesmanConfig::default()` makes a single attempt.
+#[derive(Clone, Debug, Default)]
+pub struct SesmanConfig {
+    /// Attempts after the first failed one.
+    pub retries: u32,
+    /// Delay before the first retry, doubled at each following one.
+    pub base_delay: Duration,
+}
+
+/// Repeat `call` on transient errors (judged by `Messenger::is_transient`),
+/// at most `cfg.retries` times with exponential backoff.
+pub async fn with_retry<M, T, F, Fut>(
+    messenger: &M,
+    cfg: &SesmanConfig,
+    round: &str,
+    mut call: F,
+) -> Result<T, M::E>
+where
+    M: Messenger,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, M::E>>,
+{
+    let mut attempt = 0;
+    loop {
+        match call().await {
+            Ok(v) => return Ok(v),
+            Err(e) if attempt < cfg.retries && messenger.is_transient(&e) => {
+                let delay = cfg.base_delay.saturating_mul(1 << attempt.min(16));
+                warn!(round, attempt, ?delay, "Retrying after error: {}", e);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    use libexception::*;
+    use serde::{de::DeserializeOwned, Serialize};
+
+    use super::*;
+
+    /// Fails the first `failures` sends with `error`, then behaves.
+    #[derive(Clone)]
+    struct FlakyMessenger {
+        inner: MemoryTransport,
+        failures: Arc<AtomicU32>,
+        attempts: Arc<AtomicU32>,
+        error: &'static str,
+    }
+
+    impl FlakyMessenger {
+        fn new(failures: u32, error: &'static str) -> Self {
+            Self {
+                inner: MemoryTransport::default(),
+                failures: Arc::new(AtomicU32::new(failures)),
+                attempts: Arc::new(AtomicU32::new(0)),
+                error,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Messenger for FlakyMessenger {
+        type E = Box<Exception>;
+
+        async fn send<T>(&self, topic: &str, src: MpcAddr, dst: MpcAddr, obj: &T) -> Outcome<()>
+        where
+            T: Serialize + DeserializeOwned + Send + Sync,
+        {
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            let failures = self.failures.load(Ordering::SeqCst);
+            if failures > 0 {
+                self.failures.store(failures - 1, Ordering::SeqCst);
+                throw!(self.error, "mocked");
+            }
+            self.inner.send(topic, src, dst, obj).await
+        }
+
+        async fn receive<T>(&self, topic: &str, src: MpcAddr, dst: MpcAddr) -> Outcome<T>
+        where
+            T: Serialize + DeserializeOwned + Send + Sync,
+        {
+            self.inner.receive(topic, src, dst).await
+        }
+
+        async fn scatter<T>(
+            &self,
+            topic: &str,
+            src: MpcAddr,
+            dsts: &HashSet<MpcAddr>,
+            obj: &T,
+        ) -> Outcome<()>
+        where
+            T: Serialize + DeserializeOwned + Send + Sync,
+        {
+            self.inner.scatter(topic, src, dsts, obj).await
+        }
+
+        async fn gather<T>(
+            &self,
+            topic: &str,
+            srcs: &HashSet<MpcAddr>,
+            dst: MpcAddr,
+        ) -> Outcome<HashMap<MpcAddr, T>>
+        where
+            T: Serialize + DeserializeOwned + Send + Sync,
+        {
+            self.inner.gather(topic, srcs, dst).await
+        }
+
+        fn is_transient(&self, err: &Self::E) -> bool {
+            err.get_name() != "PeerRejected"
+        }
+    }
+
+    const CFG: SesmanConfig = SesmanConfig {
+        retries: 3,
+        base_delay: Duration::from_millis(1),
+    };
+
+    #[tokio::test]
+    async fn transient_errors_are_retried() {
+        let (src, dst) = (MpcAddr::new(1, 1), MpcAddr::new(1, 2));
+        let messenger = FlakyMessenger::new(2, "ConnectionError");
+        with_retry(&messenger, &CFG, "topic", || {
+            messenger.send("topic", src, dst, &42u32)
+        })
+        .await
+        .unwrap();
+        assert_eq!(messenger.attempts.load(Ordering::SeqCst), 3);
+        let got: u32 = messenger.receive("topic", src, dst).await.unwrap();
+        assert_eq!(got, 42);
+
+        let messenger = FlakyMessenger::new(5, "ConnectionError");
+        let res = with_retry(&messenger, &CFG, "topic", || {
+            messenger.send("topic", src, dst, &42u32)
+        })
+        .await;
+        assert!(res.is_err());
+        assert_eq!(messenger.attempts.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn definitive_errors_are_not_retried() {
+        let (src, dst) = (MpcAddr::new(1, 1), MpcAddr::new(1, 2));
+        let messenger = FlakyMessenger::new(2, "PeerRejected");
+        let err = with_retry(&messenger, &CFG, "topic", || {
+            messenger.send("topic", src, dst, &42u32)
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(err.get_name(), "PeerRejected");
+        assert_eq!(messenger.attempts.load(Ordering::SeqCst), 1);
+    }
+}
@@ -3,15 +3,39 @@ use curve25519_dalek::{constants, scalar::Scalar};
 use libexception::*;
 use mpc_spec::*;
 use rand::rngs::OsRng;
+use sha2::{Digest, Sha512};
 use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::io::Read;
+use std::time::Instant;
+use zeroize::Zeroize;
 
-use super::{hd::*, KeyStore};
+#[cfg(feature = "hd")]
+use super::hd::*;
+use super::{gather_quorum, KeyStore};
 use crate::frost::{
-    agg_nonce_com, eval_xi_com, gen_rho_i, generate_challenge, is_valid_response, lagrange_lambda,
-    sign_and_respond, sign_preprocess, verify_signature, verify_solana, Signature,
-    SigningCommitmentPair, SigningNoncePair,
+    agg_nonce_com, ed25519ctx_dom, ed25519ph_dom, eval_xi_com, gen_rho_i, is_valid_response,
+    lagrange_lambda, sign_and_respond, sign_preprocess, verify_solana, SigningCommitmentPair,
+    SigningNoncePair,
+};
+pub use crate::frost::{
+    find_invalid_in_batch, lagrange_coefficient, verify_batch, verify_prehashed, verify_signature,
+    verify_with_context, ChallengeHash, Ed25519Challenge, Signature,
 };
 
+/// FROST signing of `msg_hash` by the signers in `ses_arch`,
+/// at least `th` of each group, for the child key at `drv_path`
+/// (`""` for the group public key itself). Any such subset of a group signs
+/// for the same key; see `ses_arch_of` to pick one by member ids.
+/// Without the `hd` feature, a `drv_path` other than `""` or `"m"` throws `HdUnsupported`.
+///
+/// Round 1 broadcasts nonce commitments $(dG, eG)$, round 2 broadcasts the
+/// responses $z_i$. There is no coordinator: every signer validates each
+/// response, throwing `InvalidSignerResponse` naming the signers of bad ones,
+/// and aggregates them on its own. Round 3 broadcasts the aggregate,
+/// and a signer whose peers reached a different one throws
+/// `SignatureDisagreement`. So any of them can publish the resulting
+/// signature, which is a standard Ed25519 one.
 pub async fn algo_sign(
     messenger: &impl Messenger,
     ses_arch: &HashMap<u16, HashSet<MpcAddr>>,
@@ -19,6 +43,473 @@ pub async fn algo_sign(
     msg_hash: &[u8],
     keystore: &KeyStore,
 ) -> Outcome<Signature> {
+    let (sig, child_pk) = sign_with_dom(
+        messenger,
+        ses_arch,
+        drv_path,
+        None,
+        &Ed25519Challenge,
+        &[],
+        msg_hash,
+        keystore,
+        &NonceMode::Random,
+    )
+    .await?;
+    verify_solana(&sig, &child_pk).catch("", "Failed at verify_solana()")?;
+    Ok(sig)
+}
+
+/// Same as `algo_sign`, but derives the child key at `drv_path` with the
+/// given `chain_code` instead of the one computed from the group public key.
+///
+/// The tweak of `non_hardened_derive` is added to the shares of the lowest
+/// group, and its commitment to that group's lowest dealer, so every signer
+/// sees the same tweaked key and the aggregate verifies against `child_pk`.
+#[cfg(feature = "hd")]
+pub async fn algo_sign_hd(
+    messenger: &impl Messenger,
+    ses_arch: &HashMap<u16, HashSet<MpcAddr>>,
+    drv_path: &str,
+    chain_code: &bip32::ChainCode,
+    msg_hash: &[u8],
+    keystore: &KeyStore,
+) -> Outcome<Signature> {
+    let (sig, child_pk) = sign_with_dom(
+        messenger,
+        ses_arch,
+        drv_path,
+        Some(chain_code),
+        &Ed25519Challenge,
+        &[],
+        msg_hash,
+        keystore,
+        &NonceMode::Random,
+    )
+    .await?;
+    verify_solana(&sig, &child_pk).catch("", "Failed at verify_solana()")?;
+    Ok(sig)
+}
+
+/// Same as `algo_sign`, but with the challenge computed by `hasher`
+/// instead of the RFC 8032 one, for chains with their own EdDSA variant,
+/// and the child key at `drv_path` derived with `chain_code`, as in
+/// `algo_sign_hd`. Without the `hd` feature, `chain_code` is unused and a
+/// `drv_path` other than `""` or `"m"` throws `HdUnsupported`.
+/// The result is checked with `Signature::verify_with` and the same `hasher`
+/// against the child key before it is returned.
+pub async fn algo_sign_with_challenge(
+    messenger: &impl Messenger,
+    ses_arch: &HashMap<u16, HashSet<MpcAddr>>,
+    drv_path: &str,
+    chain_code: &[u8; 32],
+    hasher: &impl ChallengeHash,
+    msg_hash: &[u8],
+    keystore: &KeyStore,
+) -> Outcome<Signature> {
+    let (sig, child_pk) = sign_with_dom(
+        messenger,
+        ses_arch,
+        drv_path,
+        Some(chain_code),
+        hasher,
+        &[],
+        msg_hash,
+        keystore,
+        &NonceMode::Random,
+    )
+    .await?;
+    sig.verify_with(hasher, &[], msg_hash, &child_pk)
+        .catch("", "Failed at verify_with()")?;
+    Ok(sig)
+}
+
+/// Same as `algo_sign`, but `ses_arch` may invite more than `th` signers of
+/// a group, and the first `th` of them to show up sign, so that stragglers
+/// don't hold up the others.
+///
+/// The lowest invited member of each group coordinates: it waits on round
+/// "sign_ready" for `th` members including itself, then broadcasts who they
+/// are on round "sign_quorum", so every signer uses the same Lagrange
+/// coefficients. Members left out, or answering later, don't sign but take
+/// the quorum's aggregate from round "sign_agg" once it verifies, so every
+/// invited party returns the same signature.
+///
+/// Throws `SignTimeout`, naming the round, if `deadline` passes first,
+/// e.g. when fewer than `th` show up or the coordinator is among the missing.
+/// `Some` needs a tokio runtime with the time driver enabled.
+/// A party with several shards of a group throws `InvalidSigners` if the
+/// quorum takes only some of them.
+pub async fn algo_sign_quorum(
+    messenger: &impl Messenger,
+    ses_arch: &HashMap<u16, HashSet<MpcAddr>>,
+    drv_path: &str,
+    msg_hash: &[u8],
+    keystore: &KeyStore,
+    deadline: Option<Instant>,
+) -> Outcome<Signature> {
+    validate_ses_arch(ses_arch, keystore)?;
+    let bcast_id = MpcAddr::bcast_id();
+    let mut group_ids_asc: Vec<u16> = ses_arch.keys().cloned().collect();
+    group_ids_asc.sort();
+    let mut invited_mine: Vec<MpcAddr> = keystore
+        .ids
+        .iter()
+        .filter(|id| ses_arch.contains_key(&id.group_id()))
+        .cloned()
+        .collect();
+    invited_mine.sort();
+    for my_id in invited_mine.iter() {
+        messenger
+            .send("sign_ready", *my_id, bcast_id, &true)
+            .await
+            .catch_()?;
+    }
+
+    // Coordinators pick the first `th` of their group.
+    for gid in group_ids_asc.iter() {
+        let invited = ses_arch.get(gid).ifnone_()?;
+        let coordinator = *invited.iter().min().ifnone_()?;
+        if !keystore.ids.contains(&coordinator) {
+            continue;
+        }
+        let th = keystore.th(*gid).catch_()?;
+        let (mine, others): (HashSet<MpcAddr>, HashSet<MpcAddr>) =
+            invited.iter().partition(|id| keystore.ids.contains(id));
+        let min = th.saturating_sub(mine.len());
+        let ready: HashMap<MpcAddr, bool> = sign_until(deadline, "sign_ready", async {
+            gather_quorum(messenger, "sign_ready", &others, bcast_id, min).await
+        })
+        .await?;
+        let mut quorum: Vec<MpcAddr> = mine.into_iter().chain(ready.into_keys()).collect();
+        quorum.sort();
+        messenger
+            .send("sign_quorum", coordinator, bcast_id, &quorum)
+            .await
+            .catch_()?;
+    }
+
+    // Learn the quorum of every group.
+    let mut quorum_arch: HashMap<u16, HashSet<MpcAddr>> = HashMap::new();
+    for gid in group_ids_asc.iter() {
+        let invited = ses_arch.get(gid).ifnone_()?;
+        let coordinator = *invited.iter().min().ifnone_()?;
+        let quorum: Vec<MpcAddr> = sign_until(deadline, "sign_quorum", async {
+            messenger
+                .receive("sign_quorum", coordinator, bcast_id)
+                .await
+                .catch_()
+        })
+        .await?;
+        let th = keystore.th(*gid).catch_()?;
+        let quorum: HashSet<MpcAddr> = quorum.into_iter().collect();
+        assert_throw!(
+            quorum.len() >= th && quorum.is_subset(invited),
+            "InvalidSigners",
+            format!(
+                "Coordinator {} picked a quorum of {} out of the invited",
+                coordinator,
+                quorum.len()
+            )
+        );
+        quorum_arch.insert(*gid, quorum);
+    }
+
+    let (chosen, left_out): (Vec<MpcAddr>, Vec<MpcAddr>) = invited_mine
+        .iter()
+        .partition(|id| quorum_arch[&id.group_id()].contains(id));
+    if left_out.is_empty() {
+        let fut = async { algo_sign(messenger, &quorum_arch, drv_path, msg_hash, keystore).await };
+        return sign_until(deadline, "sign", fut).await;
+    }
+    assert_throw!(
+        chosen.is_empty(),
+        "InvalidSigners",
+        format!("The quorum leaves out my shards {:?} only", left_out)
+    );
+
+    // Not in the quorum: take its aggregate.
+    let signers: HashSet<MpcAddr> = quorum_arch.values().flatten().cloned().collect();
+    let agg: HashMap<MpcAddr, Vec<u8>> = sign_until(deadline, "sign_agg", async {
+        gather_quorum(messenger, "sign_agg", &signers, bcast_id, 1).await
+    })
+    .await?;
+    let sig_bytes = agg.into_values().next().ifnone_()?;
+    let sig = Signature::from_bytes(&sig_bytes, msg_hash).catch_()?;
+    let main_pk = keystore.pk().catch_()?;
+    let (_, child_pk) = derive_child(drv_path, &main_pk, None)?;
+    verify_solana(&sig, &child_pk).catch(
+        "InvalidSignature",
+        "The aggregate of the quorum does not verify",
+    )?;
+    Ok(sig)
+}
+
+/// How a signer draws its nonce pair $(d, e)$ in round 1 of signing.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum NonceMode {
+    /// Fresh from the OS RNG, as `algo_sign` does.
+    #[default]
+    Random,
+    /// Hashed from my share, the message, the challenge hash, the child key,
+    /// the signers, my id and `session`, see `derive_nonce_pair`, so the same
+    /// signers signing the same message under the same key in the same
+    /// `session` reproduce the same signature without an RNG.
+    ///
+    /// Unlike RFC 6979 for a single signer, this is only safe if every
+    /// `session` is used for one signing attempt at most. My nonce does not
+    /// depend on my peers' commitments: if a peer retries the same message and
+    /// `session` with a different nonce of its own, the challenge changes
+    /// while my nonce doesn't, and a few such responses solve for my share.
+    /// So never reuse a `session`, not even to retry a failed signing, and
+    /// never across different messages.
+    Deterministic { session: Vec<u8> },
+}
+
+/// Same as `algo_sign`, but with nonces drawn by `nonce_mode`,
+/// which all signers should agree on.
+pub async fn algo_sign_with_nonce_mode(
+    messenger: &impl Messenger,
+    ses_arch: &HashMap<u16, HashSet<MpcAddr>>,
+    drv_path: &str,
+    msg_hash: &[u8],
+    keystore: &KeyStore,
+    nonce_mode: &NonceMode,
+) -> Outcome<Signature> {
+    let (sig, child_pk) = sign_with_dom(
+        messenger,
+        ses_arch,
+        drv_path,
+        None,
+        &Ed25519Challenge,
+        &[],
+        msg_hash,
+        keystore,
+        nonce_mode,
+    )
+    .await?;
+    verify_solana(&sig, &child_pk).catch("", "Failed at verify_solana()")?;
+    Ok(sig)
+}
+
+/// The nonce pair of `NonceMode::Deterministic`: $d$ and $e$ are the SHA-512
+/// of a label, the 32 bytes of `x_i`, the group and member ids of `my_id` as
+/// big-endian `u16`, the compressed `child_pk`, the number of `signers` and
+/// their ids in ascending order, and the lengths and bytes of `msg` and
+/// `session`, each read as a little-endian integer reduced mod $\ell$.
+///
+/// So the nonces change with the signing instance, not only with `session`.
+/// `msg` should carry whatever else the challenge hashes besides $R$ and the
+/// key: `algo_sign` and the like pass the length-prefixed domain, the
+/// challenge of their hasher at $R = G$, which tells hashers apart, and the
+/// message.
+pub fn derive_nonce_pair(
+    x_i: &Scalar,
+    msg: &[u8],
+    my_id: MpcAddr,
+    session: &[u8],
+    child_pk: &EdwardsPoint,
+    signers: &[MpcAddr],
+) -> Outcome<SigningNoncePair> {
+    let mut signers_asc = signers.to_vec();
+    signers_asc.sort();
+    let derive = |label: &[u8]| {
+        let mut hasher = Sha512::new();
+        hasher.update(label);
+        hasher.update(x_i.as_bytes());
+        hasher.update(my_id.group_id().to_be_bytes());
+        hasher.update(my_id.member_id().to_be_bytes());
+        hasher.update(child_pk.compress().as_bytes());
+        hasher.update((signers_asc.len() as u64).to_be_bytes());
+        for id in signers_asc.iter() {
+            hasher.update(id.group_id().to_be_bytes());
+            hasher.update(id.member_id().to_be_bytes());
+        }
+        hasher.update((msg.len() as u64).to_be_bytes());
+        hasher.update(msg);
+        hasher.update((session.len() as u64).to_be_bytes());
+        hasher.update(session);
+        let mut wide = [0u8; 64];
+        wide.copy_from_slice(&hasher.finalize());
+        let k = Scalar::from_bytes_mod_order_wide(&wide);
+        wide.zeroize();
+        k
+    };
+    let (mut d, mut e) = (derive(b"FROST-nonce-d"), derive(b"FROST-nonce-e"));
+    let nonce = SigningNoncePair::from_scalars(d, e);
+    d.zeroize();
+    e.zeroize();
+    nonce
+}
+
+/// The `ses_arch` of a single group `gid` whose members `signers` sign,
+/// e.g. `ses_arch_of(1, &[1, 3, 4])` for three members available out of five.
+/// Duplicate or zero member ids throw `InvalidSigners`.
+pub fn ses_arch_of(gid: u16, signers: &[u16]) -> Outcome<HashMap<u16, HashSet<MpcAddr>>> {
+    let members: HashSet<MpcAddr> = signers.iter().map(|i| MpcAddr::new(gid, *i)).collect();
+    assert_throw!(
+        members.len() == signers.len(),
+        "InvalidSigners",
+        format!("Signers {:?} are not distinct", signers)
+    );
+    assert_throw!(
+        !signers.contains(&0),
+        "InvalidSigners",
+        "Member id 0 is reserved"
+    );
+    Ok(HashMap::from([(gid, members)]))
+}
+
+/// Check that the signers in `ses_arch` can sign with `keystore`: each group
+/// is known to it, has at least `th` signers of that group, and includes every
+/// shard of mine in that group, who would otherwise wait for itself.
+pub(crate) fn validate_ses_arch(
+    ses_arch: &HashMap<u16, HashSet<MpcAddr>>,
+    keystore: &KeyStore,
+) -> Outcome<()> {
+    const ISE: &str = "InvalidSigners";
+    assert_throw!(!ses_arch.is_empty(), ISE, "No group signs");
+    for (gid, signers) in ses_arch.iter() {
+        let th = keystore
+            .th(*gid)
+            .catch(ISE, format!("Group {} is not in the keystore", gid))?;
+        assert_throw!(
+            signers.len() >= th,
+            ISE,
+            format!(
+                "{} signers cannot sign for group {} of threshold th={}",
+                signers.len(),
+                gid,
+                th
+            )
+        );
+        let mut strangers: Vec<&MpcAddr> = signers
+            .iter()
+            .filter(|id| id.group_id() != *gid || id.member_id() == 0)
+            .collect();
+        strangers.sort();
+        assert_throw!(
+            strangers.is_empty(),
+            ISE,
+            format!("Signers {:?} are not members of group {}", strangers, gid)
+        );
+    }
+    let mut absent: Vec<&MpcAddr> = keystore
+        .ids
+        .iter()
+        .filter(|id| ses_arch.contains_key(&id.group_id()))
+        .filter(|id| !ses_arch[&id.group_id()].contains(id))
+        .collect();
+    absent.sort();
+    assert_throw!(
+        absent.is_empty(),
+        ISE,
+        format!("My shards {:?} are left out of the signers", absent)
+    );
+    Ok(())
+}
+
+/// SHA-512 digest of everything `reader` yields, read in chunks,
+/// to be signed by `algo_sign_prehashed`.
+pub fn prehash_message(mut reader: impl Read) -> Outcome<[u8; 64]> {
+    let mut hasher = Sha512::new();
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = reader
+            .read(&mut buf)
+            .catch("IoError", "Cannot read the message")?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    let mut digest = [0u8; 64];
+    digest.copy_from_slice(&hasher.finalize());
+    Ok(digest)
+}
+
+/// Same as `algo_sign`, but signs in Ed25519ph mode (RFC 8032) the SHA-512
+/// `digest` of a message, so a large message need not be held in memory.
+/// See `prehash_message` to stream one from an `impl Read`.
+///
+/// The challenge is prefixed with `ed25519ph_dom(context)`, which keeps the
+/// signature of a digest apart from a signature of the same 64 bytes as a raw
+/// message. The result carries `digest` as its `hash` and verifies with
+/// `verify_prehashed`, not `verify_signature`.
+pub async fn algo_sign_prehashed(
+    messenger: &impl Messenger,
+    ses_arch: &HashMap<u16, HashSet<MpcAddr>>,
+    drv_path: &str,
+    digest: &[u8; 64],
+    context: &[u8],
+    keystore: &KeyStore,
+) -> Outcome<Signature> {
+    let dom = ed25519ph_dom(context)?;
+    let (sig, _) = sign_with_dom(
+        messenger,
+        ses_arch,
+        drv_path,
+        None,
+        &Ed25519Challenge,
+        &dom,
+        digest,
+        keystore,
+        &NonceMode::Random,
+    )
+    .await?;
+    Ok(sig)
+}
+
+/// Same as `algo_sign`, but domain-separated by `sign_context`, e.g. a chain
+/// id, apart from both `msg_hash` and the keygen context. A non-empty one
+/// signs in Ed25519ctx mode (RFC 8032): the challenge hashes
+/// `ed25519ctx_dom(sign_context)` before $R$, $A$ and the message, and the
+/// result verifies with `verify_with_context`. An empty one is plain
+/// `algo_sign`. Contexts longer than 255 bytes throw `InvalidParameters`.
+pub async fn algo_sign_with_context(
+    messenger: &impl Messenger,
+    ses_arch: &HashMap<u16, HashSet<MpcAddr>>,
+    drv_path: &str,
+    msg_hash: &[u8],
+    sign_context: &[u8],
+    keystore: &KeyStore,
+) -> Outcome<Signature> {
+    if sign_context.is_empty() {
+        return algo_sign(messenger, ses_arch, drv_path, msg_hash, keystore).await;
+    }
+    let dom = ed25519ctx_dom(sign_context)?;
+    let (sig, child_pk) = sign_with_dom(
+        messenger,
+        ses_arch,
+        drv_path,
+        None,
+        &Ed25519Challenge,
+        &dom,
+        msg_hash,
+        keystore,
+        &NonceMode::Random,
+    )
+    .await?;
+    verify_with_context(&sig, sign_context, &child_pk).catch_()?;
+    Ok(sig)
+}
+
+/// The rounds of `algo_sign_hd`, with the challenge of `hasher` on `dom`
+/// and `msg_hash`, `chain_code` defaulting to `eval_chain_code`, and nonces
+/// drawn by `nonce_mode`.
+/// Returns the signature with the child key it verifies against.
+#[allow(clippy::too_many_arguments)]
+async fn sign_with_dom(
+    messenger: &impl Messenger,
+    ses_arch: &HashMap<u16, HashSet<MpcAddr>>,
+    drv_path: &str,
+    chain_code: Option<&[u8; 32]>,
+    hasher: &impl ChallengeHash,
+    dom: &[u8],
+    msg_hash: &[u8],
+    keystore: &KeyStore,
+    nonce_mode: &NonceMode,
+) -> Outcome<(Signature, EdwardsPoint)> {
+    validate_ses_arch(ses_arch, keystore)?;
     let bcast_id = MpcAddr::bcast_id();
     let mut whoami_asc: Vec<MpcAddr> = keystore.ids.iter().cloned().collect();
     whoami_asc.sort();
@@ -28,11 +519,7 @@ pub async fn algo_sign(
 
     // Derive child pk
     let main_pk = keystore.pk().catch_()?;
-    let chain_code = eval_chain_code(&main_pk);
-    let (tweak_sk, child_pk) = match drv_path.is_empty() {
-        true => (Scalar::zero(), main_pk),
-        false => non_hardened_derive(drv_path, &main_pk, &chain_code).catch_()?,
-    };
+    let (tweak_sk, child_pk) = derive_child(drv_path, &main_pk, chain_code)?;
 
     // apply bip32 tweak
     let keystore = {
@@ -79,9 +566,32 @@ pub async fn algo_sign(
     }
 
     // Generate nonce pair $(d, e)$, and broadcast $(dG, eG)$.
+    let signers: Vec<MpcAddr> = ses_arch.values().flatten().cloned().collect();
     let mut my_nonce_dict: HashMap<MpcAddr, SigningNoncePair> = HashMap::new();
     for my_id in whoami_asc.iter() {
-        let _obj: _ = sign_preprocess(&mut rng).catch_()?;
+        let _obj: _ = match nonce_mode {
+            NonceMode::Random => sign_preprocess(&mut rng).catch_()?,
+            NonceMode::Deterministic { session } => {
+                let x_i = keystore.xi_pergroup.get(&my_id.group_id()).ifnone_()?;
+                let probe = hasher.challenge(
+                    dom,
+                    msg_hash,
+                    &constants::ED25519_BASEPOINT_POINT,
+                    &child_pk,
+                );
+                let nonce_msg = [
+                    &(dom.len() as u64).to_be_bytes()[..],
+                    dom,
+                    probe.as_bytes(),
+                    msg_hash,
+                ]
+                .concat();
+                let nonce =
+                    derive_nonce_pair(x_i, &nonce_msg, *my_id, session, &child_pk, &signers)
+                        .catch_()?;
+                (nonce.commitment(), nonce)
+            }
+        };
         let nonce_com: SigningCommitmentPair = _obj.0;
         let nonce_pair: SigningNoncePair = _obj.1;
         my_nonce_dict.insert(*my_id, nonce_pair.clone());
@@ -103,15 +613,17 @@ pub async fn algo_sign(
         nonce_com_dict.extend(obj);
     }
 
-    // Compute rho dict
+    // Compute rho dict, bound to the mode as well as the message
+    let rho_msg = [dom, msg_hash].concat();
     let mut rho_dict: HashMap<MpcAddr, Scalar> = HashMap::new();
     for (j, _) in nonce_com_dict.iter() {
-        let rho_i = gen_rho_i(*j, msg_hash, &nonce_com_dict);
+        let rho_i = gen_rho_i(*j, &rho_msg, &nonce_com_dict);
         rho_dict.insert(*j, rho_i);
     }
 
-    // Aggregate sig.r
+    // Aggregate sig.r, and compute challenge
     let sig_r = agg_nonce_com(&nonce_com_dict, &rho_dict).catch_()?;
+    let challenge = hasher.challenge(dom, msg_hash, &sig_r, &child_pk);
 
     // Generate and broadcast sign response
     for my_id in whoami_asc.iter() {
@@ -119,17 +631,9 @@ pub async fn algo_sign(
         let my_gid = my_id.group_id();
         let x_i = keystore.xi_pergroup.get(&my_gid).ifnone_()?;
         let group_members = ses_arch.get(&my_gid).ifnone_()?;
-        let sign_resp = sign_and_respond(
-            *my_id,
-            x_i,
-            &rho_dict,
-            &sig_r,
-            my_nonce,
-            group_members,
-            &child_pk,
-            msg_hash,
-        )
-        .catch_()?;
+        let sign_resp =
+            sign_and_respond(*my_id, x_i, &rho_dict, my_nonce, group_members, &challenge)
+                .catch_()?;
         messenger
             .send("sign_resp", *my_id, bcast_id, &sign_resp)
             .await
@@ -148,10 +652,9 @@ pub async fn algo_sign(
         resp_dict.extend(obj);
     }
 
-    // Compute challenge
-    let challenge = generate_challenge(msg_hash, &sig_r, &child_pk);
-
-    // Validate each participant's response
+    // Validate each participant's response before aggregating,
+    // so a bad one is pinned on its signer instead of spoiling the signature.
+    let mut invalid_ids: Vec<MpcAddr> = Vec::new();
     for (j, resp) in resp_dict.iter() {
         let rho_j = rho_dict.get(j).ifnone_()?;
         let signers = ses_arch.get(&j.group_id()).ifnone_()?;
@@ -160,9 +663,19 @@ pub async fn algo_sign(
         let com = nonce_com.g_d + (nonce_com.g_e * rho_j);
         let xjg = xjg_dict.get(j).ifnone_()?; // $x_j * G$
 
-        let resp_is_valid = is_valid_response(resp, xjg, &lam_i, &com, &challenge);
-        assert_throw!(resp_is_valid, "Invalid signer response");
+        if !is_valid_response(resp, xjg, &lam_i, &com, &challenge) {
+            invalid_ids.push(*j);
+        }
     }
+    invalid_ids.sort();
+    assert_throw!(
+        invalid_ids.is_empty(),
+        "InvalidSignerResponse",
+        format!(
+            "Responses of signers {:?} do not match their shares and nonces",
+            invalid_ids
+        )
+    );
 
     // Aggregate sig_s
     let mut sig_s = Scalar::zero();
@@ -175,10 +688,635 @@ pub async fn algo_sign(
         hash: msg_hash.to_vec(),
     };
 
-    verify_signature(&sig, &child_pk).catch("InvalidSignature", "Most probably lack of signers")?;
-    verify_solana(&sig, &child_pk).catch("", "Failed at verify_solana()")?;
+    sig.verify_with(hasher, dom, msg_hash, &child_pk)
+        .catch("InvalidSignature", "Most probably lack of signers")?;
+
+    // Check that every signer aggregated the same signature
+    let sig_bytes = sig.to_bytes().to_vec();
+    for my_id in whoami_asc.iter() {
+        messenger
+            .send("sign_agg", *my_id, bcast_id, &sig_bytes)
+            .await
+            .catch_()?;
+    }
+    let mut disagreeing: Vec<MpcAddr> = Vec::new();
+    for gid in group_ids_asc.iter() {
+        let group_members = ses_arch.get(gid).ifnone_()?;
+        let obj: HashMap<MpcAddr, Vec<u8>> = messenger
+            .gather("sign_agg", group_members, bcast_id)
+            .await
+            .catch_()?;
+        disagreeing.extend(
+            obj.into_iter()
+                .filter(|(_, b)| *b != sig_bytes)
+                .map(|(j, _)| j),
+        );
+    }
+    disagreeing.sort();
+    assert_throw!(
+        disagreeing.is_empty(),
+        "SignatureDisagreement",
+        format!("Parties {:?} aggregated another signature", disagreeing)
+    );
     println!("Finished aggregating signature shares");
     // #endregion
 
-    Ok(sig)
+    Ok((sig, child_pk))
+}
+
+/// The tweak and child key of `non_hardened_derive` at `drv_path`,
+/// with `chain_code` defaulting to `eval_chain_code(main_pk)`.
+#[cfg(feature = "hd")]
+fn derive_child(
+    drv_path: &str,
+    main_pk: &EdwardsPoint,
+    chain_code: Option<&[u8; 32]>,
+) -> Outcome<(Scalar, EdwardsPoint)> {
+    if drv_path.is_empty() {
+        return Ok((Scalar::zero(), *main_pk));
+    }
+    let chain_code = chain_code
+        .cloned()
+        .unwrap_or_else(|| eval_chain_code(main_pk));
+    non_hardened_derive(drv_path, main_pk, &chain_code).catch_()
+}
+
+/// Without `hd`, only the group public key itself can sign.
+#[cfg(not(feature = "hd"))]
+fn derive_child(
+    drv_path: &str,
+    main_pk: &EdwardsPoint,
+    _chain_code: Option<&[u8; 32]>,
+) -> Outcome<(Scalar, EdwardsPoint)> {
+    assert_throw!(
+        drv_path.is_empty() || drv_path == "m",
+        "HdUnsupported",
+        format!("Cannot derive \"{}\" without the `hd` feature", drv_path)
+    );
+    Ok((Scalar::zero(), *main_pk))
+}
+
+/// See `KeyStoreExt::can_sign_path`.
+#[cfg(feature = "hd")]
+pub(crate) fn is_signable_path(drv_path: &str, main_pk: &EdwardsPoint) -> Outcome<bool> {
+    if drv_path.is_empty() {
+        return Ok(true);
+    }
+    let segments = parse_and_classify_path(drv_path)?;
+    if segments.iter().any(|(_, is_hardened)| *is_hardened) {
+        return Ok(false);
+    }
+    derive_child(drv_path, main_pk, None)?;
+    Ok(true)
+}
+
+#[cfg(not(feature = "hd"))]
+pub(crate) fn is_signable_path(drv_path: &str, _main_pk: &EdwardsPoint) -> Outcome<bool> {
+    Ok(drv_path.is_empty() || drv_path == "m")
+}
+
+/// Await `fut` of `round`, racing it against `deadline` if any.
+async fn sign_until<T>(
+    deadline: Option<Instant>,
+    round: &str,
+    fut: impl Future<Output = Outcome<T>>,
+) -> Outcome<T> {
+    match deadline {
+        None => fut.await,
+        Some(deadline) => match tokio::time::timeout_at(deadline.into(), fut).await {
+            Ok(res) => res,
+            Err(_) => throw!(
+                "SignTimeout",
+                format!("Deadline exceeded at round \"{}\"", round)
+            ),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use ed25519_dalek::Verifier;
+    use serde::{de::DeserializeOwned, Serialize};
+
+    use super::super::test_util::{group_of, keygen_for_test, keygen_parties};
+    use super::*;
+    use crate::{KeyStoreExt, KeygenOptions};
+
+    async fn sign_by(
+        keystores: &[KeyStore],
+        signers: &[u16],
+        msg_hash: &[u8],
+    ) -> Vec<Outcome<Signature>> {
+        let messenger = MemoryTransport::default();
+        let ses_arch = ses_arch_of(1, signers).unwrap();
+        let futs = signers.iter().map(|i| {
+            let (messenger, ses_arch) = (messenger.clone(), &ses_arch);
+            let keystore = &keystores[*i as usize - 1];
+            async move { algo_sign(&messenger, ses_arch, "", msg_hash, keystore).await }
+        });
+        futures::future::join_all(futs).await
+    }
+
+    #[tokio::test]
+    async fn non_contiguous_member_ids_keygen_and_sign() {
+        let ids = [3u16, 7, 12];
+        let key_arch = HashMap::from([(1u16, (2, group_of(&ids)))]);
+        let messenger = MemoryTransport::default();
+        let opts = KeygenOptions::default();
+        let keystores: Vec<KeyStore> = keygen_parties(&messenger, &key_arch, &ids, &opts)
+            .await
+            .into_iter()
+            .map(|res| res.unwrap())
+            .collect();
+        assert_eq!(keystores[0].member_ids(1).unwrap(), ids.to_vec());
+        let pk = keystores[0].group_public_key();
+
+        for signers in [[7u16, 12], [3, 12]] {
+            let messenger = MemoryTransport::default();
+            let ses_arch = ses_arch_of(1, &signers).unwrap();
+            let futs = signers.iter().map(|i| {
+                let (messenger, ses_arch) = (messenger.clone(), &ses_arch);
+                let keystore = &keystores[ids.iter().position(|j| j == i).unwrap()];
+                async move { algo_sign(&messenger, ses_arch, "", b"hash", keystore).await }
+            });
+            for sig in futures::future::join_all(futs).await {
+                verify_solana(&sig.unwrap(), &pk).unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn any_2_of_3_sign_for_group_key() {
+        let keystores = keygen_for_test(2, 3).await;
+        let pk_bytes = keystores[0].group_public_key_bytes();
+        let pk = ed25519_dalek::PublicKey::from_bytes(&pk_bytes).unwrap();
+        let msg_hash = b"hash of the transaction";
+
+        for signers in [[1, 2], [1, 3], [2, 3]] {
+            for sig in sign_by(&keystores, &signers, msg_hash).await {
+                let sig = sig.unwrap();
+                let sig = ed25519_dalek::Signature::from_bytes(&sig.to_bytes()).unwrap();
+                pk.verify(msg_hash, &sig).unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn every_signer_aggregates_the_same_bytes() {
+        let keystores = keygen_for_test(2, 3).await;
+        for signers in [&[1, 3][..], &[1, 2, 3]] {
+            let sigs: Vec<[u8; 64]> = sign_by(&keystores, signers, b"hash")
+                .await
+                .into_iter()
+                .map(|sig| sig.unwrap().to_bytes())
+                .collect();
+            assert!(sigs.iter().all(|sig| *sig == sigs[0]), "{:?}", signers);
+        }
+    }
+
+    #[tokio::test]
+    async fn streamed_message_signs_as_ed25519ph() {
+        let keystores = keygen_for_test(2, 3).await;
+        let pk_bytes = keystores[0].group_public_key_bytes();
+        let pk = ed25519_dalek::PublicKey::from_bytes(&pk_bytes).unwrap();
+        let msg = vec![7u8; 3 << 20];
+        let digest = prehash_message(msg.as_slice()).unwrap();
+        assert_eq!(digest.to_vec(), Sha512::digest(&msg).to_vec());
+
+        let messenger = MemoryTransport::default();
+        let ses_arch = HashMap::from([(1u16, group_of(&[1, 3]))]);
+        let sign = |keystore: &KeyStore| {
+            let (messenger, ses_arch, digest) = (messenger.clone(), &ses_arch, &digest);
+            let keystore = keystore.clone();
+            async move {
+                algo_sign_prehashed(&messenger, ses_arch, "", digest, b"ctx", &keystore)
+                    .await
+                    .unwrap()
+            }
+        };
+        let (sig, _) = tokio::join!(sign(&keystores[0]), sign(&keystores[2]));
+
+        let group_pk = keystores[0].group_public_key();
+        verify_prehashed(&sig, b"ctx", &group_pk).unwrap();
+        assert!(verify_prehashed(&sig, b"other ctx", &group_pk).is_err());
+        // The digest is not signed as a raw 64-byte message.
+        assert!(verify_signature(&sig, &group_pk).is_err());
+
+        let lib_sig = ed25519_dalek::Signature::from_bytes(&sig.to_bytes()).unwrap();
+        let prehashed = Sha512::new().chain(&msg);
+        pk.verify_prehashed(prehashed, Some(b"ctx"), &lib_sig)
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn empty_path_signs_for_the_group_key() {
+        let keystores = keygen_for_test(2, 3).await;
+        let group_pk = keystores[0].group_public_key();
+        let messenger = MemoryTransport::default();
+        let ses_arch = ses_arch_of(1, &[1, 3]).unwrap();
+        let sign = |keystore: &KeyStore| {
+            let (messenger, ses_arch) = (messenger.clone(), &ses_arch);
+            let keystore = keystore.clone();
+            async move { algo_sign(&messenger, ses_arch, "m", b"hash", &keystore).await }
+        };
+        let (sig, _) = tokio::join!(sign(&keystores[0]), sign(&keystores[2]));
+        verify_signature(&sig.unwrap(), &group_pk).unwrap();
+    }
+
+    #[tokio::test]
+    async fn only_soft_paths_are_signable() {
+        let keystores = keygen_for_test(2, 3).await;
+        let keystore = &keystores[0];
+        for path in ["", "m"] {
+            assert!(keystore.can_sign_path(path).unwrap(), "{}", path);
+        }
+        for path in ["m/0/1", "m/44'/501'", "m/0/1'"] {
+            let signable = keystore.can_sign_path(path).unwrap();
+            let soft = cfg!(feature = "hd") && !path.contains('\'');
+            assert_eq!(signable, soft, "{}", path);
+            let derived = derive_child(path, &keystore.group_public_key(), None);
+            assert_eq!(derived.is_ok(), signable, "{}", path);
+        }
+        if cfg!(feature = "hd") {
+            assert!(keystore.can_sign_path("m/x").is_err());
+        }
+    }
+
+    #[cfg(feature = "hd")]
+    #[tokio::test]
+    async fn hd_signature_verifies_against_child_key() {
+        let keystores = keygen_for_test(2, 3).await;
+        let main_pk = keystores[0].group_public_key();
+        let chain_code = [42u8; 32];
+        let (_, child_pk) = non_hardened_derive("m/0/1", &main_pk, &chain_code).unwrap();
+        let msg_hash = b"hash";
+
+        let messenger = MemoryTransport::default();
+        let ses_arch = HashMap::from([(
+            1u16,
+            HashSet::from([MpcAddr::new(1, 1), MpcAddr::new(1, 3)]),
+        )]);
+        let sign = |keystore: &KeyStore| {
+            let (messenger, ses_arch) = (messenger.clone(), &ses_arch);
+            let keystore = keystore.clone();
+            async move {
+                algo_sign_hd(
+                    &messenger,
+                    ses_arch,
+                    "m/0/1",
+                    &chain_code,
+                    msg_hash,
+                    &keystore,
+                )
+                .await
+                .unwrap()
+            }
+        };
+        let (sig, _) = tokio::join!(sign(&keystores[0]), sign(&keystores[2]));
+        verify_solana(&sig, &child_pk).unwrap();
+        assert!(verify_solana(&sig, &main_pk).is_err());
+    }
+
+    #[tokio::test]
+    async fn first_quorum_of_over_invited_signers_signs() {
+        let keystores = keygen_for_test(2, 4).await;
+        let group_pk = keystores[0].group_public_key();
+        let messenger = MemoryTransport::default();
+        let ses_arch = ses_arch_of(1, &[1, 2, 3, 4]).unwrap();
+        let deadline = Some(Instant::now() + std::time::Duration::from_secs(10));
+        // 3 shows up after the quorum of 1 and 2 has formed, 4 never does.
+        let futs = [(1, 0), (2, 0), (3, 300)].map(|(i, delay)| {
+            let (messenger, ses_arch) = (messenger.clone(), &ses_arch);
+            let keystore = &keystores[i - 1];
+            async move {
+                tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
+                algo_sign_quorum(&messenger, ses_arch, "", b"hash", keystore, deadline).await
+            }
+        });
+        let sigs: Vec<Signature> = futures::future::join_all(futs)
+            .await
+            .into_iter()
+            .map(|sig| sig.unwrap())
+            .collect();
+        for sig in sigs.iter() {
+            assert_eq!(sig.to_bytes(), sigs[0].to_bytes());
+            verify_signature(sig, &group_pk).unwrap();
+        }
+        let quorum: Vec<MpcAddr> = messenger
+            .receive("sign_quorum", MpcAddr::new(1, 1), MpcAddr::bcast_id())
+            .await
+            .unwrap();
+        assert_eq!(quorum, vec![MpcAddr::new(1, 1), MpcAddr::new(1, 2)]);
+
+        // Nobody else shows up for the coordinator.
+        let lonely = MemoryTransport::default();
+        let deadline = Some(Instant::now() + std::time::Duration::from_millis(200));
+        let res = algo_sign_quorum(&lonely, &ses_arch, "", b"hash", &keystores[0], deadline).await;
+        assert_eq!(res.unwrap_err().get_name(), "SignTimeout");
+    }
+
+    #[tokio::test]
+    async fn n_of_n_group_signs_with_every_member() {
+        let keystores = keygen_for_test(3, 3).await;
+        assert_eq!(keystores[0].th(1).unwrap(), 3);
+        let pk = keystores[0].group_public_key();
+        for sig in sign_by(&keystores, &[1, 2, 3], b"hash").await {
+            verify_solana(&sig.unwrap(), &pk).unwrap();
+        }
+        let res = sign_by(&keystores, &[1, 3], b"hash").await;
+        assert_eq!(res[0].as_ref().unwrap_err().get_name(), "InvalidSigners");
+    }
+
+    #[tokio::test]
+    async fn n_of_n_quorum_is_every_member() {
+        for n in [2u16, 3] {
+            let keystores = keygen_for_test(n as usize, n).await;
+            let pk = keystores[0].group_public_key();
+            let messenger = MemoryTransport::default();
+            let ids: Vec<u16> = (1..=n).collect();
+            let ses_arch = ses_arch_of(1, &ids).unwrap();
+            let deadline = Some(Instant::now() + std::time::Duration::from_secs(10));
+            let futs = keystores.iter().map(|keystore| {
+                let (messenger, ses_arch) = (messenger.clone(), &ses_arch);
+                async move {
+                    algo_sign_quorum(&messenger, ses_arch, "", b"hash", keystore, deadline).await
+                }
+            });
+            for sig in futures::future::join_all(futs).await {
+                verify_solana(&sig.unwrap(), &pk).unwrap();
+            }
+            let quorum: Vec<MpcAddr> = messenger
+                .receive("sign_quorum", MpcAddr::new(1, 1), MpcAddr::bcast_id())
+                .await
+                .unwrap();
+            assert_eq!(quorum.len(), n as usize);
+        }
+    }
+
+    async fn sign_with_mode_by(
+        keystores: &[KeyStore],
+        signers: &[u16],
+        msg_hash: &[u8],
+        nonce_mode: &NonceMode,
+    ) -> Vec<[u8; 64]> {
+        let messenger = MemoryTransport::default();
+        let ses_arch = ses_arch_of(1, signers).unwrap();
+        let futs = signers.iter().map(|i| {
+            let (messenger, ses_arch) = (messenger.clone(), &ses_arch);
+            let keystore = &keystores[*i as usize - 1];
+            async move {
+                algo_sign_with_nonce_mode(&messenger, ses_arch, "", msg_hash, keystore, nonce_mode)
+                    .await
+            }
+        });
+        let sigs = futures::future::join_all(futs).await;
+        sigs.into_iter()
+            .map(|sig| sig.unwrap().to_bytes())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn deterministic_nonces_reproduce_the_signature() {
+        let keystores = keygen_for_test(2, 3).await;
+        let msg_hash = b"hash of the transaction";
+        let mode = NonceMode::Deterministic {
+            session: b"session 1".to_vec(),
+        };
+        let first = sign_with_mode_by(&keystores, &[1, 3], msg_hash, &mode).await;
+        let again = sign_with_mode_by(&keystores, &[1, 3], msg_hash, &mode).await;
+        assert_eq!(first, again);
+
+        let other = NonceMode::Deterministic {
+            session: b"session 2".to_vec(),
+        };
+        let other = sign_with_mode_by(&keystores, &[1, 3], msg_hash, &other).await;
+        assert_ne!(first[0], other[0]);
+        let random = sign_with_mode_by(&keystores, &[1, 3], msg_hash, &NonceMode::Random).await;
+        let random_again =
+            sign_with_mode_by(&keystores, &[1, 3], msg_hash, &NonceMode::default()).await;
+        assert_ne!(random[0], random_again[0]);
+    }
+
+    #[test]
+    fn derived_nonces_change_with_the_signing_instance() {
+        let x_i = Scalar::from(7u8);
+        let me = MpcAddr::new(2, 1);
+        let g = constants::ED25519_BASEPOINT_POINT;
+        let child_pk = g * Scalar::from(11u8);
+        let signers = [MpcAddr::new(1, 1), MpcAddr::new(1, 3), me];
+        let nonce_com = |child_pk: &EdwardsPoint, signers: &[MpcAddr]| {
+            let nonce = derive_nonce_pair(&x_i, b"msg", me, b"session", child_pk, signers);
+            let com = nonce.unwrap().commitment();
+            (com.g_d, com.g_e)
+        };
+        let first = nonce_com(&child_pk, &signers);
+        assert_eq!(first, nonce_com(&child_pk, &[me, signers[1], signers[0]]));
+        // Another drv_path tweaks only the pivot group's share, not mine.
+        assert_ne!(first, nonce_com(&(child_pk + g), &signers));
+        assert_ne!(first, nonce_com(&child_pk, &[signers[0], me]));
+        assert_ne!(
+            first,
+            nonce_com(&child_pk, &[MpcAddr::new(1, 2), signers[1], me])
+        );
+    }
+
+    #[tokio::test]
+    async fn sign_contexts_separate_the_signatures() {
+        let keystores = keygen_for_test(2, 3).await;
+        let pk = keystores[0].pk().unwrap();
+        let msg_hash = b"hash of the transaction";
+        let ses_arch = ses_arch_of(1, &[1, 2]).unwrap();
+        let sign_under = |ctx: &'static [u8]| {
+            let messenger = MemoryTransport::default();
+            let futs =
+                keystores[..2].iter().map(|ks| {
+                    let (messenger, ses_arch) = (messenger.clone(), &ses_arch);
+                    async move {
+                        algo_sign_with_context(&messenger, ses_arch, "", msg_hash, ctx, ks).await
+                    }
+                });
+            futures::future::join_all(futs)
+        };
+        let mainnet = sign_under(b"mainnet").await.remove(0).unwrap();
+        let testnet = sign_under(b"testnet").await.remove(0).unwrap();
+        assert_ne!(mainnet.to_bytes(), testnet.to_bytes());
+
+        verify_with_context(&mainnet, b"mainnet", &pk).unwrap();
+        verify_with_context(&testnet, b"testnet", &pk).unwrap();
+        assert!(verify_with_context(&mainnet, b"testnet", &pk).is_err());
+        assert!(mainnet.verify(msg_hash, &pk).is_err());
+        assert_eq!(
+            ed25519ctx_dom(&[]).unwrap_err().get_name(),
+            "InvalidParameters"
+        );
+    }
+
+    #[tokio::test]
+    async fn too_few_signers_fail() {
+        let keystores = keygen_for_test(2, 3).await;
+        let res = sign_by(&keystores, &[2], b"hash").await;
+        assert_eq!(res[0].as_ref().unwrap_err().get_name(), "InvalidSigners");
+    }
+
+    /// Sends `forged` instead of whatever its holder sends on `topic`.
+    struct TamperingMessenger {
+        inner: MemoryTransport,
+        topic: &'static str,
+        forged: Vec<u8>,
+    }
+
+    #[async_trait]
+    impl Messenger for TamperingMessenger {
+        type E = Box<Exception>;
+
+        async fn send<T>(&self, topic: &str, src: MpcAddr, dst: MpcAddr, obj: &T) -> Outcome<()>
+        where
+            T: Serialize + DeserializeOwned + Send + Sync,
+        {
+            match topic == self.topic {
+                true => {
+                    let forged: T =
+                        serde_pickle::from_slice(&self.forged, Default::default()).catch_()?;
+                    self.inner.send(topic, src, dst, &forged).await
+                }
+                false => self.inner.send(topic, src, dst, obj).await,
+            }
+        }
+
+        async fn receive<T>(&self, topic: &str, src: MpcAddr, dst: MpcAddr) -> Outcome<T>
+        where
+            T: Serialize + DeserializeOwned + Send + Sync,
+        {
+            self.inner.receive(topic, src, dst).await
+        }
+
+        async fn scatter<T>(
+            &self,
+            topic: &str,
+            src: MpcAddr,
+            dsts: &HashSet<MpcAddr>,
+            obj: &T,
+        ) -> Outcome<()>
+        where
+            T: Serialize + DeserializeOwned + Send + Sync,
+        {
+            self.inner.scatter(topic, src, dsts, obj).await
+        }
+
+        async fn gather<T>(
+            &self,
+            topic: &str,
+            srcs: &HashSet<MpcAddr>,
+            dst: MpcAddr,
+        ) -> Outcome<HashMap<MpcAddr, T>>
+        where
+            T: Serialize + DeserializeOwned + Send + Sync,
+        {
+            self.inner.gather(topic, srcs, dst).await
+        }
+    }
+
+    #[tokio::test]
+    async fn tampered_response_is_pinned_on_its_signer() {
+        let keystores = keygen_for_test(2, 3).await;
+        let messenger = MemoryTransport::default();
+        let tampering = TamperingMessenger {
+            inner: messenger.clone(),
+            topic: "sign_resp",
+            forged: serde_pickle::to_vec(&Scalar::random(&mut OsRng), Default::default()).unwrap(),
+        };
+        let ses_arch = ses_arch_of(1, &[1, 2, 3]).unwrap();
+        let (res1, res2, res3) = tokio::join!(
+            algo_sign(&messenger, &ses_arch, "", b"hash", &keystores[0]),
+            algo_sign(&messenger, &ses_arch, "", b"hash", &keystores[1]),
+            algo_sign(&tampering, &ses_arch, "", b"hash", &keystores[2]),
+        );
+        for res in [res1, res2, res3] {
+            let err = res.unwrap_err();
+            assert_eq!(err.get_name(), "InvalidSignerResponse");
+            let culprit = format!("{:?}", vec![MpcAddr::new(1, 3)]);
+            assert!(err.get_context().unwrap().contains(&culprit));
+        }
+    }
+
+    #[tokio::test]
+    async fn custom_challenge_is_used_by_signers_and_verifier() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let keystores = keygen_for_test(2, 3).await;
+        let pk = keystores[0].group_public_key();
+        let chain_code = [7u8; 32];
+        // e.g. a chain hashing $H(M, R, A)$ with SHA-256
+        let calls = AtomicUsize::new(0);
+        let hasher = |_: &[u8], msg: &[u8], com: &EdwardsPoint, pk: &EdwardsPoint| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            let mut ha = sha2::Sha256::new();
+            ha.update(msg);
+            ha.update(com.compress().to_bytes());
+            ha.update(pk.compress().to_bytes());
+            Scalar::from_bytes_mod_order(ha.finalize().into())
+        };
+
+        let messenger = MemoryTransport::default();
+        let ses_arch = ses_arch_of(1, &[1, 3]).unwrap();
+        let sign = |keystore: &KeyStore| {
+            let (messenger, ses_arch, hasher) = (messenger.clone(), &ses_arch, &hasher);
+            let (chain_code, keystore) = (&chain_code, keystore.clone());
+            async move {
+                algo_sign_with_challenge(
+                    &messenger, ses_arch, "", chain_code, hasher, b"hash", &keystore,
+                )
+                .await
+                .unwrap()
+            }
+        };
+        let (sig, _) = tokio::join!(sign(&keystores[0]), sign(&keystores[2]));
+        assert!(calls.load(Ordering::SeqCst) > 0);
+
+        #[cfg(not(feature = "hd"))]
+        {
+            let messenger = MemoryTransport::default();
+            let res = algo_sign_with_challenge(
+                &messenger,
+                &ses_arch,
+                "m/0",
+                &chain_code,
+                &hasher,
+                b"hash",
+                &keystores[0],
+            );
+            assert_eq!(res.await.unwrap_err().get_name(), "HdUnsupported");
+        }
+
+        sig.verify_with(&hasher, &[], b"hash", &pk).unwrap();
+        assert!(sig.verify(b"hash", &pk).is_err());
+        assert!(sig
+            .verify_with(&Ed25519Challenge, &[], b"hash", &pk)
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn any_subset_of_3_of_5_signs_for_the_group_key() {
+        let keystores = keygen_for_test(3, 5).await;
+        let pk = keystores[0].group_public_key();
+        for signers in [&[1, 2, 3][..], &[2, 4, 5], &[1, 3, 5], &[1, 2, 3, 4, 5]] {
+            for sig in sign_by(&keystores, signers, b"hash").await {
+                sig.unwrap().verify(b"hash", &pk).unwrap();
+            }
+        }
+
+        for signers in [&[1, 1, 2][..], &[0, 1, 2]] {
+            let err = ses_arch_of(1, signers).unwrap_err();
+            assert_eq!(err.get_name(), "InvalidSigners");
+        }
+        let mut ses_arch = ses_arch_of(1, &[1, 2, 3]).unwrap();
+        let messenger = MemoryTransport::default();
+        ses_arch.get_mut(&1).unwrap().insert(MpcAddr::new(2, 4));
+        let res = algo_sign(&messenger, &ses_arch, "", b"hash", &keystores[0]).await;
+        assert_eq!(res.unwrap_err().get_name(), "InvalidSigners");
+        let ses_arch = ses_arch_of(1, &[2, 3, 4]).unwrap();
+        let res = algo_sign(&messenger, &ses_arch, "", b"hash", &keystores[0]).await;
+        assert_eq!(res.unwrap_err().get_name(), "InvalidSigners");
+    }
 }
@@ -0,0 +1,68 @@
+use std::collections::{HashMap, HashSet};
+
+use libexception::*;
+use mpc_spec::*;
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+
+use crate::{algo_keygen_with_options, algo_keygen_with_rng, KeyStore, KeygenOptions};
+
+/// The members of group 1 with the given member ids.
+pub(crate) fn group_of(ids: &[u16]) -> HashSet<MpcAddr> {
+    ids.iter().map(|i| MpcAddr::new(1, *i)).collect()
+}
+
+/// A single group 1 of members 1..=n_members with threshold `th`.
+pub(crate) fn key_arch_of(th: usize, n_members: u16) -> HashMap<u16, (usize, HashSet<MpcAddr>)> {
+    let ids: Vec<u16> = (1..=n_members).collect();
+    HashMap::from([(1, (th, group_of(&ids)))])
+}
+
+/// Run `algo_keygen_with_options` concurrently for the members `ids` of
+/// group 1, returning their outcomes in the order of `ids`.
+pub(crate) async fn keygen_parties(
+    messenger: &MemoryTransport,
+    key_arch: &HashMap<u16, (usize, HashSet<MpcAddr>)>,
+    ids: &[u16],
+    opts: &KeygenOptions,
+) -> Vec<Outcome<KeyStore>> {
+    let futs = ids.iter().map(|i| async move {
+        let whoami = [MpcAddr::new(1, *i)];
+        algo_keygen_with_options(messenger, key_arch, &whoami, "test", opts).await
+    });
+    futures::future::join_all(futs).await
+}
+
+/// Run `algo_keygen` for members 1..=n of group 1, returning their keystores in order.
+pub(crate) async fn keygen_for_test(th: usize, n: u16) -> Vec<KeyStore> {
+    let messenger = MemoryTransport::default();
+    let ids: Vec<u16> = (1..=n).collect();
+    keygen_parties(
+        &messenger,
+        &key_arch_of(th, n),
+        &ids,
+        &KeygenOptions::default(),
+    )
+    .await
+    .into_iter()
+    .map(|res| res.unwrap())
+    .collect()
+}
+
+/// Like `keygen_for_test`, but member `i` draws from `ChaCha20Rng` seeded
+/// with `i`, so the keystores are the same on every run.
+pub(crate) async fn keygen_seeded_for_test(th: usize, n: u16) -> Vec<KeyStore> {
+    let messenger = MemoryTransport::default();
+    let (key_arch, opts) = (key_arch_of(th, n), KeygenOptions::default());
+    let futs = (1..=n).map(|i| {
+        let (messenger, key_arch, opts) = (&messenger, &key_arch, &opts);
+        async move {
+            let mut rng = ChaCha20Rng::seed_from_u64(i as u64);
+            let whoami = [MpcAddr::new(1, i)];
+            algo_keygen_with_rng(messenger, key_arch, &whoami, "test", opts, &mut rng)
+                .await
+                .unwrap()
+        }
+    });
+    futures::future::join_all(futs).await
+}
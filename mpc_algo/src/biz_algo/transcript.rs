@@ -0,0 +1,163 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use curve25519_dalek::edwards::EdwardsPoint;
+use curve25519_dalek::traits::Identity;
+use libexception::*;
+use mpc_spec::*;
+use serde::{Deserialize, Serialize};
+
+use super::{validate_commitments, validate_group, KeyGenDKGProposedCommitment, KeyGenZKP};
+use super::{KeyStore, KeyStoreExt};
+
+/// Thrown by `verify_public_transcript` when a ceremony does not check out.
+/// The context names the group, and the dealers if they are to blame.
+pub const TranscriptVerifyFailed: &str = "TranscriptVerifyFailed";
+
+/// What a keygen published, without any secret, for auditors to check with
+/// `verify_public_transcript`: each dealer's commitment along with its proof
+/// of knowledge, and the resulting group public key.
+/// The maps are ordered, so that every member publishes the same bytes.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PublicTranscript {
+    pub group_public_key: EdwardsPoint,
+    pub groups: BTreeMap<u16, GroupTranscript>,
+}
+
+/// The part of a `PublicTranscript` dealt by the members of one group.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GroupTranscript {
+    pub th: u16,
+    pub n_members: u16,
+    /// The round-1 commitment of each member, as every member validated it.
+    pub commitments: BTreeMap<MpcAddr, KeyGenDKGProposedCommitment>,
+}
+
+/// See `KeyStoreExt::public_transcript`.
+pub(crate) fn public_transcript_of(keystore: &KeyStore) -> Outcome<PublicTranscript> {
+    let mut groups = BTreeMap::new();
+    for (gid, vss_com_dict) in keystore.vss_com_grid.iter() {
+        let zkp_dict = keystore.vss_zkp_grid.get(gid).ifnone(
+            "IncompleteTranscript",
+            format!("Group {} keeps no keygen proofs, e.g. it was reshared", gid),
+        )?;
+        let mut commitments = BTreeMap::new();
+        for (id, vss_com) in vss_com_dict.iter() {
+            let (g_k_i, sigma) = zkp_dict.get(id).ifnone(
+                "IncompleteTranscript",
+                format!("No keygen proof of dealer {}", id),
+            )?;
+            let com = KeyGenDKGProposedCommitment {
+                shares_commitment: vss_com.clone(),
+                zkp: KeyGenZKP {
+                    g_k_i: *g_k_i,
+                    sigma: *sigma,
+                },
+            };
+            commitments.insert(*id, com);
+        }
+        let group = GroupTranscript {
+            th: keystore.threshold(*gid)?,
+            n_members: keystore.n_members(*gid)?,
+            commitments,
+        };
+        groups.insert(*gid, group);
+    }
+    Ok(PublicTranscript {
+        group_public_key: keystore.group_public_key(),
+        groups,
+    })
+}
+
+/// Re-check a published keygen of `context`: every group is well formed, every
+/// member dealt a commitment of its threshold with a valid proof of knowledge
+/// of its secret, and the group public key is the sum of those secrets.
+/// Throws `TranscriptVerifyFailed` otherwise.
+pub fn verify_public_transcript(transcript: &PublicTranscript, context: &str) -> Outcome<()> {
+    const TVF: &str = TranscriptVerifyFailed;
+    assert_throw!(!transcript.groups.is_empty(), TVF, "No group was dealt");
+    let mut pk = EdwardsPoint::identity();
+    for (gid, group) in transcript.groups.iter() {
+        let members: HashSet<MpcAddr> = group.commitments.keys().cloned().collect();
+        assert_throw!(
+            members.len() == group.n_members as usize,
+            TVF,
+            format!(
+                "Group {} claims n_members={} but has {} commitments",
+                gid,
+                group.n_members,
+                members.len()
+            )
+        );
+        let th = group.th as usize;
+        validate_group(*gid, th, &members).catch(TVF, format!("Malformed group {}", gid))?;
+        let proposed_com_dict: HashMap<MpcAddr, KeyGenDKGProposedCommitment> = group
+            .commitments
+            .iter()
+            .map(|(id, com)| (*id, com.clone()))
+            .collect();
+        let vss_com_dict = validate_commitments(&proposed_com_dict, th, &members, context)
+            .catch(TVF, format!("Bad commitments in group {}", gid))?;
+        for vss_com in vss_com_dict.values() {
+            pk += vss_com[0];
+        }
+    }
+    assert_throw!(
+        pk == transcript.group_public_key,
+        TVF,
+        "The group public key is not the sum of the dealt secrets"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use curve25519_dalek::scalar::Scalar;
+
+    use super::super::test_util::keygen_for_test;
+    use super::*;
+
+    #[tokio::test]
+    async fn tampered_transcript_fails() {
+        let keystores = keygen_for_test(2, 3).await;
+        let transcript = keystores[0].public_transcript().unwrap();
+        assert_eq!(transcript.groups[&1].th, 2);
+        assert_eq!(transcript.groups[&1].n_members, 3);
+        verify_public_transcript(&transcript, "test").unwrap();
+
+        // Every member publishes the same transcript, which survives serde.
+        let json = serde_json::to_string(&transcript).unwrap();
+        for ks in keystores.iter() {
+            let other = serde_json::to_string(&ks.public_transcript().unwrap()).unwrap();
+            assert_eq!(other, json);
+        }
+        let published: PublicTranscript = serde_json::from_str(&json).unwrap();
+        verify_public_transcript(&published, "test").unwrap();
+
+        let fails = |transcript: &PublicTranscript, context: &str| {
+            let err = verify_public_transcript(transcript, context).unwrap_err();
+            assert_eq!(err.get_name(), TranscriptVerifyFailed);
+            err.to_string()
+        };
+        fails(&transcript, "another ceremony");
+
+        let dealer = MpcAddr::new(1, 2);
+        let mut tampered = transcript.clone();
+        let com = tampered.groups.get_mut(&1).unwrap();
+        com.commitments.get_mut(&dealer).unwrap().zkp.sigma += Scalar::one();
+        let msg = fails(&tampered, "test");
+        assert!(msg.contains(&format!("{:?}", dealer)), "{}", msg);
+
+        let mut tampered = transcript.clone();
+        tampered.group_public_key += tampered.group_public_key;
+        fails(&tampered, "test");
+
+        let mut tampered = transcript;
+        tampered.groups.get_mut(&1).unwrap().th = 3;
+        fails(&tampered, "test");
+
+        let mut reshared = keystores[0].clone();
+        reshared.vss_zkp_grid.clear();
+        let err = reshared.public_transcript().unwrap_err();
+        assert_eq!(err.get_name(), "IncompleteTranscript");
+    }
+}
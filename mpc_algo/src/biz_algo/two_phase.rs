@@ -0,0 +1,265 @@
+use curve25519_dalek::{constants, edwards::EdwardsPoint, scalar::Scalar};
+use libexception::*;
+use mpc_spec::*;
+use rand::rngs::OsRng;
+use std::collections::{HashMap, HashSet};
+use zeroize::Zeroize;
+
+use super::{validate_ses_arch, KeyStore};
+use crate::frost::{
+    agg_nonce_com, eval_xi_com, gen_rho_i, is_valid_response, lagrange_lambda, sign_and_respond,
+    sign_preprocess, SigningCommitmentPair, SigningNoncePair,
+};
+
+/// What `sign_round1` leaves for `sign_round2`: the secret nonces of my
+/// shards, and the aggregate nonce commitment $R$ of the signers.
+///
+/// It is neither `Clone` nor serializable, and `sign_round2` consumes it, so
+/// its nonces answer a single challenge. They are zeroized when it is dropped.
+pub struct SignRound1 {
+    whoami_asc: Vec<MpcAddr>,
+    nonces: HashMap<MpcAddr, SigningNoncePair>,
+    com_dict: HashMap<MpcAddr, SigningCommitmentPair>,
+    rho_dict: HashMap<MpcAddr, Scalar>,
+    r: EdwardsPoint,
+}
+
+impl SignRound1 {
+    /// The aggregate nonce commitment $R$, the same at every signer.
+    pub fn r(&self) -> EdwardsPoint {
+        self.r
+    }
+}
+
+impl Drop for SignRound1 {
+    fn drop(&mut self) {
+        for nonce in self.nonces.values_mut() {
+            nonce.zeroize();
+        }
+    }
+}
+
+/// Round 1 of `algo_sign` with `drv_path` `""`, stopping at the aggregate
+/// commitment $R$ instead of hashing the challenge, for protocols that build
+/// on FROST, e.g. adaptor signatures. Each signer in `ses_arch` broadcasts
+/// its nonce commitments $(dG, eG)$, and binds them to `msg_hash` as
+/// `algo_sign` does.
+///
+/// Then every signer computes the same challenge $c$ on its own and calls
+/// `sign_round2`, which returns the $s$ with $sG = R + cA$, $A$ being
+/// `keystore.pk()`:
+/// - `Ed25519Challenge.challenge(&[], msg_hash, &r, &a)` makes $(R, s)$ a
+///   standard Ed25519 signature of `msg_hash`, as `algo_sign` would;
+/// - for an adaptor point $T = tG$, hashing $R + T$ instead makes $s$ a
+///   pre-signature, which $(R + T, s + t)$ completes.
+///
+/// NOTE: `sign_round2` signs any $c$ it is given. A signer must derive $c$
+/// itself from a message it approves and from `r` of its own round 1, never
+/// take it from a coordinator: chosen challenges over concurrent sessions
+/// forge signatures of messages nobody signed.
+pub async fn sign_round1(
+    messenger: &impl Messenger,
+    ses_arch: &HashMap<u16, HashSet<MpcAddr>>,
+    msg_hash: &[u8],
+    keystore: &KeyStore,
+) -> Outcome<SignRound1> {
+    validate_ses_arch(ses_arch, keystore)?;
+    let bcast_id = MpcAddr::bcast_id();
+    let mut whoami_asc: Vec<MpcAddr> = keystore.ids.iter().cloned().collect();
+    whoami_asc.sort();
+
+    let mut nonces: HashMap<MpcAddr, SigningNoncePair> = HashMap::new();
+    for my_id in whoami_asc.iter() {
+        let (nonce_com, nonce) = sign_preprocess(&mut OsRng).catch_()?;
+        nonces.insert(*my_id, nonce);
+        messenger
+            .send("nonce_com", *my_id, bcast_id, &nonce_com)
+            .await
+            .catch_()?;
+    }
+
+    let mut com_dict: HashMap<MpcAddr, SigningCommitmentPair> = HashMap::new();
+    for group_members in ses_arch.values() {
+        let obj = messenger
+            .gather("nonce_com", group_members, bcast_id)
+            .await
+            .catch_()?;
+        com_dict.extend(obj);
+    }
+    let mut rho_dict: HashMap<MpcAddr, Scalar> = HashMap::new();
+    for j in com_dict.keys() {
+        rho_dict.insert(*j, gen_rho_i(*j, msg_hash, &com_dict));
+    }
+    let r = agg_nonce_com(&com_dict, &rho_dict).catch_()?;
+    Ok(SignRound1 {
+        whoami_asc,
+        nonces,
+        com_dict,
+        rho_dict,
+        r,
+    })
+}
+
+/// Round 2 of the signing that `sign_round1` began: each signer responds to
+/// `challenge`, then validates and sums the responses of all into the $s$
+/// with $sG = R + cA$. `r` must be `round1.r()`, or `RoundMismatch` is thrown
+/// before any nonce is used. A response that does not match its signer's
+/// share and nonces throws `InvalidSignerResponse` naming the signers.
+pub async fn sign_round2(
+    messenger: &impl Messenger,
+    ses_arch: &HashMap<u16, HashSet<MpcAddr>>,
+    round1: SignRound1,
+    r: &EdwardsPoint,
+    challenge: &Scalar,
+    keystore: &KeyStore,
+) -> Outcome<Scalar> {
+    assert_throw!(
+        *r == round1.r,
+        "RoundMismatch",
+        "R is not the aggregate commitment of this round 1"
+    );
+    let bcast_id = MpcAddr::bcast_id();
+    for my_id in round1.whoami_asc.iter() {
+        let nonce = round1.nonces.get(my_id).ifnone_()?;
+        let x_i = keystore.xi_pergroup.get(&my_id.group_id()).ifnone_()?;
+        let group_members = ses_arch.get(&my_id.group_id()).ifnone_()?;
+        let resp = sign_and_respond(
+            *my_id,
+            x_i,
+            &round1.rho_dict,
+            nonce,
+            group_members,
+            challenge,
+        )
+        .catch_()?;
+        messenger
+            .send("sign_resp", *my_id, bcast_id, &resp)
+            .await
+            .catch_()?;
+    }
+
+    let mut s = Scalar::zero();
+    let mut invalid_ids: Vec<MpcAddr> = Vec::new();
+    for (gid, group_members) in ses_arch.iter() {
+        let resp_dict: HashMap<MpcAddr, Scalar> = messenger
+            .gather("sign_resp", group_members, bcast_id)
+            .await
+            .catch_()?;
+        let vss_com_dict = keystore.vss_com_grid.get(gid).ifnone_()?;
+        for (j, resp) in resp_dict.iter() {
+            let xjg = eval_xi_com(*j, vss_com_dict);
+            let lam_j = lagrange_lambda(*j, group_members).catch_()?;
+            let nonce_com = round1.com_dict.get(j).ifnone_()?;
+            let com = nonce_com.g_d + (nonce_com.g_e * round1.rho_dict[j]);
+            if !is_valid_response(resp, &xjg, &lam_j, &com, challenge) {
+                invalid_ids.push(*j);
+            }
+            s += resp;
+        }
+    }
+    invalid_ids.sort();
+    assert_throw!(
+        invalid_ids.is_empty(),
+        "InvalidSignerResponse",
+        format!(
+            "Responses of signers {:?} do not match their shares and nonces",
+            invalid_ids
+        )
+    );
+    let pk = keystore.pk().catch_()?;
+    assert_throw!(
+        &constants::ED25519_BASEPOINT_TABLE * &s == round1.r + pk * challenge,
+        "InvalidSignature",
+        "Most probably lack of signers"
+    );
+    Ok(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_util::keygen_for_test;
+    use super::*;
+    use crate::{ses_arch_of, verify_signature, ChallengeHash, Ed25519Challenge, Signature};
+
+    /// Run both rounds for `signers`, with the challenge `challenge_of(R)`.
+    async fn sign_in_two_phases(
+        keystores: &[KeyStore],
+        signers: &[u16],
+        msg_hash: &[u8],
+        challenge_of: impl Fn(&EdwardsPoint) -> Scalar + Copy,
+    ) -> Vec<(EdwardsPoint, Scalar)> {
+        let messenger = MemoryTransport::default();
+        let ses_arch = ses_arch_of(1, signers).unwrap();
+        let futs = signers.iter().map(|i| {
+            let (messenger, ses_arch) = (&messenger, &ses_arch);
+            let keystore = &keystores[*i as usize - 1];
+            async move {
+                let round1 = sign_round1(messenger, ses_arch, msg_hash, keystore)
+                    .await
+                    .unwrap();
+                let r = round1.r();
+                let c = challenge_of(&r);
+                let s = sign_round2(messenger, ses_arch, round1, &r, &c, keystore)
+                    .await
+                    .unwrap();
+                (r, s)
+            }
+        });
+        futures::future::join_all(futs).await
+    }
+
+    #[tokio::test]
+    async fn two_phases_make_a_standard_signature() {
+        let keystores = keygen_for_test(2, 3).await;
+        let pk = keystores[0].pk().unwrap();
+        let msg = b"hash";
+        let challenge_of = |r: &EdwardsPoint| Ed25519Challenge.challenge(&[], msg, r, &pk);
+        for (r, s) in sign_in_two_phases(&keystores, &[1, 3], msg, challenge_of).await {
+            let sig = Signature {
+                r,
+                s,
+                hash: msg.to_vec(),
+            };
+            verify_signature(&sig, &pk).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn adaptor_point_completes_a_pre_signature() {
+        let keystores = keygen_for_test(2, 3).await;
+        let pk = keystores[0].pk().unwrap();
+        let msg = b"hash";
+        let t = Scalar::random(&mut OsRng);
+        let big_t = &constants::ED25519_BASEPOINT_TABLE * &t;
+        let challenge_of =
+            |r: &EdwardsPoint| Ed25519Challenge.challenge(&[], msg, &(r + big_t), &pk);
+        for (r, s) in sign_in_two_phases(&keystores, &[2, 3], msg, challenge_of).await {
+            let pre_sig = Signature {
+                r: r + big_t,
+                s,
+                hash: msg.to_vec(),
+            };
+            assert!(verify_signature(&pre_sig, &pk).is_err());
+            let sig = Signature {
+                s: s + t,
+                ..pre_sig
+            };
+            verify_signature(&sig, &pk).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn round2_refuses_another_r() {
+        let keystores = keygen_for_test(2, 3).await;
+        let messenger = MemoryTransport::default();
+        let ses_arch = ses_arch_of(1, &[1, 2]).unwrap();
+        let round1 = sign_round1(&messenger, &ses_arch, b"hash", &keystores[0]);
+        let other = sign_round1(&messenger, &ses_arch, b"hash", &keystores[1]);
+        let (round1, _) = tokio::join!(round1, other);
+        let round1 = round1.unwrap();
+        let r = round1.r() + round1.r();
+        let c = Scalar::one();
+        let res = sign_round2(&messenger, &ses_arch, round1, &r, &c, &keystores[0]).await;
+        assert_eq!(res.unwrap_err().get_name(), "RoundMismatch");
+    }
+}
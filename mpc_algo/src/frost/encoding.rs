@@ -0,0 +1,171 @@
+use curve25519_dalek::{
+    edwards::{CompressedEdwardsY, EdwardsPoint},
+    scalar::Scalar,
+};
+use libexception::*;
+use mpc_spec::MpcAddr;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::{KeyGenDKGProposedCommitment, KeyGenZKP};
+
+/// Thrown for bytes from a peer that are not the canonical encoding of a
+/// scalar or a point. The context names the peer.
+pub const NonCanonicalEncoding: &str = "NonCanonicalEncoding";
+
+/// The scalar of the 32 little-endian bytes `peer` sent, which must be below
+/// $\ell$. Unlike `Scalar::from_bytes_mod_order`, it doesn't quietly reduce
+/// an out-of-range encoding into some other peer's valid-looking value.
+pub fn decode_scalar_strict(bytes: &[u8], peer: MpcAddr) -> Outcome<Scalar> {
+    let arr: [u8; 32] = bytes.try_into().ok().ifnone(
+        NonCanonicalEncoding,
+        format!(
+            "Party {} sent a scalar of {} bytes, expected 32",
+            peer,
+            bytes.len()
+        ),
+    )?;
+    Scalar::from_canonical_bytes(arr).ifnone(
+        NonCanonicalEncoding,
+        format!("Party {} sent a scalar that is not reduced mod l", peer),
+    )
+}
+
+/// The point of the 32 compressed bytes `peer` sent, which must decompress
+/// and be the encoding the point compresses back to, i.e. with $y < p$.
+pub fn decode_point_strict(bytes: &[u8], peer: MpcAddr) -> Outcome<EdwardsPoint> {
+    let arr: [u8; 32] = bytes.try_into().ok().ifnone(
+        NonCanonicalEncoding,
+        format!(
+            "Party {} sent a point of {} bytes, expected 32",
+            peer,
+            bytes.len()
+        ),
+    )?;
+    CompressedEdwardsY(arr)
+        .decompress()
+        .filter(|point| point.compress().to_bytes() == arr)
+        .ifnone(
+            NonCanonicalEncoding,
+            format!("Party {} sent no canonical point encoding", peer),
+        )
+}
+
+/// A `KeyGenDKGProposedCommitment` as it crosses the network, its points
+/// still compressed: dalek's serde decompresses any $y$, canonical or not.
+/// It serializes exactly as the commitment does, so either is received as
+/// the other.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct WireDKGCommitment {
+    pub shares_commitment: Vec<CompressedEdwardsY>,
+    pub zkp: WireZKP,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct WireZKP {
+    pub g_k_i: CompressedEdwardsY,
+    pub sigma: Scalar,
+}
+
+impl From<&KeyGenDKGProposedCommitment> for WireDKGCommitment {
+    fn from(com: &KeyGenDKGProposedCommitment) -> Self {
+        WireDKGCommitment {
+            shares_commitment: com.shares_commitment.iter().map(|p| p.compress()).collect(),
+            zkp: WireZKP {
+                g_k_i: com.zkp.g_k_i.compress(),
+                sigma: com.zkp.sigma,
+            },
+        }
+    }
+}
+
+/// The commitment of each peer in `wire`, every point decoded with
+/// `decode_point_strict`. Throws `NonCanonicalEncoding` naming all the peers
+/// that sent a point it rejects.
+pub(crate) fn decode_commitments_strict(
+    wire: &HashMap<MpcAddr, WireDKGCommitment>,
+) -> Outcome<HashMap<MpcAddr, KeyGenDKGProposedCommitment>> {
+    let mut peers: Vec<&MpcAddr> = wire.keys().collect();
+    peers.sort();
+    let mut coms = HashMap::with_capacity(wire.len());
+    let mut reasons: Vec<String> = Vec::new();
+    for peer in peers {
+        let com = &wire[peer];
+        let decoded = com
+            .shares_commitment
+            .iter()
+            .map(|point| decode_point_strict(point.as_bytes(), *peer))
+            .collect::<Outcome<Vec<EdwardsPoint>>>()
+            .and_then(|shares_commitment| {
+                let g_k_i = decode_point_strict(com.zkp.g_k_i.as_bytes(), *peer)?;
+                Ok(KeyGenDKGProposedCommitment {
+                    shares_commitment,
+                    zkp: KeyGenZKP {
+                        g_k_i,
+                        sigma: com.zkp.sigma,
+                    },
+                })
+            });
+        match decoded {
+            Ok(decoded) => {
+                coms.insert(*peer, decoded);
+            }
+            Err(err) => reasons.push(err.get_context().unwrap_or_default().to_string()),
+        }
+    }
+    assert_throw!(reasons.is_empty(), NonCanonicalEncoding, reasons.join("; "));
+    Ok(coms)
+}
+
+#[cfg(test)]
+mod tests {
+    use curve25519_dalek::constants;
+
+    use super::*;
+
+    #[test]
+    fn non_canonical_encodings_name_the_peer() {
+        let peer = MpcAddr::new(1, 2);
+        let s = Scalar::from(5u64);
+        assert_eq!(decode_scalar_strict(&s.to_bytes(), peer).unwrap(), s);
+
+        // $s + \ell$ reduces to $s$, but is not its encoding.
+        let mut wide = [0u8; 32];
+        let mut carry = 0u16;
+        let l = constants::BASEPOINT_ORDER.to_bytes();
+        for (i, byte) in wide.iter_mut().enumerate() {
+            let sum = s.to_bytes()[i] as u16 + l[i] as u16 + carry;
+            *byte = sum as u8;
+            carry = sum >> 8;
+        }
+        assert_eq!(Scalar::from_bytes_mod_order(wide), s);
+        let err = decode_scalar_strict(&wide, peer).unwrap_err();
+        assert_eq!(err.get_name(), NonCanonicalEncoding);
+        assert!(err.get_context().unwrap().contains("Party 1.2"));
+        assert!(decode_scalar_strict(&wide[..31], peer).is_err());
+
+        let g = constants::ED25519_BASEPOINT_POINT;
+        let g_bytes = g.compress().to_bytes();
+        assert_eq!(decode_point_strict(&g_bytes, peer).unwrap(), g);
+
+        // $y = p$ decompresses as $y = 0$, a point of order 4.
+        let mut y_is_p = [0xffu8; 32];
+        y_is_p[0] = 0xed;
+        y_is_p[31] = 0x7f;
+        assert!(CompressedEdwardsY(y_is_p).decompress().is_some());
+        let err = decode_point_strict(&y_is_p, peer).unwrap_err();
+        assert!(err.get_context().unwrap().contains("Party 1.2"));
+
+        // Some $y$ has no $x$ on the curve.
+        let off_curve = (2u8..)
+            .map(|y| {
+                let mut bytes = [0u8; 32];
+                bytes[0] = y;
+                bytes
+            })
+            .find(|bytes| CompressedEdwardsY(*bytes).decompress().is_none())
+            .unwrap();
+        let err = decode_point_strict(&off_curve, peer).unwrap_err();
+        assert_eq!(err.get_name(), NonCanonicalEncoding);
+    }
+}
@@ -1,3 +1,5 @@
+mod encoding;
+pub use encoding::*;
 mod party_key;
 pub use party_key::*;
 mod signing_key;
@@ -50,9 +52,114 @@ impl SigningCommitmentPair {
     }
 }
 
+impl Signature {
+    /// The canonical Ed25519 encoding `compress(R) || s`.
+    ///
+    /// Signing runs over the Edwards form of curve25519 with the Ed25519
+    /// basepoint and challenge $H(R, PK, m)$, not over Ristretto, so the
+    /// bytes verify with `ed25519_dalek` under the group public key
+    /// `PK.compress()`.
+    pub fn to_bytes(&self) -> [u8; 64] {
+        let mut sig_bytes = [0u8; 64];
+        sig_bytes[..32].copy_from_slice(&self.r.compress().to_bytes());
+        sig_bytes[32..].copy_from_slice(&self.s.to_bytes());
+        sig_bytes
+    }
+
+    /// Parse the encoding of `to_bytes`, a signature of `hash`.
+    /// Rejects a wrong length, and an `R` or `s` that is not canonical.
+    ///
+    /// `hash` is only stored, not checked: a `Signature` carries the message
+    /// it signs, which `verify_signature` and `verify_solana` read, but the
+    /// 64-byte encoding does not, so the caller has to supply it.
+    pub fn from_bytes(bytes: &[u8], hash: &[u8]) -> Outcome<Self> {
+        const ISE: &str = "InvalidSignature";
+        assert_throw!(
+            bytes.len() == 64,
+            ISE,
+            format!("A signature has 64 bytes, not {}", bytes.len())
+        );
+        let mut r_bytes = [0u8; 32];
+        let mut s_bytes = [0u8; 32];
+        r_bytes.copy_from_slice(&bytes[..32]);
+        s_bytes.copy_from_slice(&bytes[32..]);
+        let r = CompressedEdwardsY(r_bytes)
+            .decompress()
+            .ifnone(ISE, "R is not a point of the curve")?;
+        assert_throw!(
+            r.compress().to_bytes() == r_bytes,
+            ISE,
+            "R is not canonical"
+        );
+        let s = Scalar::from_canonical_bytes(s_bytes).ifnone(ISE, "s is not canonical")?;
+        Ok(Signature {
+            r,
+            s,
+            hash: hash.to_vec(),
+        })
+    }
+
+    /// Check the cofactored Schnorr equation $8 (sG - R - H(R, PK, m) PK) = 0$
+    /// for `msg`, the same equation `verify_batch` checks for every item.
+    pub fn verify(&self, msg: &[u8], pubkey: &EdwardsPoint) -> Outcome<()> {
+        self.verify_with_dom(&[], msg, pubkey)
+    }
+
+    /// `verify` with the challenge of `generate_challenge_with_dom`.
+    pub fn verify_with_dom(&self, dom: &[u8], msg: &[u8], pubkey: &EdwardsPoint) -> Outcome<()> {
+        self.verify_with(&signing_key::Ed25519Challenge, dom, msg, pubkey)
+    }
+
+    /// `verify_with_dom` with the challenge of `hasher`.
+    /// This is the only Schnorr check; `verify`, `verify_signature`,
+    /// `verify_prehashed` and the per-item path of `find_invalid_in_batch` call it.
+    pub fn verify_with(
+        &self,
+        hasher: &impl signing_key::ChallengeHash,
+        dom: &[u8],
+        msg: &[u8],
+        pubkey: &EdwardsPoint,
+    ) -> Outcome<()> {
+        let challenge = hasher.challenge(dom, msg, &self.r, pubkey);
+        let r = &constants::ED25519_BASEPOINT_TABLE * &self.s - pubkey * challenge;
+        assert_throw!(
+            (r - self.r).mul_by_cofactor().is_identity(),
+            "InvalidSignature",
+            "Signature is invalid"
+        );
+        Ok(())
+    }
+}
+
+/// Hex of `to_bytes`.
+impl std::fmt::Display for Signature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", hex::encode(self.to_bytes()))
+    }
+}
+
+impl Zeroize for SigningNoncePair {
+    fn zeroize(&mut self) {
+        self.d.secret.zeroize();
+        self.e.secret.zeroize();
+    }
+}
+
 impl SigningNoncePair {
+    /// The commitment $(dG, eG)$ to this pair.
+    pub fn commitment(&self) -> SigningCommitmentPair {
+        SigningCommitmentPair {
+            g_d: self.d.public,
+            g_e: self.e.public,
+        }
+    }
+
     pub fn new<R: RngCore + CryptoRng>(rng: &mut R) -> Outcome<SigningNoncePair> {
-        let (d, e) = (Scalar::random(rng), Scalar::random(rng));
+        SigningNoncePair::from_scalars(Scalar::random(rng), Scalar::random(rng))
+    }
+
+    /// The pair of nonces $(d, e)$ however they were drawn.
+    pub fn from_scalars(d: Scalar, e: Scalar) -> Outcome<SigningNoncePair> {
         let (d_pub, e_pub) = (
             &constants::ED25519_BASEPOINT_TABLE * &d,
             &constants::ED25519_BASEPOINT_TABLE * &e,
@@ -80,7 +187,12 @@ impl SigningNoncePair {
     }
 }
 
-use curve25519_dalek::{constants, edwards::EdwardsPoint, scalar::Scalar, traits::Identity};
+use curve25519_dalek::{
+    constants,
+    edwards::{CompressedEdwardsY, EdwardsPoint},
+    scalar::Scalar,
+    traits::{Identity, IsIdentity},
+};
 use libexception::*;
 use rand::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
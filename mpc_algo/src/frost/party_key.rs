@@ -4,8 +4,10 @@ use curve25519_dalek::{constants, edwards::EdwardsPoint, scalar::Scalar, traits:
 use libexception::*;
 use mpc_spec::MpcAddr;
 use rand::{CryptoRng, RngCore};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
 use zeroize::Zeroize;
 
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
@@ -48,6 +50,37 @@ pub struct KeyGenZKP {
     pub sigma: Scalar,       // KeyGen: sigma
 }
 
+impl KeyGenDKGProposedCommitment {
+    /// SHA-256 over every point and scalar of the commitment, for members to
+    /// compare what a dealer broadcast to each of them.
+    pub fn digest(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update((self.shares_commitment.len() as u64).to_be_bytes());
+        for point in self.shares_commitment.iter() {
+            hasher.update(point.compress().to_bytes());
+        }
+        hasher.update(self.zkp.g_k_i.compress().to_bytes());
+        hasher.update(self.zkp.sigma.to_bytes());
+        hasher.finalize().into()
+    }
+}
+
+/// Returns (in ascending order) the dealers whose commitment digest, as some
+/// member echoed it in `echoes` (member -> dealer -> digest), differs from
+/// the one in `mine` or is missing, i.e. who equivocated.
+pub fn find_equivocating_dealers(
+    mine: &HashMap<MpcAddr, [u8; 32]>,
+    echoes: &HashMap<MpcAddr, HashMap<MpcAddr, [u8; 32]>>,
+) -> Vec<MpcAddr> {
+    let mut dealers: Vec<MpcAddr> = mine
+        .iter()
+        .filter(|(dealer, digest)| echoes.values().any(|echo| echo.get(dealer) != Some(digest)))
+        .map(|(dealer, _)| *dealer)
+        .collect();
+    dealers.sort();
+    dealers
+}
+
 impl Zeroize for KeyGenDKGProposedCommitment {
     fn zeroize(&mut self) {
         self.shares_commitment.zeroize();
@@ -82,43 +115,72 @@ pub fn keygen_validate_peers(
     proposed_coms: &HashMap<MpcAddr, KeyGenDKGProposedCommitment>,
     context: &str,
 ) -> Outcome<HashMap<MpcAddr, Vec<EdwardsPoint>>> {
-    let mut invalid_ids = Vec::new();
-    let mut valid_coms = HashMap::new();
-
-    for (id, com) in proposed_coms.iter() {
-        let challenge = generate_dkg_challenge(
-            *id,
-            context,
-            &com.get_commitment_to_secret(),
-            &com.zkp.g_k_i,
-        )
-        .catch_()?;
-
-        if com.is_valid_zkp(challenge).is_ok() {
-            let valid_com = com.shares_commitment.clone();
-            valid_coms.insert(*id, valid_com);
-        } else {
-            invalid_ids.push(*id);
-        }
-    }
-
+    let invalid_ids = find_invalid_zkps(proposed_coms, context)?;
     if invalid_ids.len() > 0 {
         let errmsg = format!("Invalid zkp from parties {:?}", invalid_ids);
         throw!("", &errmsg);
     }
 
+    let valid_coms = proposed_coms
+        .iter()
+        .map(|(id, com)| (*id, com.shares_commitment.clone()))
+        .collect();
     Ok(valid_coms)
 }
 
+/// Returns (in ascending order) the parties whose proof in `proposed_coms`
+/// fails `verify_dkg_zkp`. The proofs are independent of each other, so they
+/// are checked in parallel on the rayon thread pool.
+pub fn find_invalid_zkps(
+    proposed_coms: &HashMap<MpcAddr, KeyGenDKGProposedCommitment>,
+    context: &str,
+) -> Outcome<Vec<MpcAddr>> {
+    let mut ids: Vec<MpcAddr> = proposed_coms.keys().cloned().collect();
+    ids.sort();
+    let verdicts: Vec<bool> = ids
+        .par_iter()
+        .map(|id| verify_dkg_zkp(*id, &proposed_coms[id], context))
+        .collect::<Outcome<_>>()?;
+    let invalid_ids = ids
+        .into_iter()
+        .zip(verdicts)
+        .filter(|(_, valid)| !valid)
+        .map(|(id, _)| id)
+        .collect();
+    Ok(invalid_ids)
+}
+
+/// Re-verify the proof of knowledge of $u_i$ in the commitment `com` that
+/// party `id` broadcast in a keygen under `context`, i.e. recompute the
+/// challenge $c$ and check $g_k + c \cdot g_{u_i} = \sigma G$.
+///
+/// Needs only public data, so stored commitments can be audited offline.
+pub fn verify_dkg_zkp(
+    id: MpcAddr,
+    com: &KeyGenDKGProposedCommitment,
+    context: &str,
+) -> Outcome<bool> {
+    assert_throw!(
+        !com.shares_commitment.is_empty(),
+        "InvalidCommitment",
+        format!("Empty commitment from party {}", id)
+    );
+    let challenge =
+        generate_dkg_challenge(id, context, &com.get_commitment_to_secret(), &com.zkp.g_k_i)
+            .catch_()?;
+    Ok(com.is_valid_zkp(challenge).is_ok())
+}
+
 pub fn merge_vss_share(
     party_shares: &HashMap<MpcAddr, Scalar>,
     share_coms: &HashMap<MpcAddr, Vec<EdwardsPoint>>,
     my_id: MpcAddr,
 ) -> Outcome<Scalar /* x_i, aka the signing key */> {
     // first, verify the integrity of the shares
-    for (id, share) in party_shares.iter() {
-        let com = share_coms.get(id).ifnone_()?;
-        verify_vss_share(my_id, share, com).catch_()?;
+    let invalid_ids = find_invalid_shares(party_shares, share_coms, my_id)?;
+    if !invalid_ids.is_empty() {
+        let errmsg = format!("Invalid share from parties {:?}", invalid_ids);
+        throw!("InvalidShare", &errmsg);
     }
 
     let mut x_i = Scalar::zero();
@@ -129,6 +191,65 @@ pub fn merge_vss_share(
     Ok(x_i)
 }
 
+/// Interpolate at $x = 0$ the signing shares $x_i$ of one group, i.e. recover
+/// the group secret, from at least `th` of them.
+///
+/// WARNING: This defeats the threshold guarantee. Whoever holds the result can
+/// sign alone, and so can anyone who ever gets hold of it. Only call it when
+/// the group has deliberately decided to collapse the wallet into a single key.
+pub fn reconstruct_secret(shares: &HashMap<MpcAddr, Scalar>, th: usize) -> Outcome<Scalar> {
+    const IPE: &str = "InvalidParameters";
+    assert_throw!(
+        th >= 1 && shares.len() >= th,
+        IPE,
+        format!(
+            "{} shares cannot reach the threshold th={}",
+            shares.len(),
+            th
+        )
+    );
+    let signers: HashSet<MpcAddr> = shares.keys().cloned().collect();
+    let gid = signers.iter().next().ifnone_()?.group_id();
+    for id in signers.iter() {
+        assert_throw!(
+            id.group_id() == gid && id.member_id() != 0,
+            IPE,
+            format!(
+                "Share of {} is not a share of a member of group {}",
+                id, gid
+            )
+        );
+    }
+
+    let mut secret = Scalar::zero();
+    for (id, x_i) in shares.iter() {
+        let mut term = super::lagrange_lambda(*id, &signers)? * x_i;
+        secret += term;
+        term.zeroize();
+    }
+    Ok(secret)
+}
+
+/// Returns (in ascending order) the senders whose share to `my_id` doesn't
+/// match the commitment they published, so they can be banned.
+pub fn find_invalid_shares(
+    party_shares: &HashMap<MpcAddr, Scalar>,
+    share_coms: &HashMap<MpcAddr, Vec<EdwardsPoint>>,
+    my_id: MpcAddr,
+) -> Outcome<Vec<MpcAddr>> {
+    let mut invalid_ids = Vec::new();
+    for (id, share) in party_shares.iter() {
+        let com = share_coms
+            .get(id)
+            .ifnone("NoCommitment", format!("No commitment from party {}", id))?;
+        if verify_vss_share(my_id, share, com).is_err() {
+            invalid_ids.push(*id);
+        }
+    }
+    invalid_ids.sort();
+    Ok(invalid_ids)
+}
+
 pub fn generate_vss_share<R: RngCore + CryptoRng>(
     u_i: &Scalar,
     my_id: MpcAddr,
@@ -146,17 +267,60 @@ pub fn generate_vss_share<R: RngCore + CryptoRng>(
             "vss_share: members not in same group"
         );
     }
-    assert_throw!(1 <= th && th < members.len());
+    deal_vss_share(u_i, members, th, rng)
+}
+
+/// Share `secret` among `members` with a fresh polynomial of degree `th - 1`.
+/// Unlike `generate_vss_share`, the dealer need not be one of `members`.
+pub fn deal_vss_share<R: RngCore + CryptoRng>(
+    secret: &Scalar,
+    members: &HashSet<MpcAddr>,
+    th: usize, // At least `th` members during sign.
+    rng: &mut R,
+) -> Outcome<(Vec<EdwardsPoint>, HashMap<MpcAddr, Scalar>)> {
+    // `th == members.len()` is an n-of-n group, the same bound as `validate_group`.
+    assert_throw!(
+        1 <= th && th <= members.len(),
+        format!(
+            "threshold th={} must be within 1..={} members",
+            th,
+            members.len()
+        )
+    );
 
     // randomly generate a polynomial
-    let mut poly: Vec<Scalar> = vec![u_i.clone()];
+    let mut poly: Vec<Scalar> = vec![*secret];
     for _ in 1..th {
         poly.push(Scalar::random(rng));
     }
 
+    let res = deal_vss_share_from_coeffs(&poly, members);
+    for c in poly.iter_mut() {
+        c.zeroize();
+    }
+    res
+}
+
+/// Same as `deal_vss_share`, but with the polynomial given by its
+/// coefficients `coeffs` in ascending power of $x$, the secret first,
+/// so `th = coeffs.len()`. Meant for reproducing reference test vectors:
+/// coefficients that are not random leak the secret.
+pub fn deal_vss_share_from_coeffs(
+    coeffs: &[Scalar],
+    members: &HashSet<MpcAddr>,
+) -> Outcome<(Vec<EdwardsPoint>, HashMap<MpcAddr, Scalar>)> {
+    assert_throw!(
+        !coeffs.is_empty() && coeffs.len() <= members.len(),
+        format!(
+            "threshold th={} must be within 1..={} members",
+            coeffs.len(),
+            members.len()
+        )
+    );
+
     // commit to the polynomial
     let mut poly_com = Vec::new();
-    for c in poly.iter() {
+    for c in coeffs.iter() {
         poly_com.push(&constants::ED25519_BASEPOINT_TABLE * c);
     }
 
@@ -165,18 +329,22 @@ pub fn generate_vss_share<R: RngCore + CryptoRng>(
     let mut shares: HashMap<MpcAddr, Scalar> = HashMap::new();
     for i in members.iter() {
         let x = Scalar::from(i.member_id());
-        let y = eval_poly(&poly, &x);
+        let y = eval_poly(coeffs, &x);
         shares.insert(*i, y);
     }
 
-    for c in poly.iter_mut() {
-        c.zeroize();
-    }
-
     Ok((poly_com, shares))
 }
 
+/// Domain separation tag of `generate_dkg_challenge`.
+pub const DKG_CHALLENGE_TAG: &[u8] = b"mpc_eddsa frost dkg challenge v1";
+
 /// This may vary from chain to chain, from protocol to protocol.
+///
+/// The transcript is `DKG_CHALLENGE_TAG` followed by the fields
+/// `commitment.compress()`, `public.compress()`, `index.to_be_bytes()` and
+/// `context`, each prefixed with its length as a big-endian `u64`,
+/// so no two distinct `(index, context)` pairs hash the same bytes.
 pub fn generate_dkg_challenge(
     index: MpcAddr,
     context: &str,
@@ -184,11 +352,18 @@ pub fn generate_dkg_challenge(
     commitment: &EdwardsPoint,
 ) -> Outcome<Scalar> {
     let mut hasher = Sha256::new();
+    hasher.update(DKG_CHALLENGE_TAG);
     // the order of the below may change to allow for EdDSA verification compatibility
-    hasher.update(commitment.compress().to_bytes());
-    hasher.update(public.compress().to_bytes());
-    hasher.update(index.to_string());
-    hasher.update(context);
+    let fields: [&[u8]; 4] = [
+        &commitment.compress().to_bytes(),
+        &public.compress().to_bytes(),
+        &index.to_be_bytes(),
+        context.as_bytes(),
+    ];
+    for field in fields {
+        hasher.update((field.len() as u64).to_be_bytes());
+        hasher.update(field);
+    }
     let result = hasher.finalize();
 
     let a: [u8; 32] = result
@@ -206,8 +381,34 @@ pub fn verify_vss_share(id: MpcAddr, share: &Scalar, com: &[EdwardsPoint]) -> Ou
 
     let x = Scalar::from(id.member_id());
     let expanded_polycom = eval_polycom(com, &x);
-    assert_throw!(polycom == expanded_polycom, "Invalid share");
+    // `polycom` is derived from the secret share, so compare in constant time
+    // rather than stopping at the first differing byte.
+    let is_valid: bool = polycom.ct_eq(&expanded_polycom).into();
+    assert_throw!(
+        is_valid,
+        "InvalidShare",
+        format!("Share of {} does not match the commitment", id)
+    );
+
+    Ok(())
+}
 
+/// Verify that the signing share $x_i$ of `id`, the sum of the shares dealt
+/// to it, matches the group's commitments `vss_com_dict`.
+/// A party should run this on any $x_i$ it did not merge itself,
+/// e.g. one enrolled by others or restored from a backup, before trusting it.
+pub fn verify_signing_share(
+    id: MpcAddr,
+    x_i: &Scalar,
+    vss_com_dict: &HashMap<MpcAddr, Vec<EdwardsPoint>>,
+) -> Outcome<()> {
+    let gxi = &constants::ED25519_BASEPOINT_TABLE * x_i;
+    let is_valid: bool = gxi.ct_eq(&eval_xi_com(id, vss_com_dict)).into();
+    assert_throw!(
+        is_valid,
+        "InvalidShare",
+        format!("Signing share of {} does not match the group", id)
+    );
     Ok(())
 }
 
@@ -241,3 +442,196 @@ fn eval_polycom(coef_coms: &[EdwardsPoint], x: &Scalar) -> EdwardsPoint {
     }
     polycom
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn vss_at_the_largest_id_is_exact() {
+        let top = MpcAddr::new(1, u16::MAX);
+        let members: HashSet<MpcAddr> = [MpcAddr::new(1, 1), MpcAddr::new(1, 40000), top].into();
+        let coeffs = [Scalar::random(&mut OsRng), Scalar::random(&mut OsRng)];
+        let (com, shares) = deal_vss_share_from_coeffs(&coeffs, &members).unwrap();
+
+        let x = Scalar::from(u16::MAX as u64);
+        assert_eq!(shares[&top], coeffs[0] + coeffs[1] * x);
+        for (id, share) in shares.iter() {
+            verify_vss_share(*id, share, &com).unwrap();
+        }
+        let pair = HashMap::from([
+            (top, shares[&top]),
+            (MpcAddr::new(1, 40000), shares[&MpcAddr::new(1, 40000)]),
+        ]);
+        assert_eq!(reconstruct_secret(&pair, 2).unwrap(), coeffs[0]);
+    }
+
+    #[test]
+    fn tampered_share_is_attributed() {
+        let members: HashSet<MpcAddr> = (1..=3).map(|i| MpcAddr::new(1, i)).collect();
+        let me = MpcAddr::new(1, 1);
+        let mut party_shares = HashMap::new();
+        let mut share_coms = HashMap::new();
+        for dealer in members.iter() {
+            let u_i = Scalar::random(&mut OsRng);
+            let (com, shares) = generate_vss_share(&u_i, *dealer, &members, 2, &mut OsRng).unwrap();
+            party_shares.insert(*dealer, shares[&me]);
+            share_coms.insert(*dealer, com);
+        }
+        assert!(find_invalid_shares(&party_shares, &share_coms, me)
+            .unwrap()
+            .is_empty());
+
+        let cheater = MpcAddr::new(1, 3);
+        *party_shares.get_mut(&cheater).unwrap() += Scalar::one();
+        assert_eq!(
+            find_invalid_shares(&party_shares, &share_coms, me).unwrap(),
+            vec![cheater]
+        );
+        assert!(merge_vss_share(&party_shares, &share_coms, me).is_err());
+    }
+
+    fn propose(id: MpcAddr, context: &str) -> KeyGenDKGProposedCommitment {
+        let party_key = PartyKey::new(&mut OsRng);
+        let challenge =
+            generate_dkg_challenge(id, context, &party_key.g_u_i(), &party_key.g_k_i()).unwrap();
+        KeyGenDKGProposedCommitment {
+            shares_commitment: vec![party_key.g_u_i()],
+            zkp: KeyGenZKP {
+                g_k_i: party_key.g_k_i(),
+                sigma: party_key.k_i + party_key.u_i * challenge,
+            },
+        }
+    }
+
+    #[test]
+    fn dkg_zkp_is_verified() {
+        let id = MpcAddr::new(1, 1);
+        let com = propose(id, "ses");
+        assert!(verify_dkg_zkp(id, &com, "ses").unwrap());
+        assert!(!verify_dkg_zkp(id, &com, "other ses").unwrap());
+        assert!(!verify_dkg_zkp(MpcAddr::new(1, 2), &com, "ses").unwrap());
+
+        let mut tampered = com.clone();
+        tampered.zkp.sigma += Scalar::one();
+        assert!(!verify_dkg_zkp(id, &tampered, "ses").unwrap());
+    }
+
+    #[test]
+    fn parallel_zkp_check_matches_serial() {
+        let context = "ses";
+        let mut proposed_coms: HashMap<MpcAddr, _> = (1..=32)
+            .map(|i| MpcAddr::new(1, i))
+            .map(|id| (id, propose(id, context)))
+            .collect();
+        for i in [3, 17, 30] {
+            let com = proposed_coms.get_mut(&MpcAddr::new(1, i)).unwrap();
+            com.zkp.sigma += Scalar::one();
+        }
+
+        let mut serial: Vec<MpcAddr> = proposed_coms
+            .iter()
+            .filter(|(id, com)| !verify_dkg_zkp(**id, com, context).unwrap())
+            .map(|(id, _)| *id)
+            .collect();
+        serial.sort();
+        assert_eq!(find_invalid_zkps(&proposed_coms, context).unwrap(), serial);
+        assert_eq!(
+            serial,
+            vec![MpcAddr::new(1, 3), MpcAddr::new(1, 17), MpcAddr::new(1, 30)]
+        );
+        assert!(keygen_validate_peers(&proposed_coms, context).is_err());
+    }
+
+    #[test]
+    fn equivocating_dealer_is_found() {
+        let ids: Vec<MpcAddr> = (1..=3).map(|i| MpcAddr::new(1, i)).collect();
+        let coms: HashMap<MpcAddr, _> = ids.iter().map(|id| (*id, propose(*id, "ses"))).collect();
+        let mine: HashMap<MpcAddr, [u8; 32]> =
+            coms.iter().map(|(id, com)| (*id, com.digest())).collect();
+        let mut echoes: HashMap<MpcAddr, _> = ids.iter().map(|id| (*id, mine.clone())).collect();
+        assert!(find_equivocating_dealers(&mine, &echoes).is_empty());
+
+        let forged = propose(ids[2], "ses");
+        assert_ne!(forged.digest(), coms[&ids[2]].digest());
+        echoes
+            .get_mut(&ids[1])
+            .unwrap()
+            .insert(ids[2], forged.digest());
+        assert_eq!(find_equivocating_dealers(&mine, &echoes), vec![ids[2]]);
+
+        echoes.get_mut(&ids[0]).unwrap().remove(&ids[0]);
+        assert_eq!(
+            find_equivocating_dealers(&mine, &echoes),
+            vec![ids[0], ids[2]]
+        );
+    }
+
+    #[test]
+    fn fixed_coefficients_give_the_reference_vss() {
+        // $f(x) = 1 + 2x + 3x^2$
+        let coeffs: Vec<Scalar> = [1u64, 2, 3].iter().map(|c| Scalar::from(*c)).collect();
+        let members: HashSet<MpcAddr> = (1..=4).map(|i| MpcAddr::new(1, i)).collect();
+        let (com, shares) = deal_vss_share_from_coeffs(&coeffs, &members).unwrap();
+
+        let g = constants::ED25519_BASEPOINT_POINT;
+        assert_eq!(
+            hex::encode(com[0].compress().to_bytes()),
+            "5866666666666666666666666666666666666666666666666666666666666666"
+        );
+        assert_eq!(com, vec![g, g * Scalar::from(2u64), g * Scalar::from(3u64)]);
+        for (i, y) in [(1, 6u64), (2, 17), (3, 34), (4, 57)] {
+            assert_eq!(shares[&MpcAddr::new(1, i)], Scalar::from(y));
+        }
+        for (id, share) in shares.iter() {
+            assert!(verify_vss_share(*id, share, &com).is_ok());
+        }
+
+        assert!(deal_vss_share_from_coeffs(&[], &members).is_err());
+        assert!(deal_vss_share_from_coeffs(&[Scalar::one(); 5], &members).is_err());
+    }
+
+    #[test]
+    fn vss_share_is_verified() {
+        let members: HashSet<MpcAddr> = (1..=3).map(|i| MpcAddr::new(1, i)).collect();
+        let secret = Scalar::random(&mut OsRng);
+        let (com, shares) = deal_vss_share(&secret, &members, 2, &mut OsRng).unwrap();
+        for (id, share) in shares.iter() {
+            assert!(verify_vss_share(*id, share, &com).is_ok());
+            let tampered = share + Scalar::one();
+            let err = verify_vss_share(*id, &tampered, &com).unwrap_err();
+            assert_eq!(err.get_name(), "InvalidShare");
+        }
+    }
+
+    #[test]
+    fn signing_share_is_verified_against_the_group() {
+        let members: HashSet<MpcAddr> = (1..=3).map(|i| MpcAddr::new(1, i)).collect();
+        let (mut x, mut vss_com_dict) = (HashMap::new(), HashMap::new());
+        for dealer in members.iter() {
+            let secret = Scalar::random(&mut OsRng);
+            let (com, shares) = deal_vss_share(&secret, &members, 2, &mut OsRng).unwrap();
+            for (id, share) in shares {
+                *x.entry(id).or_insert_with(Scalar::zero) += share;
+            }
+            vss_com_dict.insert(*dealer, com);
+        }
+        for (id, x_i) in x.iter() {
+            verify_signing_share(*id, x_i, &vss_com_dict).unwrap();
+        }
+        let (one, two) = (MpcAddr::new(1, 1), MpcAddr::new(1, 2));
+        let err = verify_signing_share(one, &x[&two], &vss_com_dict).unwrap_err();
+        assert_eq!(err.get_name(), "InvalidShare");
+        assert!(err.get_context().unwrap().contains("1.1"));
+    }
+
+    #[test]
+    fn dkg_challenge_separates_id_from_context() {
+        // "1.1" + "2x" and "1.12" + "x" concatenate to the same string.
+        let g = constants::ED25519_BASEPOINT_POINT;
+        let c1 = generate_dkg_challenge(MpcAddr::new(1, 1), "2x", &g, &g).unwrap();
+        let c2 = generate_dkg_challenge(MpcAddr::new(1, 12), "x", &g, &g).unwrap();
+        assert_ne!(c1, c2);
+    }
+}
@@ -1,6 +1,11 @@
 use std::collections::{HashMap, HashSet};
 
-use curve25519_dalek::{constants, edwards::EdwardsPoint, scalar::Scalar, traits::Identity};
+use curve25519_dalek::{
+    constants,
+    edwards::EdwardsPoint,
+    scalar::Scalar,
+    traits::{Identity, IsIdentity, VartimeMultiscalarMul},
+};
 use libexception::*;
 use mpc_spec::MpcAddr;
 use rand::{CryptoRng, RngCore};
@@ -27,11 +32,9 @@ pub fn sign_and_respond(
     my_id: MpcAddr,
     x_i: &Scalar,
     rho_dict: &HashMap<MpcAddr, Scalar>,
-    sig_r: &EdwardsPoint,
     nonce: &SigningNoncePair, // .len() == cached_com_count
     signers: &HashSet<MpcAddr>,
-    main_pk: &EdwardsPoint,
-    msg: &[u8],
+    challenge: &Scalar, // c = H_2(R, Y, m)
 ) -> Outcome<Scalar> {
     let my_rho_i = rho_dict.get(&my_id).ifnone_()?;
 
@@ -43,8 +46,7 @@ pub fn sign_and_respond(
     );
     let lambda_i = lagrange_lambda(my_id, &signers).catch_()?;
 
-    // c= H_2(R, Y, m)
-    let c = generate_challenge(msg, &sig_r, main_pk);
+    let c = challenge;
 
     // z_i = d_i + (e_i * rho_i) + lambda_i * s_i * c
     let response = nonce.d.secret + (nonce.e.secret * my_rho_i) + (lambda_i * x_i * c);
@@ -56,27 +58,100 @@ pub fn sign_and_respond(
 /// for performing Lagrange interpolation, which underpins threshold secret
 /// sharing schemes based on Shamir secret sharing.
 pub fn lagrange_lambda(id: MpcAddr, signers: &HashSet<MpcAddr>) -> Outcome<Scalar> {
+    lagrange_lambda_at(id, signers, &Scalar::zero())
+}
+
+/// Lagrange basis polynomial of `id` over `signers`, evaluated at `x`.
+/// `lagrange_lambda` is the special case of $x = 0$.
+pub fn lagrange_lambda_at(id: MpcAddr, signers: &HashSet<MpcAddr>, x: &Scalar) -> Outcome<Scalar> {
     let mut num = Scalar::one();
     let mut den = Scalar::one();
     for j in signers {
         if *j == id {
             continue;
         }
-        num *= Scalar::from(j.member_id());
-        den *= Scalar::from(j.member_id()) - Scalar::from(id.member_id());
+        num *= x - Scalar::from(j.member_id());
+        den *= Scalar::from(id.member_id()) - Scalar::from(j.member_id());
     }
     assert_throw!(den != Scalar::zero(), "Duplicate shares provided");
 
     Ok(num * den.invert())
 }
 
+/// $\lambda_i$ of member `signer_id` among the members `signer_set` of a
+/// group, i.e. the weight of its share in the secret $f(0)$ they interpolate.
+/// Throws `InvalidSignerSet` if a member id is 0 or repeated, or `signer_id`
+/// is not in `signer_set`.
+pub fn lagrange_coefficient(signer_id: u16, signer_set: &[u16]) -> Outcome<Scalar> {
+    const ISS: &str = "InvalidSignerSet";
+    let mut ids: Vec<u16> = signer_set.to_vec();
+    ids.sort_unstable();
+    assert_throw!(ids.first() != Some(&0), ISS, "Member id 0 is the secret");
+    if let Some(pair) = ids.windows(2).find(|pair| pair[0] == pair[1]) {
+        throw!(ISS, format!("Member {} is repeated", pair[0]));
+    }
+    assert_throw!(
+        ids.binary_search(&signer_id).is_ok(),
+        ISS,
+        format!("Member {} is not in {:?}", signer_id, ids)
+    );
+    let signers: HashSet<MpcAddr> = ids.iter().map(|j| MpcAddr::new(1, *j)).collect();
+    lagrange_lambda(MpcAddr::new(1, signer_id), &signers)
+}
+
 // to be reviewed again? For H(m, R) instead of H(R, Y, m)???
 /// generates the challenge value H(m, R) used for both signing and verification.
 /// ed25519_ph hashes the message first, and derives the challenge as H(H(m), R),
 /// this would be a better optimization but incompatibility with other
 /// implementations may be undesirable.
 pub fn generate_challenge(msg: &[u8], com: &EdwardsPoint, pk: &EdwardsPoint) -> Scalar {
+    generate_challenge_with_dom(&[], msg, com, pk)
+}
+
+/// How the signing challenge $c$ is hashed from the group commitment $R$,
+/// the public key $A$ and the message $M$, for chains whose EdDSA variant
+/// deviates from RFC 8032. All signers and the verifier must agree on it.
+///
+/// `dom` is the mode prefix, empty unless signing Ed25519ph. Any
+/// `Fn(dom, msg, com, pk) -> Scalar` closure is a `ChallengeHash`.
+pub trait ChallengeHash: Send + Sync {
+    fn challenge(&self, dom: &[u8], msg: &[u8], com: &EdwardsPoint, pk: &EdwardsPoint) -> Scalar;
+}
+
+impl<F> ChallengeHash for F
+where
+    F: Fn(&[u8], &[u8], &EdwardsPoint, &EdwardsPoint) -> Scalar + Send + Sync,
+{
+    fn challenge(&self, dom: &[u8], msg: &[u8], com: &EdwardsPoint, pk: &EdwardsPoint) -> Scalar {
+        self(dom, msg, com, pk)
+    }
+}
+
+/// The RFC 8032 challenge that `algo_sign` uses, see `generate_challenge_with_dom`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Ed25519Challenge;
+
+impl ChallengeHash for Ed25519Challenge {
+    fn challenge(&self, dom: &[u8], msg: &[u8], com: &EdwardsPoint, pk: &EdwardsPoint) -> Scalar {
+        generate_challenge_with_dom(dom, msg, com, pk)
+    }
+}
+
+/// `generate_challenge` with `dom` hashed first, i.e. $H(dom, R, Y, m)$.
+/// An empty `dom` is plain Ed25519; `ed25519ph_dom` gives Ed25519ph
+/// and `ed25519ctx_dom` Ed25519ctx.
+///
+/// The bytes hashed by SHA-512 are `dom || R.compress() || A.compress() || M`,
+/// with no length prefixes, and the 64-byte digest is read as a little-endian
+/// integer reduced mod $\ell$.
+pub fn generate_challenge_with_dom(
+    dom: &[u8],
+    msg: &[u8],
+    com: &EdwardsPoint,
+    pk: &EdwardsPoint,
+) -> Scalar {
     let mut ha = Sha512::new();
+    ha.update(dom);
     ha.update(com.compress().to_bytes());
     ha.update(pk.compress().to_bytes());
     ha.update(msg);
@@ -136,11 +211,117 @@ pub fn is_valid_response(
 /// validate performs a plain Schnorr validation operation; this is identical
 /// to performing validation of a Schnorr signature that has been signed by a
 /// single party.
+/// It is `Signature::verify` on the message `sig.hash`.
 pub fn verify_signature(sig: &Signature, pubkey: &EdwardsPoint) -> Outcome<()> {
-    let G = &constants::ED25519_BASEPOINT_TABLE;
-    let challenge = generate_challenge(&sig.hash, &sig.r, &pubkey);
-    let r = G * &sig.s - pubkey * challenge;
-    assert_throw!(r == sig.r, "Signature is invalid");
+    sig.verify(&sig.hash, pubkey)
+}
+
+/// The prefix `dom2(1, context)` of RFC 8032 that makes a challenge Ed25519ph,
+/// whose message is the SHA-512 digest of the real one.
+///
+/// Ed25519 hashes no prefix at all, and every Ed25519ph transcript starts with
+/// the 32 bytes `"SigEd25519 no Ed25519 collisions"` followed by the flag 1,
+/// so a digest signed in this mode never verifies as a raw message, however short.
+pub fn ed25519ph_dom(context: &[u8]) -> Outcome<Vec<u8>> {
+    dom2(1, context, "Ed25519ph")
+}
+
+/// The prefix `dom2(0, context)` of RFC 8032 that makes a challenge Ed25519ctx,
+/// i.e. $H(dom2(0, context), R, A, M)$, so that signatures of a key under
+/// one context never verify under another, nor as plain Ed25519.
+/// RFC 8032 asks for a non-empty context, of at most 255 bytes.
+pub fn ed25519ctx_dom(context: &[u8]) -> Outcome<Vec<u8>> {
+    assert_throw!(
+        !context.is_empty(),
+        "InvalidParameters",
+        "An Ed25519ctx context must not be empty"
+    );
+    dom2(0, context, "Ed25519ctx")
+}
+
+/// `"SigEd25519 no Ed25519 collisions" || flag || len(context) || context`.
+fn dom2(flag: u8, context: &[u8], mode: &str) -> Outcome<Vec<u8>> {
+    assert_throw!(
+        context.len() <= 255,
+        "InvalidParameters",
+        format!(
+            "An {} context has at most 255 bytes, not {}",
+            mode,
+            context.len()
+        )
+    );
+    let mut dom = b"SigEd25519 no Ed25519 collisions".to_vec();
+    dom.push(flag); // 1 if prehashed
+    dom.push(context.len() as u8);
+    dom.extend_from_slice(context);
+    Ok(dom)
+}
+
+/// Verify an Ed25519ph signature made by `algo_sign_prehashed`,
+/// whose `sig.hash` is the SHA-512 digest of the message.
+pub fn verify_prehashed(sig: &Signature, context: &[u8], pubkey: &EdwardsPoint) -> Outcome<()> {
+    sig.verify_with_dom(&ed25519ph_dom(context)?, &sig.hash, pubkey)
+}
+
+/// Verify an Ed25519ctx signature made by `algo_sign_with_context`
+/// with a non-empty `sign_context`, against its `sig.hash`.
+pub fn verify_with_context(
+    sig: &Signature,
+    sign_context: &[u8],
+    pubkey: &EdwardsPoint,
+) -> Outcome<()> {
+    sig.verify_with_dom(&ed25519ctx_dom(sign_context)?, &sig.hash, pubkey)
+}
+
+/// Indices of the invalid items of a batch, in order, each `(msg, sig, pubkey)`
+/// checked as by `Signature::verify`. Empty if every item is valid.
+///
+/// With random 128-bit weights $z_i$, checks the single multiscalar equation
+/// $8 \left( \sum z_i R_i + \sum z_i c_i A_i - (\sum z_i s_i) G \right) = 0$.
+/// Only on failure is every item verified on its own, with the same cofactored
+/// equation, so a torsion component in some $R_i$ or $A_i$ gets the same answer
+/// from both paths. A failing batch costs about as much as verifying items one by one.
+pub fn find_invalid_in_batch(items: &[(Vec<u8>, Signature, EdwardsPoint)]) -> Vec<usize> {
+    let mut rng = rand::rngs::OsRng;
+    let mut s_sum = Scalar::zero();
+    let mut scalars: Vec<Scalar> = Vec::with_capacity(2 * items.len() + 1);
+    let mut points: Vec<EdwardsPoint> = Vec::with_capacity(2 * items.len() + 1);
+    for (msg, sig, pubkey) in items.iter() {
+        let mut z_bytes = [0u8; 32];
+        rng.fill_bytes(&mut z_bytes[..16]);
+        let z = Scalar::from_bits(z_bytes);
+        let challenge = generate_challenge(msg, &sig.r, pubkey);
+        s_sum += z * sig.s;
+        scalars.push(z);
+        points.push(sig.r);
+        scalars.push(z * challenge);
+        points.push(*pubkey);
+    }
+    scalars.push(-s_sum);
+    points.push(constants::ED25519_BASEPOINT_POINT);
+
+    let sum = EdwardsPoint::vartime_multiscalar_mul(&scalars, &points);
+    if sum.mul_by_cofactor().is_identity() {
+        return Vec::new();
+    }
+    items
+        .iter()
+        .enumerate()
+        .filter(|(_, (msg, sig, pubkey))| sig.verify(msg, pubkey).is_err())
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Like `find_invalid_in_batch`, but throws `InvalidSignature`
+/// naming the invalid indices.
+pub fn verify_batch(items: &[(Vec<u8>, Signature, EdwardsPoint)]) -> Outcome<()> {
+    let invalid = find_invalid_in_batch(items);
+    if !invalid.is_empty() {
+        throw!(
+            "InvalidSignature",
+            format!("Signatures {:?} of the batch are invalid", invalid)
+        );
+    }
     Ok(())
 }
 
@@ -153,13 +334,155 @@ pub fn verify_solana(sig: &Signature, pk: &EdwardsPoint) -> Outcome<()> {
     };
     let sig = {
         use ed25519_dalek::Signature as LibSignature;
-        let mut sig_bytes = [0u8; 64];
-        sig_bytes[..32].copy_from_slice(&sig.r.compress().to_bytes());
-        sig_bytes[32..].copy_from_slice(&sig.s.to_bytes());
-        let sig = LibSignature::from_bytes(&sig_bytes).catch_()?;
+        let sig = LibSignature::from_bytes(&sig.to_bytes()).catch_()?;
         sig
     };
 
     pk.verify_strict(msg, &sig).catch_()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A single-party signature, as the aggregate of a threshold one.
+    fn sign_alone(sk: &Scalar, msg: &[u8]) -> (Vec<u8>, Signature, EdwardsPoint) {
+        let G = &constants::ED25519_BASEPOINT_TABLE;
+        let pk = G * sk;
+        let k = Scalar::random(&mut rand::rngs::OsRng);
+        let r = G * &k;
+        let s = k + generate_challenge(msg, &r, &pk) * sk;
+        let sig = Signature {
+            r,
+            s,
+            hash: msg.to_vec(),
+        };
+        (msg.to_vec(), sig, pk)
+    }
+
+    #[test]
+    fn signature_round_trips() {
+        let (msg, sig, pk) = sign_alone(&Scalar::from(1234u64), b"msg");
+        sig.verify(&msg, &pk).unwrap();
+        assert!(sig.verify(b"other msg", &pk).is_err());
+        verify_signature(&sig, &pk).unwrap();
+        let mut forged = sig.clone();
+        forged.hash = b"other msg".to_vec();
+        let err = verify_signature(&forged, &pk).unwrap_err();
+        assert_eq!(err.get_name(), "InvalidSignature");
+
+        let parsed = Signature::from_bytes(&sig.to_bytes(), &msg).unwrap();
+        assert_eq!(
+            (parsed.r, parsed.s, &parsed.hash),
+            (sig.r, sig.s, &sig.hash)
+        );
+        assert_eq!(sig.to_string(), hex::encode(sig.to_bytes()));
+
+        let buf = serde_pickle::to_vec(&sig, Default::default()).unwrap();
+        let unpickled: Signature = serde_pickle::from_slice(&buf, Default::default()).unwrap();
+        assert_eq!(unpickled.to_bytes(), sig.to_bytes());
+    }
+
+    #[test]
+    fn lagrange_coefficients_interpolate_the_secret() {
+        // f(x) = 3 + 5x + 7x^2, of threshold 3
+        let f = |x: u16| {
+            let x = Scalar::from(x);
+            Scalar::from(3u8) + Scalar::from(5u8) * x + Scalar::from(7u8) * x * x
+        };
+        for set in [vec![1, 2, 3], vec![4, 1, 3], vec![2, 5, 6, 9]] {
+            let mut secret = Scalar::zero();
+            for i in set.iter() {
+                secret += lagrange_coefficient(*i, &set).unwrap() * f(*i);
+            }
+            assert_eq!(secret, f(0), "{:?}", set);
+        }
+
+        let rejection = |id, set: &[u16]| {
+            let err = lagrange_coefficient(id, set).unwrap_err();
+            assert_eq!(err.get_name(), "InvalidSignerSet");
+            err.get_context().unwrap().to_string()
+        };
+        assert!(rejection(2, &[1, 3]).contains("Member 2 is not in [1, 3]"));
+        assert!(rejection(1, &[3, 1, 3]).contains("Member 3 is repeated"));
+        assert!(rejection(1, &[1, 0]).contains("id 0"));
+    }
+
+    #[test]
+    fn malformed_signature_is_rejected() {
+        let (msg, sig, _) = sign_alone(&Scalar::from(1234u64), b"msg");
+        let bytes = sig.to_bytes();
+        let rejection = |bytes: &[u8]| {
+            let err = Signature::from_bytes(bytes, &msg).unwrap_err();
+            assert_eq!(err.get_name(), "InvalidSignature");
+            err.get_context().unwrap().to_string()
+        };
+        assert!(rejection(&bytes[..63]).contains("64 bytes, not 63"));
+
+        let mut non_canonical = bytes;
+        non_canonical[63] = 0xff;
+        assert!(rejection(&non_canonical).contains("s is not canonical"));
+
+        // y = 2 is not the y-coordinate of any point.
+        let mut off_curve = bytes;
+        off_curve[..32].copy_from_slice(&[0u8; 32]);
+        off_curve[0] = 2;
+        assert!(rejection(&off_curve).contains("R is not a point"));
+
+        // y = p decompresses as y = 0, but only 0 encodes it.
+        let mut unreduced = bytes;
+        unreduced[..32].copy_from_slice(&[0xff; 32]);
+        unreduced[0] = 0xed;
+        unreduced[31] = 0x7f;
+        assert!(rejection(&unreduced).contains("R is not canonical"));
+    }
+
+    #[test]
+    fn batch_finds_tampered_signature() {
+        let mut items: Vec<_> = (1..=8u64)
+            .map(|i| sign_alone(&Scalar::from(i * 1000 + 7), &i.to_be_bytes()))
+            .collect();
+        assert!(verify_batch(&items).is_ok());
+        assert!(verify_batch(&[]).is_ok());
+
+        items[5].1.s += Scalar::one();
+        items[2].1.s += Scalar::one();
+        assert_eq!(find_invalid_in_batch(&items), vec![2, 5]);
+        let err = verify_batch(&items).unwrap_err();
+        assert_eq!(err.get_name(), "InvalidSignature");
+        assert_eq!(
+            err.get_context(),
+            Some("Signatures [2, 5] of the batch are invalid")
+        );
+    }
+
+    #[test]
+    fn torsion_in_r_gets_one_answer() {
+        // R = kG + T for a point T of order 8, with s computed over that R.
+        let G = &constants::ED25519_BASEPOINT_TABLE;
+        let sk = Scalar::from(1234u64);
+        let pk = G * &sk;
+        let msg = b"msg".to_vec();
+        let k = Scalar::random(&mut rand::rngs::OsRng);
+        let r = G * &k + constants::EIGHT_TORSION[1];
+        assert!(!r.is_torsion_free());
+        let s = k + generate_challenge(&msg, &r, &pk) * sk;
+        let sig = Signature {
+            r,
+            s,
+            hash: msg.clone(),
+        };
+
+        let alone = sig.verify(&msg, &pk).is_ok();
+        assert!(alone, "the cofactored check ignores the torsion of R");
+        let mut items = vec![sign_alone(&sk, b"other"), (msg, sig, pk)];
+        assert_eq!(find_invalid_in_batch(&items).is_empty(), alone);
+
+        // Forcing the per-item path must not change the verdict on item 1.
+        items[0].1.s += Scalar::one();
+        let invalid = find_invalid_in_batch(&items);
+        assert_eq!(invalid.contains(&1), !alone);
+        assert!(invalid.contains(&0));
+    }
+}
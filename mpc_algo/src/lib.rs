@@ -1,3 +1,11 @@
+//! Every scalar crosses the network and the disk in its canonical encoding:
+//! 32 bytes, little-endian, below the group order $\ell$, as given by
+//! `Scalar::to_bytes`. Every point is its 32-byte compressed Edwards y.
+//! A receiver decodes them strictly, with `decode_scalar_strict` and
+//! `decode_point_strict`: a scalar not below $\ell$ or a point whose $y$ is
+//! not below $p$ is rejected with `NonCanonicalEncoding` naming its sender,
+//! never reduced into some other value.
+
 #![deny(unused_must_use)]
 #![allow(non_snake_case, non_upper_case_globals)]
 
@@ -0,0 +1,27 @@
+use std::collections::{HashMap, HashSet};
+
+use mpc_algo::{algo_keygen, KeyStoreExt};
+use mpc_spec::{MemoryTransport, MpcAddr};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn keygen_2_of_3_converges() {
+    let transport = MemoryTransport::default();
+    let members: HashSet<MpcAddr> = (1..=3).map(|i| MpcAddr::new(1, i)).collect();
+    let key_arch = HashMap::from([(1u16, (2usize, members))]);
+
+    let parties: Vec<_> = (1..=3)
+        .map(|i| {
+            let (transport, key_arch) = (transport.clone(), key_arch.clone());
+            tokio::spawn(async move {
+                let whoami = [MpcAddr::new(1, i)];
+                algo_keygen(&transport, &key_arch, &whoami, "in-memory").await
+            })
+        })
+        .collect();
+    let mut pks = Vec::new();
+    for party in parties {
+        let keystore = party.await.unwrap().unwrap();
+        pks.push(keystore.group_public_key());
+    }
+    assert!(pks.iter().all(|pk| *pk == pks[0]));
+}
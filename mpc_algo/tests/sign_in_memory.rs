@@ -0,0 +1,48 @@
+use std::collections::{HashMap, HashSet};
+
+use ed25519_dalek::Verifier;
+use mpc_algo::{algo_keygen, algo_sign, verify_signature, KeyStoreExt};
+use mpc_spec::{MemoryTransport, MpcAddr};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn keygen_2_of_3_then_sign_verifies() {
+    let transport = MemoryTransport::default();
+    let members: HashSet<MpcAddr> = (1..=3).map(|i| MpcAddr::new(1, i)).collect();
+    let key_arch = HashMap::from([(1u16, (2usize, members))]);
+
+    let parties: Vec<_> = (1..=3)
+        .map(|i| {
+            let (transport, key_arch) = (transport.clone(), key_arch.clone());
+            tokio::spawn(async move {
+                let whoami = [MpcAddr::new(1, i)];
+                algo_keygen(&transport, &key_arch, &whoami, "in-memory").await
+            })
+        })
+        .collect();
+    let mut keystores = Vec::new();
+    for party in parties {
+        keystores.push(party.await.unwrap().unwrap());
+    }
+
+    // Members 1 and 3 sign, on a transport of their own.
+    let transport = MemoryTransport::default();
+    let signers: HashSet<MpcAddr> = [1, 3].map(|i| MpcAddr::new(1, i)).into();
+    let ses_arch = HashMap::from([(1u16, signers)]);
+    let msg = b"keygen + sign round-trip";
+    let parties: Vec<_> = [&keystores[0], &keystores[2]]
+        .map(|keystore| {
+            let (transport, ses_arch, keystore) =
+                (transport.clone(), ses_arch.clone(), keystore.clone());
+            tokio::spawn(async move { algo_sign(&transport, &ses_arch, "", msg, &keystore).await })
+        })
+        .into();
+    let group_pk = keystores[0].group_public_key();
+    let pk = ed25519_dalek::PublicKey::from_bytes(&keystores[0].group_public_key_bytes()).unwrap();
+    for party in parties {
+        let sig = party.await.unwrap().unwrap();
+        verify_signature(&sig, &group_pk).unwrap();
+        // and so does any Ed25519 verifier
+        let sig = ed25519_dalek::Signature::from_bytes(&sig.to_bytes()).unwrap();
+        pk.verify(msg, &sig).unwrap();
+    }
+}
@@ -9,6 +9,7 @@ use mpc_spec::MpcAddr;
 
 #[tokio::main] // `tokio` re-exported by `mpc_sesman::prelude::*`
 async fn main() -> Outcome<()> {
+    tracing_subscriber::fmt::init(); // print keygen progress
     use clap::{Arg, ArgAction, Command};
     let matches = Command::new("demo_keygen")
         .arg(
@@ -23,7 +24,7 @@ async fn main() -> Outcome<()> {
     let key_arch = showcase_key_arch();
     let whoami = showcase_name_id(&name).catch_()?;
 
-    let client = ShowcaseSesmanClient {};
+    let client = ShowcaseSesmanClient::for_context("showcase");
     let keystore = algo_keygen(&client, &key_arch, &whoami, "showcase")
         .await
         .catch_()?;
@@ -40,10 +40,10 @@ async fn main() -> Outcome<()> {
     let mut file = File::open(path).await.catch("", &path)?;
     let mut buf = Vec::new();
     file.read_to_end(&mut buf).await.catch_()?;
-    let keystore = serde_pickle::from_slice(&buf, Default::default()).catch_()?;
+    let keystore = KeyStore::migrate(&buf).catch_()?;
 
     // sign
-    let client = ShowcaseSesmanClient {};
+    let client = ShowcaseSesmanClient::for_context("showcase sign");
     let sig = algo_sign(
         &client,
         &ses_arch,
@@ -1,17 +1,141 @@
 use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
 use libexception::*;
 use mpc_spec::*;
 use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+
+use tonic::{transport::Channel, Code};
 
 use super::{protogen::sesman::sesman_client::SesmanClient, GRPC_URL};
 use crate::sesman::protogen::sesman::Message;
 
-#[derive(Clone, Copy)]
-pub struct ShowcaseSesmanClient;
+#[allow(dead_code)] // used by ../demo_keygen.rs and ../demo_sign.rs, not by the server
+#[derive(Clone)]
+pub struct ShowcaseSesmanClient<C = PickleCodec> {
+    /// Larger messages are refused before being deserialized.
+    pub max_message_size: usize,
+    /// Sent with every message and expected back with every reply, so
+    /// concurrent sessions with the same round names never read each other's.
+    pub session: String,
+    /// Where the sesman server listens.
+    pub url: &'static str,
+    /// Encoding of the messages, which the server stores as opaque bytes.
+    pub codec: C,
+    /// Shared by clones, see `with_flood_limit`.
+    flood: Arc<FloodGuard>,
+}
+
+impl Default for ShowcaseSesmanClient {
+    fn default() -> Self {
+        Self {
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            session: String::new(),
+            url: GRPC_URL,
+            codec: PickleCodec,
+            flood: Arc::default(),
+        }
+    }
+}
+
+#[allow(dead_code)] // used by ../demo_keygen.rs and ../demo_sign.rs, not by the server
+impl ShowcaseSesmanClient {
+    /// A client for the session of the keygen or sign with `context`,
+    /// whose id is the first 16 bytes of SHA-256 of `context`, in hex.
+    pub fn for_context(context: &str) -> Self {
+        let digest = Sha256::digest(context.as_bytes());
+        let session = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+        Self {
+            session,
+            ..Default::default()
+        }
+    }
+}
+
+#[allow(dead_code)] // used by ../demo_keygen.rs and ../demo_sign.rs, not by the server
+impl<C: WireCodec> ShowcaseSesmanClient<C> {
+    /// The same client, encoding messages with `codec`, e.g. `CborCodec`
+    /// to talk with parties written in another language.
+    pub fn with_codec<D: WireCodec>(self, codec: D) -> ShowcaseSesmanClient<D> {
+        ShowcaseSesmanClient {
+            max_message_size: self.max_message_size,
+            session: self.session,
+            url: self.url,
+            codec,
+            flood: self.flood,
+        }
+    }
+
+    /// The same client, throwing `FloodDetected` once it consumed more of a
+    /// round than `limit`, in case the sesman relays bogus messages without end.
+    pub fn with_flood_limit(self, limit: FloodLimit) -> Self {
+        Self {
+            flood: Arc::new(FloodGuard::new(limit)),
+            ..self
+        }
+    }
+
+    /// The envelope of a message of my session.
+    fn envelope(&self, topic: &str, src: MpcAddr, dst: MpcAddr, obj: Option<Vec<u8>>) -> Message {
+        Message {
+            topic: topic.to_string(),
+            src: src.as_primitive(),
+            dst: dst.as_primitive(),
+            obj,
+            session: self.session.clone(),
+        }
+    }
+
+    /// Ask the sesman for the message indexed by `msg`, if it has arrived.
+    async fn fetch(
+        &self,
+        cl: &mut SesmanClient<Channel>,
+        msg: &Message,
+    ) -> Outcome<Option<Vec<u8>>> {
+        let src = MpcAddr::from(msg.src);
+        let resp = match cl.outbox(msg.clone()).await {
+            Ok(resp) => resp.into_inner(),
+            // thrown by the decoder before buffering the whole frame
+            Err(status) if status.code() == Code::OutOfRange => throw!(
+                "MessageTooLarge",
+                format!(
+                    "message from {} at round \"{}\" exceeds the limit of {} bytes",
+                    src, msg.topic, self.max_message_size
+                )
+            ),
+            Err(status) => return Err(status).catch_(),
+        };
+        assert_throw!(
+            resp.session == msg.session,
+            "SessionMismatch",
+            format!(
+                "message from {} at round \"{}\" is of session \"{}\", not \"{}\"",
+                src, msg.topic, resp.session, msg.session
+            )
+        );
+        if let Some(obj) = &resp.obj {
+            check_message_size(obj.len(), self.max_message_size, &msg.topic, src)?;
+            self.flood.consume(&msg.topic, src, obj.len())?;
+        }
+        Ok(resp.obj)
+    }
+
+    async fn connect(&self) -> Outcome<SesmanClient<Channel>> {
+        self.connect_to(self.url).await
+    }
+
+    async fn connect_to(&self, url: &'static str) -> Outcome<SesmanClient<Channel>> {
+        let cl = SesmanClient::connect(url)
+            .await
+            .catch("ConnectionError", url)?;
+        // leave room for the envelope of `obj`
+        Ok(cl.max_decoding_message_size(self.max_message_size + 1024))
+    }
+}
 
 #[async_trait]
-impl Messenger for ShowcaseSesmanClient {
+impl<C: WireCodec> Messenger for ShowcaseSesmanClient<C> {
     type E = Box<Exception>;
 
     async fn send<T>(
@@ -24,18 +148,10 @@ impl Messenger for ShowcaseSesmanClient {
     where
         T: Serialize + DeserializeOwned + Send + Sync,
     {
-        let mut cl = SesmanClient::connect(GRPC_URL)
-            .await
-            .map_err(|e| format!("ConnectionError: {:#?}", e))
-            .catch_()?;
+        let mut cl = self.connect().await?;
 
-        let obj = serde_pickle::to_vec(obj, Default::default()).catch_()?;
-        let req = Message {
-            topic: topic.to_string(),
-            src: src.as_primitive(),
-            dst: dst.as_primitive(),
-            obj: Some(obj),
-        };
+        let obj = self.codec.encode(obj)?;
+        let req = self.envelope(topic, src, dst, Some(obj));
 
         cl.inbox(req).await.catch_()?;
         Ok(())
@@ -50,21 +166,12 @@ impl Messenger for ShowcaseSesmanClient {
     where
         T: Serialize + DeserializeOwned + Send + Sync,
     {
-        let mut cl = SesmanClient::connect(GRPC_URL)
-            .await
-            .catch("ConnectionError", GRPC_URL)?;
+        let mut cl = self.connect().await?;
 
-        let msg = Message {
-            topic: topic.to_string(),
-            src: src.as_primitive(),
-            dst: dst.as_primitive(),
-            obj: None, // as index
-        };
+        let msg = self.envelope(topic, src, dst, None); // as index
         loop {
-            let resp = cl.outbox(msg.clone()).await.catch_()?.into_inner();
-            if let Some(obj) = resp.obj {
-                let obj = serde_pickle::from_slice(&obj, Default::default()).catch_()?;
-                return Ok(obj);
+            if let Some(obj) = self.fetch(&mut cl, &msg).await? {
+                return self.codec.decode(&obj);
             }
             use tokio::time::{sleep, Duration};
             sleep(Duration::from_millis(200)).await;
@@ -81,18 +188,11 @@ impl Messenger for ShowcaseSesmanClient {
     where
         T: Serialize + DeserializeOwned + Send + Sync,
     {
-        let mut cl = SesmanClient::connect(GRPC_URL)
-            .await
-            .catch("ConnectionError", GRPC_URL)?;
+        let mut cl = self.connect().await?;
 
-        let obj = serde_pickle::to_vec(obj, Default::default()).catch_()?;
+        let obj = self.codec.encode(obj)?;
         for dst in dsts.iter() {
-            let req = Message {
-                topic: topic.to_string(),
-                src: src.as_primitive(),
-                dst: dst.as_primitive(),
-                obj: Some(obj.clone()),
-            };
+            let req = self.envelope(topic, src, *dst, Some(obj.clone()));
             cl.inbox(req).await.catch_()?;
         }
         Ok(())
@@ -107,23 +207,14 @@ impl Messenger for ShowcaseSesmanClient {
     where
         T: Serialize + DeserializeOwned + Send + Sync,
     {
-        let mut cl = SesmanClient::connect(GRPC_URL)
-            .await
-            .catch("ConnectionError", GRPC_URL)?;
+        let mut cl = self.connect().await?;
 
         let mut ret: HashMap<MpcAddr, T> = HashMap::new();
         for src in srcs.iter() {
-            let msg = Message {
-                topic: topic.to_string(),
-                src: src.as_primitive(),
-                dst: dst.as_primitive(),
-                obj: None, // as index
-            };
+            let msg = self.envelope(topic, *src, dst, None); // as index
             loop {
-                let resp = cl.outbox(msg.clone()).await.catch_()?.into_inner();
-                if let Some(obj) = resp.obj {
-                    let obj = serde_pickle::from_slice(&obj, Default::default()).catch_()?;
-                    ret.insert(*src, obj);
+                if let Some(obj) = self.fetch(&mut cl, &msg).await? {
+                    ret.insert(*src, self.codec.decode(&obj)?);
                     break;
                 }
                 use tokio::time::{sleep, Duration};
@@ -133,3 +224,149 @@ impl Messenger for ShowcaseSesmanClient {
         Ok(ret)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tonic::transport::Server;
+
+    use super::*;
+    use crate::sesman::protogen::sesman::sesman_server::SesmanServer;
+    use crate::sesman::server::ShowcaseSesmanServer;
+
+    /// Serve a fresh sesman on a free port instead of GRPC_URL, which a demo
+    /// may hold, and return its url.
+    async fn serve() -> &'static str {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let server = ShowcaseSesmanServer::new().await.unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(SesmanServer::new(server))
+                .serve(addr),
+        );
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        Box::leak(format!("http://{}", addr).into_boxed_str())
+    }
+
+    #[tokio::test]
+    async fn oversized_message_is_refused_by_the_client() {
+        let client = ShowcaseSesmanClient {
+            max_message_size: 1024,
+            url: serve().await,
+            ..Default::default()
+        };
+        let mut cl = client.connect().await.unwrap();
+        let (src, dst) = (MpcAddr::new(1, 1), MpcAddr::new(1, 2));
+        // "obj" is decoded and then checked, "frame" is refused by the decoder
+        for (topic, size) in [("fits", 1024), ("obj", 1500), ("frame", 4096)] {
+            let msg = client.envelope(topic, src, dst, Some(vec![0u8; size]));
+            cl.inbox(msg.clone()).await.unwrap();
+
+            let index = Message { obj: None, ..msg };
+            let res = client.fetch(&mut cl, &index).await;
+            if size <= client.max_message_size {
+                assert_eq!(res.unwrap().unwrap().len(), size);
+            } else {
+                let err = res.unwrap_err();
+                assert_eq!(err.get_name(), "MessageTooLarge", "{}", topic);
+                let by_decoder = err.get_context().unwrap().contains("exceeds the limit");
+                assert_eq!(by_decoder, topic == "frame");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn second_message_of_a_round_is_refused() {
+        let client = ShowcaseSesmanClient {
+            url: serve().await,
+            ..Default::default()
+        };
+        let (src, dst) = (MpcAddr::new(1, 4), MpcAddr::new(1, 1));
+        client.send("aead_share", src, dst, &1u32).await.unwrap();
+        client.send("aead_share", src, dst, &1u32).await.unwrap();
+        let err = client
+            .send("aead_share", src, dst, &2u32)
+            .await
+            .unwrap_err();
+        let msg = err.to_string();
+        assert!(
+            msg.contains("DuplicateMessage") && msg.contains("1.4"),
+            "{}",
+            msg
+        );
+        let got: u32 = client.receive("aead_share", src, dst).await.unwrap();
+        assert_eq!(got, 1);
+    }
+
+    #[tokio::test]
+    async fn flooded_round_is_cut_short() {
+        let limit = FloodLimit {
+            max_messages: 2,
+            max_bytes: 1 << 20,
+        };
+        let client = ShowcaseSesmanClient {
+            url: serve().await,
+            ..Default::default()
+        }
+        .with_flood_limit(limit);
+        let dst = MpcAddr::new(1, 1);
+        let srcs: HashSet<MpcAddr> = (2..=4).map(|i| MpcAddr::new(1, i)).collect();
+        for src in srcs.iter() {
+            client.send("sign_resp", *src, dst, &1u32).await.unwrap();
+        }
+        let err = client
+            .gather::<u32>("sign_resp", &srcs, dst)
+            .await
+            .unwrap_err();
+        assert_eq!(err.get_name(), "FloodDetected");
+    }
+
+    #[tokio::test]
+    async fn cbor_client_speaks_cbor() {
+        let url = serve().await;
+        let cbor = ShowcaseSesmanClient {
+            url,
+            ..ShowcaseSesmanClient::for_context("cbor")
+        }
+        .with_codec(CborCodec);
+        let (src, dst) = (MpcAddr::new(1, 2), MpcAddr::gcast_id(1));
+        let digests: HashMap<MpcAddr, u16> = (1..=3).map(|i| (MpcAddr::new(1, i), i)).collect();
+        cbor.send("dkg_com_echo", src, dst, &digests).await.unwrap();
+        let got: HashMap<MpcAddr, u16> = cbor.receive("dkg_com_echo", src, dst).await.unwrap();
+        assert_eq!(got, digests);
+
+        let mut cl = cbor.connect().await.unwrap();
+        let index = cbor.envelope("dkg_com_echo", src, dst, None);
+        let bytes = cbor.fetch(&mut cl, &index).await.unwrap().unwrap();
+        assert_eq!(bytes, CborCodec.encode(&digests).unwrap());
+        assert!(PickleCodec.decode::<HashMap<MpcAddr, u16>>(&bytes).is_err());
+    }
+
+    #[tokio::test]
+    async fn overlapping_sessions_keep_their_messages_apart() {
+        let url = serve().await;
+        let client_of = |context: &str| ShowcaseSesmanClient {
+            url,
+            ..ShowcaseSesmanClient::for_context(context)
+        };
+        let (a, b) = (client_of("keygen a"), client_of("keygen b"));
+        assert_ne!(a.session, b.session);
+        assert_eq!(a.session, client_of("keygen a").session);
+
+        // same round, same parties
+        let (src, dst) = (MpcAddr::new(1, 1), MpcAddr::gcast_id(1));
+        a.send("dkg_com", src, dst, &1u32).await.unwrap();
+        b.send("dkg_com", src, dst, &2u32).await.unwrap();
+        let got_a: u32 = a.receive("dkg_com", src, dst).await.unwrap();
+        let got_b: u32 = b.receive("dkg_com", src, dst).await.unwrap();
+        assert_eq!((got_a, got_b), (1, 2));
+
+        // nothing of session a is visible to session c
+        let c = client_of("keygen c");
+        let mut cl = c.connect().await.unwrap();
+        let index = c.envelope("dkg_com", src, dst, None);
+        assert!(c.fetch(&mut cl, &index).await.unwrap().is_none());
+    }
+}
@@ -10,6 +10,8 @@ pub struct Message {
     pub dst: u32,
     #[prost(bytes = "vec", optional, tag = "4")]
     pub obj: ::core::option::Option<::prost::alloc::vec::Vec<u8>>,
+    #[prost(string, tag = "5")]
+    pub session: ::prost::alloc::string::String,
 }
 #[derive(serde::Serialize, serde::Deserialize)]
 #[allow(clippy::derive_partial_eq_without_eq)]
@@ -1,5 +1,6 @@
-use dashmap::DashMap;
+use dashmap::{mapref::entry::Entry, DashMap};
 use libexception::*;
+use mpc_spec::MpcAddr;
 use tonic::{transport::Server, Request, Response, Status};
 
 use super::{
@@ -36,10 +37,24 @@ impl ShowcaseSesmanServer {
 
     async fn biz_inbox(&self, msg: Request<Message>) -> Outcome<Response<Void>> {
         let msg = msg.into_inner();
+        let k = key_of(&msg);
         let obj = msg.obj.ifnone_()?;
-
-        let k = format!("{}{}{}", msg.topic, msg.src, msg.dst);
-        self.db.insert(k, obj);
+        // One message per sender, round and receiver; a retry may repeat it.
+        match self.db.entry(k) {
+            Entry::Occupied(prev) => assert_throw!(
+                same_message(prev.get(), &obj),
+                "DuplicateMessage",
+                format!(
+                    "{} already sent another message to {} at round \"{}\"",
+                    MpcAddr::from(msg.src),
+                    MpcAddr::from(msg.dst),
+                    msg.topic
+                )
+            ),
+            Entry::Vacant(slot) => {
+                slot.insert(obj);
+            }
+        }
 
         Ok(Response::new(Void::default()))
     }
@@ -47,17 +62,27 @@ impl ShowcaseSesmanServer {
     async fn biz_outbox(&self, msg: Request<Message>) -> Outcome<Response<Message>> {
         let mut msg = msg.into_inner();
 
-        let k = format!("{}{}{}", msg.topic, msg.src, msg.dst);
-        let obj: Option<Vec<u8>> = match self.db.get(&k) {
-            Some(x) => Some(x.clone()), // sqlx::get
-            None => None,
-        };
+        let obj: Option<Vec<u8>> = self.db.get(&key_of(&msg)).map(|x| x.clone()); // sqlx::get
         msg.obj = obj;
 
         Ok(Response::new(msg))
     }
 }
 
+/// Mailbox key of `msg`. Messages of different sessions never share one,
+/// even with the same topic, src and dst.
+fn key_of(msg: &Message) -> String {
+    format!("{}/{}/{}/{}", msg.session, msg.topic, msg.src, msg.dst)
+}
+
+/// Whether two pickles decode to the same value, though their bytes may
+/// differ by the iteration order of a `HashMap`. Messages of `CborCodec`
+/// are canonical, so equal ones have equal bytes.
+fn same_message(a: &[u8], b: &[u8]) -> bool {
+    let decode = |x: &[u8]| serde_pickle::value_from_slice(x, Default::default()).ok();
+    a == b || matches!((decode(a), decode(b)), (Some(a), Some(b)) if a == b)
+}
+
 #[tonic::async_trait] // equivalent to async_trait
 impl Sesman for ShowcaseSesmanServer {
     async fn inbox(&self, msg: Request<Message>) -> Result<Response<Void>, Status> {